@@ -0,0 +1,21 @@
+//! Spins the demo objects about their y axes in perspective, as cards being flipped.
+use anyhow::Result;
+use fightish::{AppBuilder, Object, Transform2D};
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let objects = [(-0.5, 0, 2.0), (0.5, 1, 3.0)]
+        .into_iter()
+        .map(|(x, frame_index, period)| Object {
+            flip_period: Some(period),
+            ..Object::from_transform(
+                Transform2D { translation: cgmath::vec2(x, 0.0), scale: cgmath::vec2(0.4, 0.4), ..Default::default() },
+                frame_index,
+            )
+        })
+        .collect();
+    let mut app = AppBuilder::new().initial_objects(objects).build();
+    let event_loop = app.create_event_loop()?;
+    event_loop.run_app(&mut app)?;
+    Ok(())
+}
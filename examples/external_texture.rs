@@ -0,0 +1,62 @@
+//! Renders into a texture the application owns, as when compositing into another renderer.
+use anyhow::Result;
+use fightish::engine::{DEFAULT_DEPTH_FORMAT, RenderDongle, RenderEngine};
+use fightish::model::{make_load_test_default, SimpleLoader};
+use fightish::render::{RenderContext, TargetTextureDongle};
+use fightish::scene::{Object, SceneData};
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 256;
+// any color renderable format works, as long as the engine is created with it
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let mut context = RenderContext::new();
+    let device_id = pollster::block_on(context.device(None))?;
+
+    // the texture another renderer would own and sample from
+    let texture = context.get_device_by_id(device_id).device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("External texture"),
+        size: wgpu::Extent3d {
+            width: WIDTH,
+            height: HEIGHT,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    // the engine's depth buffer, sized like the texture
+    let mut dongle = RenderDongle::new(DEFAULT_DEPTH_FORMAT)?;
+    dongle.configure(context.get_device_by_id(device_id), FORMAT);
+    let attachments: Vec<wgpu::Texture> = (0..dongle.num_textures())
+        .map(|i| context.get_device_by_id(device_id).device.create_texture(&dongle.texture_desc(i, WIDTH, HEIGHT)))
+        .collect();
+
+    let mut engine = RenderEngine::new(
+        &context,
+        device_id,
+        &FORMAT,
+        &dongle,
+        dongle.depth_format(),
+        dongle.sample_count(),
+        SimpleLoader::new(make_load_test_default(2, 2..5, 3..5))?,
+    )?;
+    let scene = SceneData {
+        vp_x: 0,
+        vp_y: 0,
+        vp_width: WIDTH,
+        vp_height: HEIGHT,
+        camera_tf: cgmath::Matrix4::from_scale(2.0),
+        time: 0.0,
+        global_tint: [1.0; 4],
+        objects: vec![Object::new(cgmath::Matrix4::from_scale(0.5), 0)],
+    };
+    engine.render_to_texture(context.get_device_by_id(device_id), &texture, &dongle, &attachments, &scene)?;
+    println!("Rendered a {}x{} {:?} frame.", WIDTH, HEIGHT, FORMAT);
+    Ok(())
+}
@@ -0,0 +1,18 @@
+//! Draws the demo scene over the desktop, in a window without a background.
+use anyhow::Result;
+use fightish::AppBuilder;
+use winit::window::Window;
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let mut app = AppBuilder::new()
+        .window_attributes(Window::default_attributes().with_transparent(true).with_decorations(false))
+        .clear_color(wgpu::Color::TRANSPARENT)
+        .build();
+    // opaque shards are the same premultiplied or not, translucent ones would need premultiplied colors
+    app.set_alpha_mode(wgpu::CompositeAlphaMode::PreMultiplied);
+    app.set_frame_latency(1);
+    let event_loop = app.create_event_loop()?;
+    event_loop.run_app(&mut app)?;
+    Ok(())
+}
@@ -0,0 +1,13 @@
+
+// a shard's color at `time` seconds under its animation mode, see `ModelShard::anim_mode`.
+fn animated_color(color: vec4<f32>, anim_mode: u32, time: f32) -> vec4<f32> {
+    switch anim_mode {
+        // alpha pulsing between 0 and its value once per second
+        case 1u: {
+            return vec4(color.rgb, color.a * (0.5 + 0.5 * sin(6.2831853 * time)));
+        }
+        default: {
+            return color;
+        }
+    }
+}
@@ -7,43 +7,71 @@ pub struct Uniforms {
     // note even though only really using 2+1D transformations, the alignments on vec3's are a real pain.
     pub clip_world_tf: [[f32; 4]; 4], // tf from world coordinates to clip coordinates (for bb purposes)
     pub frag_clip_tf: [[f32; 4]; 4], // tf from fragment coordinates to world coordinates.
+    pub winding_epsilon: f32, // grid in fragment coordinates segment endpoints are snapped to, 0 disables.
+    pub time: f32, // seconds, for time dependent effects.
+    pub viewport_size: [f32; 2], // in pixels. 8 byte aligned.
+    pub aa_width: f32, // width in pixels of the antialiased edge ramp, 0 for hard edges.
+    pub premultiplied: u32, // 1 if colors are premultiplied by alpha, so coverage scales them entirely.
+    pub filler: [f32; 2], // pads global_tint to its 16 byte alignment.
+    pub global_tint: [f32; 4], // multiplied into every shard's color.
+    pub world_clip_tf: [[f32; 4]; 4], // tf from clip coordinates to world coordinates, for the grid.
+    pub grid_color: [f32; 4],
+    pub grid_spacing: f32, // world units between grid lines, 0 when the grid is off.
+    pub grid_filler: [f32; 3], // keeps the struct a multiple of 16 bytes.
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ModelVertex {
     pub pos: [f32; 2]
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ModelSegment {
     pub idx: [i32; 4] // making this signed in case using negative values for special cases later
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ModelShard {
     pub bb: [f32; 4],
     pub color: [f32; 4],
     pub segment_range: [i32; 2],
+    /// Draw order within the frame, greater depths in front. Each object's depths are offset above those below it,
+    /// and a scene's total may reach [`crate::MAX_CLIP_DEPTH`].
     pub clip_depth: u32,
-    pub filler: u32,
+    /// How the color changes over time, evaluated on the GPU from the scene time:
+    /// 0 keeps it static, 1 pulses the alpha between 0 and its value once per second.
+    /// Other values are reserved and static for now. The pulse only shows while blending, since
+    /// [`crate::engine::BlendMode::Replace`] ignores alpha.
+    #[cfg_attr(feature = "serde", serde(alias = "filler"))]
+    pub anim_mode: u32,
 }
 
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ModelFrame {
     pub shard_range: [i32; 2],
     pub segment_range: [i32; 2],
 }
 
+/// Extents of a model frame, which is what an object drawing it takes in the scene buffers.
 #[derive(Copy, Clone, Debug, Default)]
 pub struct FrameInfo {
+    /// One more than the largest shard clip depth.
     pub clip_size: u32,
     pub shard_size: u32,
     pub segment_size: u32,
+    /// Union of the shards' bounding boxes, `[min_x, min_y, max_x, max_y]` in local coordinates.
+    pub bounds: [f32; 4],
+    /// Whether any shard has a nonzero [`ModelShard::anim_mode`], so the frame changes with the scene time.
+    pub animated: bool,
 }
 
 #[repr(C)]
@@ -54,6 +82,31 @@ pub struct FrameObject {
     pub clip_offset: u32,
     pub shard_offset: i32,
     pub segment_offset: i32,
+    pub tint: [f32; 4],
+}
+
+/// An object drawn by the instanced path, straight from the model's shards.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceData {
+    pub world_tex_tf: [[f32; 4]; 4],
+    pub frag_tex_tf: [[f32; 4]; 4], // maps the model's coordinates to fragment coordinates
+    pub frame_index: i32,
+    pub clip_offset: u32,
+    pub filler: [u32; 2],
+    pub tint: [f32; 4],
+}
+
+/// An object drawn as a quad textured from the sprite atlas.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteData {
+    pub world_tex_tf: [[f32; 4]; 4],
+    pub bounds: [f32; 4], // the quad in the model's coordinates, the frame's bounds
+    pub uv: [f32; 4], // atlas rect stretched over the quad, v growing downwards unlike y
+    pub tint: [f32; 4],
+    pub clip_depth: u32,
+    pub filler: [u32; 3], // keeps the struct a multiple of 16 bytes.
 }
 
 fn pad_to_copy_buffer_alignment(size: wgpu::BufferAddress) -> wgpu::BufferAddress {
@@ -124,6 +177,7 @@ impl LayoutEnum for UniformGroup {
     }
 }
 
+/// The model buffers bound by [`crate::model::model_bind_group`].
 #[derive(Debug, Clone, Copy)]
 pub enum ModelGroup {
     Vertex,
@@ -158,9 +212,10 @@ impl LayoutEnum for ModelGroup {
     }
 
     fn layout_entry(&self) -> wgpu::BindGroupLayoutEntry {
+        // the instanced path reads the model while drawing
         create_bind_group_layout_entry_buffer(
             self,
-            wgpu::ShaderStages::COMPUTE,
+            wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
             wgpu::BufferBindingType::Storage {read_only: true}
         )
     }
@@ -222,6 +277,48 @@ impl LayoutEnum for SceneGroup {
     }
 }
 
+#[derive(Debug, Copy, Clone)]
+pub enum InstanceGroup {
+    Instance,
+}
+
+impl LayoutEnum for InstanceGroup {
+    type Iter = <[Self; 1] as IntoIterator>::IntoIter;
+
+    fn entry_iter() -> Self::Iter {
+        [Self::Instance].into_iter()
+    }
+
+    fn size(&self) -> u64 {
+        match self {
+            Self::Instance => size_of::<InstanceData>() as u64
+        }
+    }
+
+    fn binding(&self) -> u32 {
+        match self {
+            Self::Instance => 0,
+        }
+    }
+
+    fn layout_entry(&self) -> wgpu::BindGroupLayoutEntry {
+        create_bind_group_layout_entry_buffer(
+            self,
+            wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            wgpu::BufferBindingType::Storage {read_only: true}
+        )
+    }
+
+    fn buffer_descriptor(&self, count: u64) -> wgpu::BufferDescriptor<'static> {
+        wgpu::BufferDescriptor {
+            label: Some("Instances buffer"),
+            size: self.size() * count,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum FrameGroup {
     Segment,
@@ -269,3 +366,87 @@ impl LayoutEnum for FrameGroup {
         }
     }
 }
+
+#[derive(Debug, Copy, Clone)]
+pub enum SpriteGroup {
+    Sprite,
+}
+
+impl LayoutEnum for SpriteGroup {
+    type Iter = <[Self; 1] as IntoIterator>::IntoIter;
+
+    fn entry_iter() -> Self::Iter {
+        [Self::Sprite].into_iter()
+    }
+
+    fn size(&self) -> u64 {
+        match self {
+            Self::Sprite => size_of::<SpriteData>() as u64
+        }
+    }
+
+    fn binding(&self) -> u32 {
+        match self {
+            Self::Sprite => 0,
+        }
+    }
+
+    fn layout_entry(&self) -> wgpu::BindGroupLayoutEntry {
+        create_bind_group_layout_entry_buffer(
+            self,
+            wgpu::ShaderStages::VERTEX,
+            wgpu::BufferBindingType::Storage {read_only: true}
+        )
+    }
+
+    fn buffer_descriptor(&self, count: u64) -> wgpu::BufferDescriptor<'static> {
+        wgpu::BufferDescriptor {
+            label: Some("Sprites buffer"),
+            size: self.size() * count,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }
+    }
+}
+
+/// The sprite atlas and how it's sampled. These bindings aren't buffers, so unlike the other groups
+/// they aren't a [`LayoutEnum`].
+#[derive(Debug, Copy, Clone)]
+pub enum TextureGroup {
+    Texture,
+    Sampler,
+}
+
+impl TextureGroup {
+    pub const ENTRIES: [Self; 2] = [Self::Texture, Self::Sampler];
+
+    pub fn create_bind_group_layout(device: &wgpu::Device, label: wgpu::Label<'_>) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &Self::ENTRIES.map(|t| t.layout_entry()),
+            label,
+        })
+    }
+
+    pub fn binding(&self) -> u32 {
+        match self {
+            Self::Texture => 0,
+            Self::Sampler => 1,
+        }
+    }
+
+    pub fn layout_entry(&self) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding: self.binding(),
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: match self {
+                Self::Texture => wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                Self::Sampler => wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            },
+            count: None,
+        }
+    }
+}
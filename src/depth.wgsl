@@ -0,0 +1,9 @@
+// clip space z of clip depths, shared by the shard and sprite shaders.
+// depths step by exactly 2^-24, one value of a 24 bit depth buffer, and are exact in f32, so every depth
+// below MAX_CLIP_DEPTH keeps a depth value of its own in both Depth24Plus and Depth32Float.
+
+const CLIP_DEPTH_STEP: f32 = 1.0 / 16777216.0;
+
+fn clip_depth_z(clip_depth: u32) -> f32 {
+    return f32(clip_depth) * CLIP_DEPTH_STEP;
+}
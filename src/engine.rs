@@ -1,59 +1,158 @@
-use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use crate::error::{FightishError, Result};
 use cgmath::SquareMatrix;
 use log::*;
 use crate::buffer_structs::*;
-use crate::model::SimpleLoader;
-use crate::render::{DeviceHandle, DeviceId, LayoutEnum, RenderContext, TargetTextureDongle};
-use crate::scene::SceneData;
+use crate::model::{Model, ModelLoader, ModelRegistry, SimpleLoader};
+use crate::render::{
+    COLOR_ATTACHMENT, DEPTH_ATTACHMENT, DeviceHandle, DeviceId, LayoutEnum, OffscreenTarget, RenderContext,
+    TargetTextureDongle, Viewport,
+};
+use crate::scene::{planar_inverse, world_bounds, Object, SceneData};
 
-const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24Plus;
+/// Depth buffer format used unless another one is requested.
+pub const DEFAULT_DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24Plus;
+/// The total clip depth a scene may use, summed over its objects' frames. Each clip depth gets a distinct value
+/// in a `Depth24Plus` or `Depth32Float` depth buffer below it, and scenes going beyond fail to render.
+pub const MAX_CLIP_DEPTH: u32 = 1 << 23;
+/// Default snapping grid for segment endpoints, in pixels. Snapping is off unless enabled with
+/// [`RenderEngine::set_winding_epsilon`], where a grid of 1/256 suits most scenes.
+pub const DEFAULT_WINDING_EPSILON: f32 = 0.0;
 
+/// Draws scenes of objects from models supplied by `L`, see [`ModelLoader`].
 #[derive(Debug)]
-pub struct RenderEngine {
-    render_pipeline: wgpu::RenderPipeline,
+pub struct RenderEngine<L: ModelLoader = SimpleLoader> {
+    device_id: DeviceId,
+    format: wgpu::TextureFormat,
+    depth_view_index: usize, // index of the depth buffer in the target texture views
+    depth_format: wgpu::TextureFormat,
+    color_view_index: Option<usize>, // index of the multisampled color buffer, if any
+    sample_count: u32,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    instanced_pipeline_layout: wgpu::PipelineLayout,
+    sprite_pipeline_layout: wgpu::PipelineLayout,
+    render_pipelines: RenderPipelines,
+    compute_pipeline_layout: wgpu::PipelineLayout,
     compute_pipeline: wgpu::ComputePipeline,
+    compute_workgroup_size: u32, // invocations sharing each object's shards and segments
 
     world_uniforms_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
 
-    shard_vertex_frame_capacity: u64,
-    shard_vertex_frame_buffer: wgpu::Buffer,
-    segment_frame_capacity: u64,
+    shard_vertex_frame_buffer: GrowableBuffer<FrameGroup>,
     frame_bind_group_layout: wgpu::BindGroupLayout,
     frame_read_bind_group_layout: wgpu::BindGroupLayout,
-    segment_frame_buffer: wgpu::Buffer,
+    segment_frame_buffer: GrowableBuffer<FrameGroup>,
     frame_bind_group: wgpu::BindGroup,
     frame_read_bind_group: wgpu::BindGroup,
 
-    loader: SimpleLoader,
+    models: ModelRegistry<L>,
+    object_batch_alignment: usize, // in objects, for binding the scene buffer from a model's first object
 
-    object_scene_capacity: u64,
-    object_scene_buffer: wgpu::Buffer,
+    object_scene_buffer: GrowableBuffer<SceneGroup>,
     scene_bind_group_layout: wgpu::BindGroupLayout,
     scene_bind_group: wgpu::BindGroup,
+
+    instance_buffer: GrowableBuffer<InstanceGroup>,
+    instance_bind_group_layout: wgpu::BindGroupLayout,
+    instance_bind_group: wgpu::BindGroup,
+
+    sprite_buffer: GrowableBuffer<SpriteGroup>,
+    sprite_bind_group_layout: wgpu::BindGroupLayout,
+    sprite_bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    sprite_atlas: Option<wgpu::BindGroup>, // binds the atlas texture and its sampler
+
+    visibility_enabled: bool,
+    visibility_queries: Option<VisibilityQueries>,
+    timestamp_queries: Option<TimestampQueries>, // None without timestamp query support
+
+    winding_epsilon: f32,
+    aa_width: f32,
+    wireframe: bool,
+    debug_draw_bounds: bool,
+    frustum_culling: bool,
+    draw_grid: bool,
+    grid_spacing: f32, // world units between grid lines
+    grid_color: wgpu::Color,
+    frame_index_policy: FrameIndexPolicy,
+    clear_color: wgpu::Color,
+    frame_extents: (u64, u64), // shard vertices and segments the last scene wrote to the frame buffers
+    pass_hooks: PassHooks,
 }
 
-impl RenderEngine {
-    pub fn new(context: &RenderContext, device_id: DeviceId, format: &wgpu::TextureFormat, mut loader: SimpleLoader) -> RenderEngine {
-        let device = context.get_device_by_id(device_id);
-        let shader = device
-            .device
-            .create_shader_module(
-                wgpu::ShaderModuleDescriptor {
-                    label: Some("Shader"),
-                    source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-                }
-            );
+impl<L: ModelLoader> RenderEngine<L> {
+    /// Objects sharing a frame with at least this many others, counting themselves, are drawn instanced,
+    /// unless blending, antialiasing, visibility queries or the debug overlays are on.
+    const MIN_INSTANCES: usize = 16;
+    /// The most shards the general path draws per scene, so draw ranges of up to 8 vertices per shard fit a `u32`.
+    const MAX_SHARD_EXTENT: u32 = u32::MAX / 8;
+    /// Invocations per object in the compute pass unless set otherwise.
+    pub const DEFAULT_COMPUTE_WORKGROUP_SIZE: u32 = 64;
 
-        let compute_shader = device
-            .device
-            .create_shader_module(
-                wgpu::ShaderModuleDescriptor {
-                    label: Some("Frame preprocessing compute shader"),
-                    source: wgpu::ShaderSource::Wgsl(include_str!("frame_preprocess.wgsl").into())
-                }
-            );
+    /// Creates an engine rendering to `format`, using the [`DEPTH_ATTACHMENT`] of the `dongle` as depth buffer,
+    /// which must have `depth_format`. With a `sample_count` above 1 the dongle must provide a multisampled color view, see
+    /// [`TargetTextureDongle::color_view_index`]. Counts the adapter doesn't support fall back to 1.
+    ///
+    /// This used to take the index of the depth view. Custom dongles now return that index from
+    /// [`TargetTextureDongle::attachment_view`] for [`DEPTH_ATTACHMENT`] instead.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<D: TargetTextureDongle>(
+        context: &RenderContext,
+        device_id: DeviceId,
+        format: &wgpu::TextureFormat,
+        dongle: &D,
+        depth_format: wgpu::TextureFormat,
+        sample_count: u32,
+        mut loader: L,
+    ) -> Result<Self> {
+        if !depth_format.has_depth_aspect() {
+            return Err(FightishError::InvalidTarget(format!("{:?} is not a depth format.", depth_format)));
+        }
+        let device = context.get_device_by_id(device_id);
+        let supported_sample_count = device.supported_sample_count(&[*format, depth_format], sample_count);
+        if supported_sample_count != sample_count {
+            warn!("Sample count {} unsupported, falling back to {}.", sample_count, supported_sample_count);
+        }
+        let sample_count = supported_sample_count;
+        let depth_view_index = dongle
+            .attachment_view(DEPTH_ATTACHMENT)
+            .ok_or(FightishError::InvalidTarget(format!("The dongle has no {:?} attachment.", DEPTH_ATTACHMENT)))?;
+        if depth_view_index >= dongle.num_views() {
+            return Err(FightishError::InvalidTarget(format!(
+                "Depth view index {} out of range, the dongle has {} views.",
+                depth_view_index,
+                dongle.num_views(),
+            )));
+        }
+        let depth_view_format = dongle.view_format(depth_view_index);
+        if depth_view_format != depth_format {
+            return Err(FightishError::InvalidTarget(format!(
+                "Depth view {} has format {:?}, expected {:?}.",
+                depth_view_index,
+                depth_view_format,
+                depth_format,
+            )));
+        }
+        if dongle.view_sample_count(depth_view_index) != sample_count {
+            return Err(FightishError::InvalidTarget(format!(
+                "Depth view {} has {} samples, expected {}.",
+                depth_view_index,
+                dongle.view_sample_count(depth_view_index),
+                sample_count,
+            )));
+        }
+        let color_view_index = dongle.color_view_index();
+        check_color_view(dongle, format, sample_count)?;
+        if !device.is_color_renderable(*format) {
+            return Err(FightishError::InvalidTarget(format!("{:?} is not a color renderable format.", format)));
+        }
 
+        // shader compile errors and invalid pipelines are validation errors, which would otherwise panic.
+        device.device.push_error_scope(wgpu::ErrorFilter::Validation);
 
         let uniform_bind_group_layout = device
             .create_bind_group_layout::<UniformGroup>(Some("Uniform bind group layout"));
@@ -94,54 +193,49 @@ impl RenderEngine {
                 ],
                 push_constant_ranges: &[],
             });
+        let instance_bind_group_layout = device
+            .create_bind_group_layout::<InstanceGroup>(Some("Instance bind group layout"));
+        let instanced_pipeline_layout = device
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Instanced pipeline layout"),
+                bind_group_layouts: &[
+                    &uniform_bind_group_layout,
+                    &model_bind_group_layout,
+                    &instance_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
 
-
-        let render_pipeline = device
+        let sprite_bind_group_layout = device
+            .create_bind_group_layout::<SpriteGroup>(Some("Sprite bind group layout"));
+        let texture_bind_group_layout = TextureGroup::create_bind_group_layout(
+            &device.device,
+            Some("Texture bind group layout"),
+        );
+        let sprite_pipeline_layout = device
             .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("Render Pipeline"),
-                layout: Some(&render_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "vs_main", // name of the main function of the vertex shader
-                    buffers: &[],
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &shader,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: format.clone(),
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: None,
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    unclipped_depth: false,
-                    conservative: false,
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::GreaterEqual,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
-                }),
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                multiview: None,
-                cache: None,
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Sprite pipeline layout"),
+                bind_group_layouts: &[
+                    &uniform_bind_group_layout,
+                    &texture_bind_group_layout,
+                    &sprite_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
             });
 
+        let render_pipelines = RenderPipelines::new(
+            device,
+            &render_pipeline_layout,
+            &instanced_pipeline_layout,
+            &sprite_pipeline_layout,
+            *format,
+            depth_format,
+            sample_count,
+            PipelineOptions::default(),
+        );
+
         let compute_pipeline_layout = device
             .device
             .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor{
@@ -155,16 +249,14 @@ impl RenderEngine {
                 push_constant_ranges: &[],
             });
 
-        let compute_pipeline = device
-            .device
-            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor{
-                label: Some("Compute pipeline"),
-                layout: Some(&compute_pipeline_layout),
-                module: &compute_shader,
-                entry_point: "main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                cache: None,
-            });
+        let compute_workgroup_size = Self::DEFAULT_COMPUTE_WORKGROUP_SIZE
+            .min(device.device.limits().max_compute_workgroup_size_x)
+            .min(device.device.limits().max_compute_invocations_per_workgroup)
+            .max(1);
+        let compute_pipeline = create_compute_pipeline(device, &compute_pipeline_layout, compute_workgroup_size);
+        if let Some(e) = pollster::block_on(device.device.pop_error_scope()) {
+            return Err(FightishError::Pipeline(e.to_string()));
+        }
 
         let world_uniforms_buffer = device
             .create_buffer_with_layout_enum(&UniformGroup::World, 1);
@@ -177,19 +269,15 @@ impl RenderEngine {
                 }
             );
 
-        let segment_frame_capacity = 1u64;
-        let shard_vertex_frame_capacity = 1u64;
-        let segment_frame_buffer = device
-            .create_buffer_with_layout_enum(&FrameGroup::Segment, segment_frame_capacity);
-        let shard_vertex_frame_buffer = device
-            .create_buffer_with_layout_enum(&FrameGroup::ShardVertex, shard_vertex_frame_capacity);
+        let segment_frame_buffer = GrowableBuffer::new(device, FrameGroup::Segment, "Frame segments");
+        let shard_vertex_frame_buffer = GrowableBuffer::new(device, FrameGroup::ShardVertex, "Frame shard vertices");
         let frame_bind_group = device
             .create_bind_group_with_enum_layout_map(
                 &frame_bind_group_layout,
                 Some("Frame bind group"),
                 |t| match t {
-                    FrameGroup::Segment => segment_frame_buffer.as_entire_binding(),
-                    FrameGroup::ShardVertex => shard_vertex_frame_buffer.as_entire_binding(),
+                    FrameGroup::Segment => segment_frame_buffer.buffer().as_entire_binding(),
+                    FrameGroup::ShardVertex => shard_vertex_frame_buffer.buffer().as_entire_binding(),
                 }
             );
         let frame_read_bind_group = device
@@ -197,34 +285,68 @@ impl RenderEngine {
                 &frame_read_bind_group_layout,
                 Some("Frame read bind group"),
                 |t| match t {
-                    FrameGroup::Segment => segment_frame_buffer.as_entire_binding(),
-                    FrameGroup::ShardVertex => shard_vertex_frame_buffer.as_entire_binding(),
+                    FrameGroup::Segment => segment_frame_buffer.buffer().as_entire_binding(),
+                    FrameGroup::ShardVertex => shard_vertex_frame_buffer.buffer().as_entire_binding(),
                 }
             );
 
-        let object_scene_capacity = 1u64;
-        let object_scene_buffer = device
-            .create_buffer_with_layout_enum(&SceneGroup::Object, object_scene_capacity);
+        let object_scene_buffer = GrowableBuffer::new(device, SceneGroup::Object, "Scene objects");
         let scene_bind_group = device
             .create_bind_group_with_enum_layout_map(
                 &scene_bind_group_layout,
                 Some("Scene bind group"),
                 |t| match t {
-                    SceneGroup::Object => object_scene_buffer.as_entire_binding(),
+                    SceneGroup::Object => object_scene_buffer.buffer().as_entire_binding(),
+                }
+            );
+
+        let instance_buffer = GrowableBuffer::new(device, InstanceGroup::Instance, "Instances");
+        let instance_bind_group = device
+            .create_bind_group_with_enum_layout_map(
+                &instance_bind_group_layout,
+                Some("Instance bind group"),
+                |t| match t {
+                    InstanceGroup::Instance => instance_buffer.buffer().as_entire_binding(),
+                }
+            );
+
+        let sprite_buffer = GrowableBuffer::new(device, SpriteGroup::Sprite, "Sprites");
+        let sprite_bind_group = device
+            .create_bind_group_with_enum_layout_map(
+                &sprite_bind_group_layout,
+                Some("Sprite bind group"),
+                |t| match t {
+                    SpriteGroup::Sprite => sprite_buffer.buffer().as_entire_binding(),
                 }
             );
 
         loader.load(device);
+        let mut models = ModelRegistry::new();
+        models.add(loader);
+        let object_batch_alignment = {
+            let size = SceneGroup::Object.size();
+            let align = device.device.limits().min_storage_buffer_offset_alignment as u64;
+            (align / gcd(size, align)) as usize
+        };
 
-        RenderEngine {
-            render_pipeline,
+        Ok(RenderEngine {
+            device_id,
+            format: *format,
+            depth_view_index,
+            depth_format,
+            color_view_index,
+            sample_count,
+            render_pipeline_layout,
+            instanced_pipeline_layout,
+            sprite_pipeline_layout,
+            render_pipelines,
+            compute_pipeline_layout,
             compute_pipeline,
+            compute_workgroup_size,
 
             world_uniforms_buffer,
             uniform_bind_group,
 
-            shard_vertex_frame_capacity,
-            segment_frame_capacity,
             shard_vertex_frame_buffer,
             segment_frame_buffer,
             frame_bind_group_layout,
@@ -232,271 +354,2894 @@ impl RenderEngine {
             frame_bind_group,
             frame_read_bind_group,
 
-            loader,
+            models,
+            object_batch_alignment,
             // vertex_model_buffer,
             // segment_model_buffer,
             // shard_model_buffer,
             // frame_model_buffer,
             // model_bind_group,
 
-            object_scene_capacity,
             object_scene_buffer,
             scene_bind_group_layout,
             scene_bind_group,
-        }
+
+            instance_buffer,
+            instance_bind_group_layout,
+            instance_bind_group,
+
+            sprite_buffer,
+            sprite_bind_group_layout,
+            sprite_bind_group,
+            texture_bind_group_layout,
+            sprite_atlas: None,
+
+            visibility_enabled: false,
+            visibility_queries: None,
+            timestamp_queries: device
+                .device
+                .features()
+                .contains(wgpu::Features::TIMESTAMP_QUERY)
+                .then(|| TimestampQueries::new(device)),
+
+            winding_epsilon: DEFAULT_WINDING_EPSILON,
+            aa_width: 0.0,
+            wireframe: false,
+            debug_draw_bounds: false,
+            frustum_culling: false,
+            draw_grid: false,
+            grid_spacing: 1.0,
+            grid_color: wgpu::Color { r: 0.25, g: 0.25, b: 0.25, a: 1.0 },
+            frame_index_policy: FrameIndexPolicy::default(),
+            clear_color: wgpu::Color::BLACK,
+            frame_extents: (0, 0),
+            pass_hooks: PassHooks::default(),
+        })
     }
-    pub fn render(&mut self, device: &DeviceHandle,
-                         target_surface_view: &wgpu::TextureView,
-                         target_texture_views: &Vec<wgpu::TextureView>,
-                         scene_data: &SceneData,
+
+    /// The device the engine's resources live on.
+    pub fn device_id(&self) -> DeviceId {
+        self.device_id
+    }
+
+    /// The color format the render pipelines are built for.
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    /// Rebuilds the render pipelines for a new target format, e.g. for a surface recreated after a suspend.
+    /// When multisampling, the dongle's color view must already have the new format.
+    pub fn set_format<D: TargetTextureDongle>(
+        &mut self,
+        device: &DeviceHandle,
+        format: wgpu::TextureFormat,
+        dongle: &D,
     ) -> Result<()> {
-        let frame_info = self.loader.frame_info();
-        if scene_data.objects.len() as u64 > self.object_scene_capacity {
-            let old_capacity = self.object_scene_capacity;
-            while self.object_scene_capacity < scene_data.objects.len() as u64 {
-                self.object_scene_capacity *= 2;
-            }
-            info!(
-                "Scene objects {} exceeds buffer capacity {}, resizing to capacity {}.",
-                scene_data.objects.len(),
-                old_capacity,
-                self.object_scene_capacity,
-            );
-            self.object_scene_buffer.destroy();
-            self.object_scene_buffer = device
-                .create_buffer_with_layout_enum(
-                    &SceneGroup::Object,
-                    self.object_scene_capacity
-                );
-            self.scene_bind_group = device
-                .create_bind_group_with_enum_layout_map(
-                    &self.scene_bind_group_layout,
-                    Some("Scene bind group"),
-                    |t| match t {
-                        SceneGroup::Object => self.object_scene_buffer.as_entire_binding(),
-                    }
-                );
+        if format == self.format {
+            return Ok(());
         }
-        let shard_extent: u32 = scene_data
-            .objects
-            .iter()
-            .map(|o| frame_info[o.frame_index as usize].shard_size)
-            .sum();
+        if !device.is_color_renderable(format) {
+            return Err(FightishError::InvalidTarget(format!("{:?} is not a color renderable format.", format)));
+        }
+        check_color_view(dongle, &format, self.sample_count)?;
+        self.rebuild_pipelines(device, format, self.render_pipelines.options)
+    }
 
-        let segment_extent: u32 = scene_data
-            .objects
-            .iter()
-            .map(|o| frame_info[o.frame_index as usize].segment_size)
-            .sum();
+    /// Sets how many compute invocations share the preprocessing of each object, rebuilding the compute pipeline.
+    /// Larger workgroups suit frames with many shards and segments. Each object is still one workgroup.
+    pub fn set_compute_workgroup_size(&mut self, device: &DeviceHandle, size: u32) -> Result<()> {
+        let limits = device.device.limits();
+        let max_size = limits.max_compute_workgroup_size_x.min(limits.max_compute_invocations_per_workgroup);
+        if size == 0 || size > max_size {
+            return Err(FightishError::InvalidArgument(format!(
+                "Compute workgroup size {} out of range, the device allows 1 to {}.",
+                size,
+                max_size,
+            )));
+        }
+        if size == self.compute_workgroup_size {
+            return Ok(());
+        }
+        device.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let compute_pipeline = create_compute_pipeline(device, &self.compute_pipeline_layout, size);
+        if let Some(e) = pollster::block_on(device.device.pop_error_scope()) {
+            return Err(FightishError::Pipeline(e.to_string()));
+        }
+        self.compute_pipeline = compute_pipeline;
+        self.compute_workgroup_size = size;
+        Ok(())
+    }
 
-        let model_group = self.loader.bind_group().unwrap();
+    pub fn compute_workgroup_size(&self) -> u32 {
+        self.compute_workgroup_size
+    }
 
-        let mut frame_bind_group_dirty = false;
-        let shard_vertex_extent = shard_extent as u64 * 6;
-        if shard_vertex_extent > self.shard_vertex_frame_capacity {
-            frame_bind_group_dirty = true;
-            let old_capacity = self.shard_vertex_frame_capacity;
-            while self.shard_vertex_frame_capacity < shard_vertex_extent {
-                self.shard_vertex_frame_capacity *= 2;
-            }
-            info!(
-                "Frame shard vertices requested {} exceeds capacity {}, resizing buffer to capacity {}.",
-                shard_vertex_extent,
-                old_capacity,
-                self.shard_vertex_frame_capacity,
-            );
-            self.shard_vertex_frame_buffer.destroy();
-            self.shard_vertex_frame_buffer = device
-                .create_buffer_with_layout_enum(
-                    &FrameGroup::ShardVertex,
-                    self.shard_vertex_frame_capacity
-                );
+    /// The most objects a scene can draw on the device, limited by the size of the object storage buffer
+    /// and by the compute workgroups per dispatch, one per object.
+    /// Objects of several models are padded apart in the buffer, so mixed scenes fit slightly fewer.
+    pub fn max_objects(&self, device: &DeviceHandle) -> u64 {
+        let max_workgroups = device.device.limits().max_compute_workgroups_per_dimension as u64;
+        (max_binding_size(device) / SceneGroup::Object.size()).min(max_workgroups)
+    }
+
+    /// The most shards, summed over the drawn objects, a scene can have on the device.
+    pub fn max_shards(&self, device: &DeviceHandle) -> u64 {
+        (max_binding_size(device) / (6 * FrameGroup::ShardVertex.size())).min(Self::MAX_SHARD_EXTENT as u64)
+    }
+
+    /// The most segments, summed over the drawn objects, a scene can have on the device.
+    pub fn max_segments(&self, device: &DeviceHandle) -> u64 {
+        max_binding_size(device) / FrameGroup::Segment.size()
+    }
+
+    /// Sets which faces of the shard quads are culled, and which winding is front facing,
+    /// rebuilding the render pipelines. Shard quads are wound counterclockwise on screen unless the object's
+    /// transform mirrors them, so culling back faces with [`wgpu::FrontFace::Ccw`] keeps unmirrored objects.
+    /// Nothing is culled by default.
+    pub fn set_culling(
+        &mut self,
+        device: &DeviceHandle,
+        cull_mode: Option<wgpu::Face>,
+        front_face: wgpu::FrontFace,
+    ) -> Result<()> {
+        let options = PipelineOptions { cull_mode, front_face, ..self.render_pipelines.options };
+        if options == self.render_pipelines.options {
+            return Ok(());
         }
-        if segment_extent as u64 > self.segment_frame_capacity {
-            frame_bind_group_dirty = true;
-            let old_capacity = self.segment_frame_capacity;
-            while self.segment_frame_capacity < segment_extent as u64 {
-                self.segment_frame_capacity *= 2;
-            }
-            info!(
-                "Frame segments requested {} exceeds capacity {}, resizing buffer to capacity {}.",
-                segment_extent,
-                old_capacity,
-                self.segment_frame_capacity,
-            );
-            self.segment_frame_buffer.destroy();
-            self.segment_frame_buffer = device
-                .create_buffer_with_layout_enum(
-                    &FrameGroup::Segment,
-                    self.segment_frame_capacity);
+        self.rebuild_pipelines(device, self.format, options)
+    }
+
+    /// Sets how shard colors combine with what's drawn before them, rebuilding the render pipelines.
+    /// Blending follows the draw order, which is the layer and then scene order of the objects and sprites,
+    /// and within an object the model's shard order. Depth isn't written while blending, so shards blend over
+    /// everything drawn before them, and instancing is off.
+    /// Antialiasing blends with straight alpha even in [`BlendMode::Replace`].
+    pub fn set_blend_mode(&mut self, device: &DeviceHandle, blend_mode: BlendMode) -> Result<()> {
+        if blend_mode == self.render_pipelines.options.blend_mode {
+            return Ok(());
         }
-        if frame_bind_group_dirty {
-            info!("Rebuilding dirty bind groups.");
-            self.frame_bind_group = device
-                .create_bind_group_with_enum_layout_map(
-                    &self.frame_bind_group_layout,
-                    Some("Frame bind group"),
-                    |t| match t {
-                        FrameGroup::Segment => self.segment_frame_buffer.as_entire_binding(),
-                        FrameGroup::ShardVertex => self.shard_vertex_frame_buffer.as_entire_binding(),
-                    }
-                );
-            self.frame_read_bind_group = device
-                .create_bind_group_with_enum_layout_map(
-                    &self.frame_read_bind_group_layout,
-                    Some("Frame read bind group"),
-                    |t| match t {
-                        FrameGroup::Segment => self.segment_frame_buffer.as_entire_binding(),
-                        FrameGroup::ShardVertex => self.shard_vertex_frame_buffer.as_entire_binding(),
-                    }
-                );
+        let options = PipelineOptions { blend_mode, ..self.render_pipelines.options };
+        self.rebuild_pipelines(device, self.format, options)
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.render_pipelines.options.blend_mode
+    }
+
+    fn rebuild_pipelines(&mut self, device: &DeviceHandle, format: wgpu::TextureFormat, options: PipelineOptions) -> Result<()> {
+        device.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let render_pipelines = RenderPipelines::new(
+            device,
+            &self.render_pipeline_layout,
+            &self.instanced_pipeline_layout,
+            &self.sprite_pipeline_layout,
+            format,
+            self.depth_format,
+            self.sample_count,
+            options,
+        );
+        if let Some(e) = pollster::block_on(device.device.pop_error_scope()) {
+            return Err(FightishError::Pipeline(e.to_string()));
         }
+        self.render_pipelines = render_pipelines;
+        self.format = format;
+        Ok(())
+    }
 
-        let mut encoder = device
-            .device
-            .create_command_encoder(
-                &wgpu::CommandEncoderDescriptor {
-                    label: Some("Render Encoder"),
+    /// Finds the topmost object drawn at fragment position `frag` in `viewport`, returning its index in the scene.
+    /// Objects are hit where any shard's bounding box covers the point, which is coarser than the drawn shapes.
+    /// Needs the CPU side models, so fails for models loaded with [`SimpleLoader::from_bytes`].
+    pub fn pick(&self, scene_data: &SceneData, viewport: &Viewport, frag: [f32; 2]) -> Result<Option<usize>> {
+        let clip = frag_clip_tf(viewport)
+            .invert()
+            .ok_or(FightishError::InvalidArgument("Cannot pick in an empty viewport.".into()))?
+            * cgmath::vec4(frag[0], frag[1], 0.0, 1.0);
+        let world = scene_data.camera_tf * clip;
+
+        // resolved frame of each object, skipped and hidden objects take no clip depth
+        let frames = scene_data.objects
+            .iter()
+            .map(|object| {
+                if !object.visible {
+                    return Ok(None);
                 }
-            );
+                let loader = self.models
+                    .get(object.model_id)
+                    .ok_or(FightishError::UnknownModel(object.model_id))?;
+                Ok(self.frame_index_policy
+                    .resolve(object.frame_index, loader.frame_info().len())?
+                    .map(|f| (loader, f)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // mirrors the clip offsets of `render_scene`, where the larger clip depth is drawn on top.
+        let clip_offsets = layered_clip_offsets(scene_data.objects.iter().zip(&frames).map(|(o, frame)| {
+            (o.layer, frame.map_or(0, |(loader, f)| loader.frame_info()[f].clip_size))
+        }))?;
+        let mut topmost: Option<(u32, usize)> = None;
+        for (i, object) in scene_data.objects.iter().enumerate() {
+            let Some((loader, f)) = frames[i] else { continue };
+            let clip_offset = clip_offsets[i];
+            let model = loader
+                .model()
+                .ok_or(FightishError::NoCpuModel(object.model_id))?;
+            // a degenerate object has no area to hit
+            if let Some(local_world_tf) = planar_inverse(&object.world_local_tf) {
+                let local = local_world_tf * world;
+                let local = local.truncate().truncate() / local.w;
+                let frame = &model.frames[f];
+                for shard in &model.shards[frame.shard_range[0] as usize..frame.shard_range[1] as usize] {
+                    let [x0, y0, x1, y1] = shard.bb;
+                    let depth = shard.clip_depth + clip_offset;
+                    let hit = x0.min(x1) <= local.x && local.x <= x0.max(x1)
+                        && y0.min(y1) <= local.y && local.y <= y0.max(y1);
+                    if hit && topmost.is_none_or(|(d, _)| depth >= d) {
+                        topmost = Some((depth, i));
+                    }
+                }
+            }
+        }
+        Ok(topmost.map(|(_, i)| i))
+    }
 
-        let mut view = device.queue.write_buffer_with(
-            &self.object_scene_buffer,
-            0,
-            wgpu::BufferSize::new(SceneGroup::Object.size() * scene_data.objects.len() as u64).unwrap(),
-        )
-            .ok_or(anyhow!("Unable to get object buffer view"))?;
-        let mut clip_offset: u32 = 0;
-        let mut shard_offset: i32 = 0;
-        let mut segment_offset: i32 = 0;
+    /// The number of frames in a model, `None` if there's no such model.
+    pub fn num_frames(&self, model_id: usize) -> Option<usize> {
+        self.models.get(model_id).map(|l| l.frame_info().len())
+    }
 
-        for i in 0..scene_data.objects.len() {
-            let o = &scene_data.objects[i];
-            bytemuck::cast_slice_mut(&mut *view)[i] = FrameObject {
-                world_tex_tf: o.world_local_tf.into(),
-                frame_index: o.frame_index,
-                clip_offset,
-                shard_offset,
-                segment_offset,
-            };
-            let frame: &FrameInfo = &frame_info[o.frame_index as usize];
-            clip_offset += frame.clip_size;
-            shard_offset += frame.shard_size as i32;
-            segment_offset += frame.segment_size as i32;
-        }
-        drop(view);
+    /// A frame's bounds in the model's coordinates, see [`ModelLoader::frame_bounds`].
+    pub fn frame_bounds(&self, model_id: usize, frame_index: usize) -> Option<[f32; 4]> {
+        self.models.get(model_id).and_then(|l| l.frame_bounds(frame_index))
+    }
 
-        let mut view = device
-            .queue
-            .write_buffer_with(
-                &self.world_uniforms_buffer,
-                0,
-                wgpu::BufferSize::new(UniformGroup::World.size()).unwrap(),
-            )
-            .ok_or(anyhow!("Could not write to world uniforms buffer"))?;
-        view.copy_from_slice(bytemuck::cast_slice(
-            &[Self::get_uniforms(scene_data)]
-        ));
-        drop(view);
+    /// Whether an object showing the frame changes with the scene time, through shards with a nonzero
+    /// [`ModelShard::anim_mode`]. The frame index is resolved like in rendering, and is `false` where that fails.
+    pub fn frame_animated(&self, model_id: usize, frame_index: i32) -> bool {
+        self.models.get(model_id).is_some_and(|l| {
+            let frame_info = l.frame_info();
+            matches!(self.frame_index_policy.resolve(frame_index, frame_info.len()), Ok(Some(f)) if frame_info[f].animated)
+        })
+    }
 
-        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
-            label: Some("Frame Preprocessing Pass"),
-            timestamp_writes: None,
+    /// Sets the atlas that objects with a [`Object::sprite`] rect are textured from, replacing any previous one.
+    pub fn set_sprite_atlas(&mut self, device: &DeviceHandle, atlas: &SpriteAtlas) -> Result<()> {
+        let max_size = device.device.limits().max_texture_dimension_2d;
+        if atlas.width > max_size || atlas.height > max_size {
+            return Err(FightishError::InvalidArgument(format!(
+                "Sprite atlas {}x{} exceeds the device's limit of {} pixels per side.",
+                atlas.width,
+                atlas.height,
+                max_size,
+            )));
+        }
+        let size = wgpu::Extent3d { width: atlas.width, height: atlas.height, depth_or_array_layers: 1 };
+        let texture = device.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Sprite atlas texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SpriteAtlas::FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
         });
-        compute_pass.set_pipeline(&self.compute_pipeline);
-        compute_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-        compute_pass.set_bind_group(1, &self.frame_bind_group, &[]);
-        compute_pass.set_bind_group(2, model_group, &[]);
-        compute_pass.set_bind_group(3, &self.scene_bind_group, &[]);
-        compute_pass.dispatch_workgroups(scene_data.objects.len() as u32, 1, 1);
-        drop(compute_pass);
-
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Render Pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &target_surface_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.0,
-                        g: 0.0,
-                        b: 0.0,
-                        a: 1.0,
-                    }),
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &target_texture_views[0],
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(0.0),
-                    store: wgpu::StoreOp::Store,
-                }),
-                stencil_ops: None,
-            }),
-            occlusion_query_set: None,
-            timestamp_writes: None,
+        device.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &atlas.pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * atlas.width),
+                rows_per_image: Some(atlas.height),
+            },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // clamped, so sprites at the atlas edges don't bleed into the opposite edge
+        let sampler = device.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Sprite atlas sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
         });
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-        render_pass.set_bind_group(1, &self.frame_read_bind_group, &[]);
-        render_pass.draw(0..(shard_extent * 6), 0..1);
-        drop(render_pass);
-
-        device.queue.submit(std::iter::once(encoder.finish()));
+        let entries = TextureGroup::ENTRIES.map(|t| wgpu::BindGroupEntry {
+            binding: t.binding(),
+            resource: match t {
+                TextureGroup::Texture => wgpu::BindingResource::TextureView(&view),
+                TextureGroup::Sampler => wgpu::BindingResource::Sampler(&sampler),
+            },
+        });
+        self.sprite_atlas = Some(device.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Texture bind group"),
+            layout: &self.texture_bind_group_layout,
+            entries: &entries,
+        }));
         Ok(())
     }
 
-    fn get_uniforms(scene_data: &SceneData) -> Uniforms{
-        let frag_clip_tf = // frag coords scaled from vp_x/y to width + vp_x / height + vp_y;
-            cgmath::Matrix4::from_translation(cgmath::vec3(
-                scene_data.vp_x as f32,
-                scene_data.vp_y as f32,
-                0f32,
-            ))
-                * // scaled from 0 to width/height
-                cgmath::Matrix4::from_nonuniform_scale(
-                    scene_data.vp_width as f32 / 2.0,
-                    -(scene_data.vp_height as f32 / 2.0),
-                    1f32,
-                )
-                * // scaled from 0 to +2 for x and -2 to 0 for y
-                cgmath::Matrix4::from_translation(cgmath::vec3(1f32, -1f32, 0f32))
-            ; // scaled -1 to +1 (clip coords)
-
-        let world_clip_tf = scene_data.camera_tf;
-
-        Uniforms {
-            clip_world_tf: world_clip_tf.invert().unwrap().into(),
-            frag_clip_tf: frag_clip_tf.into(),
-        }
+    /// Adds another model for objects to draw from, returning its model id.
+    pub fn add_model(&mut self, device: &DeviceHandle, mut loader: L) -> usize {
+        loader.load(device);
+        self.models.add(loader)
     }
-}
 
-#[derive(Debug)]
-pub struct RenderDongle ();
-impl RenderDongle {
-    pub fn new() -> Self {Self ()}
-}
-impl TargetTextureDongle for RenderDongle {
-    fn num_textures(&self) -> usize { 1 }
+    pub fn set_frame_index_policy(&mut self, policy: FrameIndexPolicy) {
+        self.frame_index_policy = policy;
+    }
 
-    fn texture_desc(&self, _index: usize, width: u32, height: u32) -> wgpu::TextureDescriptor {
-        let depth_size = wgpu::Extent3d {
-            width,
-            height,
+    /// The color the target is cleared to before the first scene, black by default.
+    pub fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.clear_color = color;
+    }
+
+    /// Draws the smallest model frame through each usable pipeline, general, instanced and sprite, into a
+    /// throwaway 1x1 target and waits for the GPU. Some drivers only finish compiling pipelines on first use,
+    /// and the scene and frame buffers are grown on first use too. Warmed up, the first real frame should take
+    /// about as long as the ones after it, instead of adding the pipeline compilation, often tens of
+    /// milliseconds per pipeline, and the initial allocations. The buffers grow only to the small warm-up scene.
+    pub fn warm_up(&mut self, device: &DeviceHandle) -> Result<()> {
+        let size = wgpu::Extent3d {
+            width: 1,
+            height: 1,
             depth_or_array_layers: 1,
         };
-        wgpu::TextureDescriptor {
-            label: Some("Depth buffer"),
-            size: depth_size,
+        let texture_desc = |label, format, sample_count| wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
-            format: DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::TEXTURE_BINDING,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             view_formats: &[],
+        };
+        let color = device.device.create_texture(&texture_desc("Warm up color texture", self.format, 1));
+        // stand-ins for the target's views, unused indices just get another depth texture.
+        let num_views = self.depth_view_index.max(self.color_view_index.unwrap_or(0)) + 1;
+        let views: Vec<wgpu::TextureView> = (0..num_views)
+            .map(|i| {
+                let format = if Some(i) == self.color_view_index { self.format } else { self.depth_format };
+                device
+                    .device
+                    .create_texture(&texture_desc("Warm up target texture", format, self.sample_count))
+                    .create_view(&wgpu::TextureViewDescriptor::default())
+            })
+            .collect();
+
+        // the frame with the fewest segments, fit to the view so frustum culling keeps it
+        let smallest = self.models
+            .iter()
+            .enumerate()
+            .flat_map(|(model_id, loader)| loader.frame_info()
+                .iter()
+                .enumerate()
+                .filter(|(_, f)| f.shard_size > 0)
+                .map(move |(i, f)| (model_id, i, *f))
+            )
+            .min_by_key(|(_, _, f)| f.segment_size);
+        let objects: Vec<Object> = smallest.map_or_else(Vec::new, |(model_id, i, frame)| {
+            let [x0, y0, x1, y1] = frame.bounds;
+            let size = (x1 - x0).max(y1 - y0).max(f32::EPSILON);
+            let tf = cgmath::Matrix4::from_scale(1.0 / size)
+                * cgmath::Matrix4::from_translation(cgmath::Vector3::new(-(x0 + x1) / 2.0, -(y0 + y1) / 2.0, 0.0));
+            let object = Object { model_id, ..Object::new(tf, i as i32) };
+            let sprite = self.sprite_atlas
+                .is_some()
+                .then(|| Object { sprite: Some([0.0, 0.0, 1.0, 1.0]), ..object.clone() });
+            std::iter::once(object).chain(sprite).collect()
+        });
+        let instances = smallest.map_or(0, |_| Self::MIN_INSTANCES);
+        let color_view = color.create_view(&wgpu::TextureViewDescriptor::default());
+        // the general path and sprites, then enough copies of the object to be drawn instanced
+        for objects in [objects.clone(), objects.into_iter().take(1).cycle().take(instances).collect()] {
+            let scene_data = SceneData {
+                vp_x: 0,
+                vp_y: 0,
+                vp_width: 1,
+                vp_height: 1,
+                camera_tf: cgmath::Matrix4::identity(),
+                time: 0.0,
+                global_tint: [1.0; 4],
+                objects,
+            };
+            self.render(device, &color_view, &views, &scene_data)?;
+        }
+        device.device.poll(wgpu::Maintain::Wait);
+        color.destroy();
+        Ok(())
+    }
+
+    /// Estimates the GPU memory held by the engine's buffers, from their capacities and element sizes.
+    /// Textures owned by the render target aren't included.
+    pub fn gpu_memory_estimate(&self) -> MemoryReport {
+        MemoryReport {
+            uniforms: UniformGroup::World.size(),
+            scene: self.object_scene_buffer.bytes() + self.instance_buffer.bytes() + self.sprite_buffer.bytes(),
+            frame: self.shard_vertex_frame_buffer.bytes() + self.segment_frame_buffer.bytes(),
+            model: self.models.gpu_bytes(),
+            queries: self.visibility_queries
+                .as_ref()
+                .map(|q| 2 * q.capacity as u64 * wgpu::QUERY_SIZE as u64)
+                .unwrap_or(0),
+        }
+    }
+
+    /// Draws the outline of every shard's bounding quad over the scene, for debugging the geometry.
+    /// Needs line polygon mode support, without which it stays off with a warning.
+    pub fn set_wireframe(&mut self, enabled: bool) {
+        if enabled && self.render_pipelines.wireframe.is_none() {
+            warn!("Wireframe mode unsupported by the device, keeping fill mode.");
+            return;
+        }
+        self.wireframe = enabled;
+    }
+
+    pub fn wireframe(&self) -> bool {
+        self.wireframe
+    }
+
+    /// Outlines the transformed bounding box of every shard over the scene,
+    /// to check the bounding boxes from the compute pass cover the drawn shards.
+    pub fn set_debug_draw_bounds(&mut self, enabled: bool) {
+        self.debug_draw_bounds = enabled;
+    }
+
+    /// Draws a world space reference grid behind the objects, with lines one pixel wide at any zoom.
+    pub fn set_draw_grid(&mut self, enabled: bool) {
+        self.draw_grid = enabled;
+    }
+
+    /// Sets the world units between grid lines, 1 by default.
+    pub fn set_grid_spacing(&mut self, spacing: f32) {
+        if spacing.is_finite() && spacing > 0.0 {
+            self.grid_spacing = spacing;
+        } else {
+            warn!("Ignoring invalid grid spacing {}.", spacing);
+        }
+    }
+
+    /// Sets the color of the grid lines, blended with their alpha over the clear color.
+    pub fn set_grid_color(&mut self, color: wgpu::Color) {
+        self.grid_color = color;
+    }
+
+    /// Makes the preprocessed frame buffers copyable, for [`RenderEngine::dump_frame_buffers`].
+    /// Off by default, since the extra usage may cost performance. Takes effect from the next render.
+    pub fn set_debug_readback(&mut self, enabled: bool) {
+        let usage = if enabled { wgpu::BufferUsages::COPY_SRC } else { wgpu::BufferUsages::empty() };
+        self.shard_vertex_frame_buffer.set_extra_usage(usage);
+        self.segment_frame_buffer.set_extra_usage(usage);
+    }
+
+    /// Reads back the shard vertices and segments the compute pass wrote for the last rendered scene,
+    /// blocking until the GPU is done. The bytes are laid out like the shaders' `ShardVertex` and `FrameSegment`.
+    /// Needs [`RenderEngine::set_debug_readback`] before that render.
+    pub fn dump_frame_buffers(&self, device: &DeviceHandle) -> Result<FrameBufferDump> {
+        let (shard_vertices, segments) = self.frame_extents;
+        Ok(FrameBufferDump {
+            shard_vertices: self.shard_vertex_frame_buffer.read(device, shard_vertices)?,
+            segments: self.segment_frame_buffer.read(device, segments)?,
+        })
+    }
+
+    /// Sets the grid, in pixels, that transformed segment endpoints are snapped to before the coverage test.
+    /// Shards sharing an edge then see bit-identical endpoints, which avoids cracks and double coverage
+    /// along the seam. Edges move by at most half the epsilon, so keep it well below a pixel;
+    /// 0, the default, disables snapping.
+    pub fn set_winding_epsilon(&mut self, epsilon: f32) {
+        self.winding_epsilon = epsilon.max(0.0);
+    }
+
+    /// Sets the width in pixels over which shard edges fade out, e.g. 1 to smooth edges without MSAA;
+    /// 0, the default, keeps hard edges. Coverage is blended with the shard's alpha, so turning it on or off
+    /// rebuilds the render pipelines to alpha blend while it's enabled. Edges shared by abutting shards of a frame
+    /// stay hard, so they meet without a seam.
+    pub fn set_aa_width(&mut self, device: &DeviceHandle, width: f32) -> Result<()> {
+        self.aa_width = width.max(0.0);
+        // edge coverage needs alpha blending, which would otherwise change how translucent shards draw.
+        if self.render_pipelines.options.antialiased == (self.aa_width > 0.0) {
+            return Ok(());
         }
+        let options = PipelineOptions { antialiased: self.aa_width > 0.0, ..self.render_pipelines.options };
+        self.rebuild_pipelines(device, self.format, options)
+    }
+
+    /// Skips objects whose frame bounds lie entirely outside the viewport, before any GPU work is done for them.
+    /// Saves compute and draw work in large worlds, at the CPU cost of transforming every object's bounds.
+    /// Culled objects get no visibility query results. Off by default.
+    pub fn set_frustum_culling(&mut self, enabled: bool) {
+        self.frustum_culling = enabled;
+    }
+
+    /// Sets how the scene and frame buffers grow when a scene doesn't fit, see [`BufferGrowth`].
+    /// Takes effect from the next render.
+    pub fn set_buffer_growth(&mut self, growth: BufferGrowth) {
+        self.shard_vertex_frame_buffer.growth = growth;
+        self.segment_frame_buffer.growth = growth;
+        self.object_scene_buffer.growth = growth;
+        self.instance_buffer.growth = growth;
+        self.sprite_buffer.growth = growth;
+    }
+
+    /// Enables or disables per-object occlusion queries.
+    /// When enabled each object is drawn with its own draw call bracketed by a query,
+    /// which costs some performance on scenes with many objects.
+    /// If the device can't create the queries, they're disabled again with a warning at the next render,
+    /// and [`RenderEngine::visibility_queries`] returns false. Some backends, e.g. OpenGL, only count 1 for
+    /// objects with any visible samples.
+    pub fn set_visibility_queries(&mut self, enabled: bool) {
+        self.visibility_enabled = enabled;
+        if !enabled {
+            self.visibility_queries = None;
+        }
+    }
+
+    /// Whether visibility queries are enabled, see [`RenderEngine::set_visibility_queries`].
+    pub fn visibility_queries(&self) -> bool {
+        self.visibility_enabled
+    }
+
+    /// The visible sample counts of the most recently read back frame, as (object index, samples) pairs.
+    /// Objects are indexed by their position in that frame's `SceneData::objects`.
+    /// Empty if visibility queries are disabled or no results have arrived yet.
+    pub fn last_visibility(&self) -> Vec<(usize, u32)> {
+        self.visibility_queries
+            .as_ref()
+            .map(|q| q.last.clone())
+            .unwrap_or_default()
+    }
+    /// The GPU time of the compute and render passes of the most recently read back frame.
+    /// `None` if the device doesn't support timestamp queries or no results have arrived yet.
+    pub fn last_timings(&self) -> Option<PassTimings> {
+        self.timestamp_queries.as_ref().and_then(|q| q.last)
+    }
+
+    /// Like [`RenderEngine::last_timings`], smoothed over recent frames.
+    pub fn average_timings(&self) -> Option<PassTimings> {
+        self.timestamp_queries.as_ref().and_then(|q| q.average)
+    }
+
+    /// Sets a callback recording commands into each scene's encoder before its render passes, after the compute pass,
+    /// e.g. to upload data or draw a background. It's given the color view the render pass draws into, which is
+    /// multisampled when the engine is, and the depth view. Both are cleared before the hook, as the render pass
+    /// would, and the render passes then load them, so what it draws stays under the scene's shards where its
+    /// depths are below theirs. `None` removes it.
+    ///
+    /// Hooks are called while the engine is mutably borrowed, and must be `'static`, so they share state with the
+    /// rest of the program through e.g. `Rc<RefCell<_>>` rather than borrows. Their commands go out in the
+    /// scene's submission, after anything they submit to the queue themselves.
+    pub fn set_pre_pass(&mut self, hook: Option<PassHook>) {
+        self.pass_hooks.pre = hook;
+    }
+
+    /// Sets a callback recording commands into each scene's encoder after its render pass, in the same submission,
+    /// e.g. to draw debug text or a UI over the scene. It's given the target view, which has the scene's colors by
+    /// then, resolved if multisampling, and the scene's depth view, with the engine's sample count.
+    /// Like [`RenderEngine::set_pre_pass`], it's called once per scene. `None` removes it.
+    pub fn set_post_pass(&mut self, hook: Option<PassHook>) {
+        self.pass_hooks.post = hook;
+    }
+
+    /// Renders a scene into its own viewport, which must lie within the target.
+    pub fn render(&mut self, device: &DeviceHandle,
+                         target_surface_view: &wgpu::TextureView,
+                         target_texture_views: &[wgpu::TextureView],
+                         scene_data: &SceneData,
+    ) -> Result<()> {
+        let viewport = Viewport::from_scene(scene_data);
+        // the target is only known to hold the viewport, which is clipped at the target's top left corner
+        let target_size = [
+            (viewport.x + viewport.width as i32).max(0) as u32,
+            (viewport.y + viewport.height as i32).max(0) as u32,
+        ];
+        self.render_multi(device, target_surface_view, target_texture_views, target_size, &[(scene_data, viewport)])
+    }
+
+    /// Renders a scene into a texture owned by someone else, e.g. to composite it in another renderer.
+    /// The texture must be single sampled, usable as render attachment and have the engine's [`RenderEngine::format`].
+    /// The depth and any multisampled color attachments are the `dongle`'s `textures`, created from its
+    /// [`TargetTextureDongle::texture_desc`] at the size of the texture.
+    pub fn render_to_texture<D: TargetTextureDongle>(
+        &mut self,
+        device: &DeviceHandle,
+        texture: &wgpu::Texture,
+        dongle: &D,
+        textures: &[wgpu::Texture],
+        scene_data: &SceneData,
+    ) -> Result<()> {
+        if texture.format() != self.format {
+            return Err(FightishError::InvalidTarget(format!(
+                "Texture format {:?} doesn't match the engine's format {:?}, see set_format.",
+                texture.format(),
+                self.format,
+            )));
+        }
+        if !texture.usage().contains(wgpu::TextureUsages::RENDER_ATTACHMENT) || texture.sample_count() != 1 {
+            return Err(FightishError::InvalidTarget(
+                "Cannot render into a texture that isn't a single sampled render attachment.".into(),
+            ));
+        }
+        let size = texture.size();
+        if textures.len() != dongle.num_textures() {
+            return Err(FightishError::InvalidTarget(format!(
+                "Got {} attachment textures, the dongle has {}.",
+                textures.len(),
+                dongle.num_textures(),
+            )));
+        }
+        // the passes would fail validation on the device rather than here
+        if let Some(t) = textures.iter().find(|t| (t.width(), t.height()) != (size.width, size.height)) {
+            return Err(FightishError::InvalidTarget(format!(
+                "Attachment size {}x{} doesn't match the {}x{} texture.",
+                t.width(),
+                t.height(),
+                size.width,
+                size.height,
+            )));
+        }
+        let views = (0..dongle.num_views())
+            .map(|i| {
+                let texture = textures.get(dongle.view_index(i)).ok_or(FightishError::InvalidTarget(format!(
+                    "View {} of the dongle has no texture.",
+                    i,
+                )))?;
+                Ok(texture.create_view(&dongle.view_desc(i)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let viewport = Viewport::from_scene(scene_data);
+        self.render_multi(device, &view, &views, [size.width, size.height], &[(scene_data, viewport)])
+    }
+
+    /// Renders a scene into an offscreen target and reads the result back as tightly packed pixels,
+    /// blocking until the GPU is done.
+    pub fn render_to_buffer<D: TargetTextureDongle>(
+        &mut self,
+        context: &RenderContext,
+        target: &OffscreenTarget<D>,
+        scene_data: &SceneData,
+    ) -> Result<Vec<u8>> {
+        let target_data = target.get_data();
+        let fits = scene_data.vp_x >= 0
+            && scene_data.vp_y >= 0
+            && scene_data.vp_x as u32 + scene_data.vp_width <= target_data.vp_width
+            && scene_data.vp_y as u32 + scene_data.vp_height <= target_data.vp_height;
+        if !fits {
+            return Err(FightishError::InvalidTarget(format!(
+                "Scene viewport {}x{} at ({}, {}) doesn't fit the {}x{} target.",
+                scene_data.vp_width,
+                scene_data.vp_height,
+                scene_data.vp_x,
+                scene_data.vp_y,
+                target_data.vp_width,
+                target_data.vp_height,
+            )));
+        }
+        self.render(target.device(context), target.color_view(), target.texture_views(), scene_data)?;
+        target.read_pixels(context)
+    }
+
+    /// Renders several independent scenes into the same target, each restricted to its own viewport.
+    /// The viewport takes precedence over the scene's own `vp_*` fields.
+    /// The color target is cleared before the first scene and loaded for the rest,
+    /// while depth is cleared for every scene so scenes don't occlude each other.
+    /// Viewports are clipped to the target of `target_size` `[width, height]` pixels, and scenes whose viewports
+    /// are entirely outside it draw nothing.
+    pub fn render_multi(&mut self, device: &DeviceHandle,
+                        target_surface_view: &wgpu::TextureView,
+                        target_texture_views: &[wgpu::TextureView],
+                        target_size: [u32; 2],
+                        scenes: &[(&SceneData, Viewport)],
+    ) -> Result<()> {
+        for (i, (scene_data, viewport)) in scenes.iter().enumerate() {
+            self.render_scene(
+                device,
+                target_surface_view,
+                target_texture_views,
+                target_size,
+                scene_data,
+                viewport,
+                i == 0,
+            )?;
+        }
+        Ok(())
+    }
+
+    // each scene is its own submission, since the scene buffers are shared and rewritten per scene.
+    #[allow(clippy::too_many_arguments)]
+    fn render_scene(&mut self, device: &DeviceHandle,
+                    target_surface_view: &wgpu::TextureView,
+                    target_texture_views: &[wgpu::TextureView],
+                    target_size: [u32; 2],
+                    scene_data: &SceneData,
+                    viewport: &Viewport,
+                    clear_color: bool,
+    ) -> Result<()> {
+        device.check_lost()?;
+        // wgpu needs viewports inside the target, so one hanging off it is cut down to the part inside, with the
+        // camera narrowed to match. One entirely outside draws nothing, but the passes still clear and time the scene.
+        let (viewport, camera_tf) = match viewport.scissor(target_size) {
+            Some([x, y, width, height]) => {
+                let clipped = Viewport { x: x as i32, y: y as i32, width, height };
+                let clip_tf = frag_clip_tf(viewport).invert().unwrap() * frag_clip_tf(&clipped);
+                (clipped, scene_data.camera_tf * clip_tf)
+            }
+            None => (*viewport, scene_data.camera_tf),
+        };
+        let viewport = &viewport;
+        // checked first, so a degenerate camera skips the scene before anything is written.
+        let uniforms = self.get_uniforms(scene_data, &camera_tf, viewport)?;
+        let depth_view = target_texture_views
+            .get(self.depth_view_index)
+            .ok_or(FightishError::MissingView { kind: "depth", index: self.depth_view_index })?;
+        // when multisampling, render into the dongle's color view and resolve into the target.
+        let (color_view, resolve_target) = match self.color_view_index {
+            Some(i) => (
+                target_texture_views
+                    .get(i)
+                    .ok_or(FightishError::MissingView { kind: "color", index: i })?,
+                Some(target_surface_view),
+            ),
+            None => (target_surface_view, None),
+        };
+        if let Some(queries) = self.visibility_queries.as_mut() {
+            queries.collect(device);
+        }
+        if let Some(queries) = self.timestamp_queries.as_mut() {
+            queries.collect(device);
+        }
+        // skipped while the previous timings are still being read back
+        let timestamp_set = self.timestamp_queries
+            .as_ref()
+            .filter(|q| !q.in_flight)
+            .map(|q| &q.query_set);
+        // (index in the scene, object, frame info of the resolved frame) of each object to draw
+        let mut objects: Vec<(usize, &Object, usize, &FrameInfo)> = scene_data
+            .objects
+            .iter()
+            .enumerate()
+            .filter(|(_, o)| o.visible)
+            .filter_map(|(i, o)| {
+                let Some(loader) = self.models.get(o.model_id) else {
+                    return Some(Err(FightishError::UnknownModel(o.model_id)));
+                };
+                let frame_info = loader.frame_info();
+                self
+                    .frame_index_policy
+                    .resolve(o.frame_index, frame_info.len())
+                    .transpose()
+                    .map(|f| f.map(|f| (i, o, f, &frame_info[f])))
+            })
+            .collect::<Result<_>>()?;
+        if self.frustum_culling {
+            let clip_world_tf = cgmath::Matrix4::from(uniforms.clip_world_tf);
+            // sprites too, whose quads span the same bounds. Antialiased edges reach past the shard bounding boxes.
+            let margin = [
+                2.0 * self.aa_width / viewport.width.max(1) as f32,
+                2.0 * self.aa_width / viewport.height.max(1) as f32,
+            ];
+            objects.retain(|&(_, o, _, frame)| !outside_clip(&(clip_world_tf * o.world_local_tf), &frame.bounds, margin));
+        }
+        // stable, so the scene order is kept within a layer. Objects and sprites are drawn in this order,
+        // which blending depends on.
+        objects.sort_by(|a, b| a.1.layer.total_cmp(&b.1.layer));
+        let blended = self.render_pipelines.options.blend_mode != BlendMode::Replace;
+
+        // objects sharing a frame with enough others are drawn instanced, after the rest, which only
+        // doesn't show while shards replace what's below them: blending and antialiased edges need the
+        // objects in order. Visibility queries need a draw per object, and the debug overlays only cover
+        // the compute pass's output, so they turn instancing off too.
+        let instancing = !blended
+            && self.aa_width <= 0.0
+            && !self.visibility_enabled
+            && !self.wireframe
+            && !self.debug_draw_bounds;
+        let mut frame_counts: HashMap<(usize, usize), usize> = HashMap::new();
+        for &(_, o, f, _) in &objects {
+            *frame_counts.entry((o.model_id, f)).or_default() += 1;
+        }
+        let frag_world_tf = frag_clip_tf(viewport) * cgmath::Matrix4::from(uniforms.clip_world_tf);
+
+        // offsets follow the layers and then the scene order, so later objects still draw on top.
+        let clip_offsets = layered_clip_offsets(objects.iter().map(|&(_, o, _, frame)| (o.layer, frame.clip_size)))?;
+        let mut shard_offset: i32 = 0;
+        let mut segment_offset: i32 = 0;
+        let mut object_draw_ranges: Vec<Range<u32>> = Vec::with_capacity(objects.len());
+        let mut object_indices: Vec<usize> = Vec::with_capacity(objects.len()); // in the scene, of each draw range
+        let mut frame_objects: Vec<(&Object, FrameObject)> = Vec::with_capacity(objects.len());
+        // (model id, frame) and instances of each instanced frame, in order of first appearance
+        let mut instance_groups: Vec<((usize, usize), Vec<InstanceData>)> = Vec::new();
+        let mut sprites: Vec<SpriteData> = Vec::new();
+        let mut draw_runs: Vec<DrawRun> = Vec::new();
+        for (&(i, o, f, frame), &object_clip_offset) in objects.iter().zip(&clip_offsets) {
+            if let Some(uv) = o.sprite {
+                // the quad spans the frame's bounds, which an empty frame doesn't have
+                if frame.shard_size == 0 {
+                    continue;
+                }
+                if self.sprite_atlas.is_none() {
+                    return Err(FightishError::MissingSpriteAtlas(i));
+                }
+                sprites.push(SpriteData {
+                    world_tex_tf: o.world_local_tf.into(),
+                    bounds: frame.bounds,
+                    uv,
+                    tint: o.tint,
+                    clip_depth: object_clip_offset,
+                    filler: [0; 3],
+                });
+                DrawRun::push(&mut draw_runs, DrawRun::Sprites((sprites.len() - 1)..sprites.len()));
+                continue;
+            }
+            if instancing && frame_counts[&(o.model_id, f)] >= Self::MIN_INSTANCES {
+                let instance = InstanceData {
+                    world_tex_tf: o.world_local_tf.into(),
+                    frag_tex_tf: (frag_world_tf * o.world_local_tf).into(),
+                    frame_index: f as i32,
+                    clip_offset: object_clip_offset,
+                    filler: [0; 2],
+                    tint: o.tint,
+                };
+                match instance_groups.iter_mut().find(|(key, _)| *key == (o.model_id, f)) {
+                    Some((_, instances)) => instances.push(instance),
+                    None => instance_groups.push(((o.model_id, f), vec![instance])),
+                }
+                continue;
+            }
+            let (next_shard_offset, next_segment_offset) = next_frame_offsets(shard_offset, segment_offset, frame)?;
+            frame_objects.push((o, FrameObject {
+                world_tex_tf: o.world_local_tf.into(),
+                frame_index: f as i32,
+                clip_offset: object_clip_offset,
+                shard_offset,
+                segment_offset,
+                tint: o.tint,
+            }));
+            object_draw_ranges.push((shard_offset as u32 * 6)..(next_shard_offset as u32 * 6));
+            DrawRun::push(&mut draw_runs, DrawRun::Objects((object_draw_ranges.len() - 1)..object_draw_ranges.len()));
+            object_indices.push(i);
+            shard_offset = next_shard_offset;
+            segment_offset = next_segment_offset;
+        }
+        let shard_extent = shard_offset as u32;
+        let segment_extent = segment_offset as u32;
+        if shard_extent as u64 > self.max_shards(device) || segment_extent as u64 > self.max_segments(device) {
+            return Err(FightishError::BufferOverflow(format!(
+                "Cannot render: scene's {} shards and {} segments exceed the device's limits of {} and {}.",
+                shard_extent,
+                segment_extent,
+                self.max_shards(device),
+                self.max_segments(device),
+            )));
+        }
+
+        // the compute pass runs once per model over that model's objects, so the scene buffer holds
+        // the objects grouped by model, each group starting at an offset it can be bound from.
+        let mut batches: Vec<(usize, Range<usize>)> = Vec::new(); // model id and object range
+        let mut batched_objects: Vec<FrameObject> = Vec::with_capacity(frame_objects.len());
+        for model_id in 0..self.models.len() {
+            let batch: Vec<FrameObject> = frame_objects
+                .iter()
+                .filter(|(o, _)| o.model_id == model_id)
+                .map(|&(_, fo)| fo)
+                .collect();
+            if batch.is_empty() { continue; }
+            let start = batched_objects.len().next_multiple_of(self.object_batch_alignment);
+            batched_objects.resize(start, bytemuck::Zeroable::zeroed());
+            batched_objects.extend(batch);
+            batches.push((model_id, start..batched_objects.len()));
+        }
+
+        if batched_objects.len() as u64 > self.max_objects(device) {
+            return Err(FightishError::BufferOverflow(format!(
+                "Cannot render: scene's {} objects, with padding between models, exceed the device's limit of {}.",
+                batched_objects.len(),
+                self.max_objects(device),
+            )));
+        }
+        if self.object_scene_buffer.reserve(device, batched_objects.len() as u64)? {
+            self.scene_bind_group = device
+                .create_bind_group_with_enum_layout_map(
+                    &self.scene_bind_group_layout,
+                    Some("Scene bind group"),
+                    |t| match t {
+                        SceneGroup::Object => self.object_scene_buffer.buffer().as_entire_binding(),
+                    }
+                );
+        }
+        // the first batch starts at 0 and can use the bind group of the whole buffer
+        let batch_bind_groups: Vec<Option<wgpu::BindGroup>> = batches
+            .iter()
+            .map(|(_, range)| (range.start > 0).then(|| {
+                device.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Scene batch bind group"),
+                    layout: &self.scene_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: SceneGroup::Object.binding(),
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: self.object_scene_buffer.buffer(),
+                            offset: range.start as u64 * SceneGroup::Object.size(),
+                            size: wgpu::BufferSize::new(range.len() as u64 * SceneGroup::Object.size()),
+                        }),
+                    }],
+                })
+            }))
+            .collect();
+
+        // (model id, shards per instance, instance range) of each instanced draw
+        let mut instance_batches: Vec<(usize, u32, Range<u32>)> = Vec::with_capacity(instance_groups.len());
+        let mut instances: Vec<InstanceData> = Vec::new();
+        for ((model_id, f), group) in instance_groups {
+            let shard_size = self.models.get(model_id).map_or(0, |l| l.frame_info()[f].shard_size);
+            let start = instances.len() as u32;
+            instances.extend(group);
+            instance_batches.push((model_id, shard_size, start..instances.len() as u32));
+        }
+        if self.instance_buffer.reserve(device, instances.len() as u64)? {
+            self.instance_bind_group = device
+                .create_bind_group_with_enum_layout_map(
+                    &self.instance_bind_group_layout,
+                    Some("Instance bind group"),
+                    |t| match t {
+                        InstanceGroup::Instance => self.instance_buffer.buffer().as_entire_binding(),
+                    }
+                );
+        }
+        if !instances.is_empty() {
+            device.queue.write_buffer(self.instance_buffer.buffer(), 0, bytemuck::cast_slice(&instances));
+        }
+        if self.sprite_buffer.reserve(device, sprites.len() as u64)? {
+            self.sprite_bind_group = device
+                .create_bind_group_with_enum_layout_map(
+                    &self.sprite_bind_group_layout,
+                    Some("Sprite bind group"),
+                    |t| match t {
+                        SpriteGroup::Sprite => self.sprite_buffer.buffer().as_entire_binding(),
+                    }
+                );
+        }
+        if !sprites.is_empty() {
+            device.queue.write_buffer(self.sprite_buffer.buffer(), 0, bytemuck::cast_slice(&sprites));
+        }
+
+        let shard_vertex_extent = shard_extent as u64 * 6;
+        // both must be reserved each scene, so they keep track of their usage.
+        let shard_vertex_resized = self.shard_vertex_frame_buffer.reserve(device, shard_vertex_extent)?;
+        let segment_resized = self.segment_frame_buffer.reserve(device, segment_extent as u64)?;
+        self.frame_extents = (shard_vertex_extent, segment_extent as u64);
+        if shard_vertex_resized || segment_resized {
+            info!("Rebuilding dirty bind groups.");
+            self.frame_bind_group = device
+                .create_bind_group_with_enum_layout_map(
+                    &self.frame_bind_group_layout,
+                    Some("Frame bind group"),
+                    |t| match t {
+                        FrameGroup::Segment => self.segment_frame_buffer.buffer().as_entire_binding(),
+                        FrameGroup::ShardVertex => self.shard_vertex_frame_buffer.buffer().as_entire_binding(),
+                    }
+                );
+            self.frame_read_bind_group = device
+                .create_bind_group_with_enum_layout_map(
+                    &self.frame_read_bind_group_layout,
+                    Some("Frame read bind group"),
+                    |t| match t {
+                        FrameGroup::Segment => self.segment_frame_buffer.buffer().as_entire_binding(),
+                        FrameGroup::ShardVertex => self.shard_vertex_frame_buffer.buffer().as_entire_binding(),
+                    }
+                );
+        }
+
+        let mut encoder = device
+            .device
+            .create_command_encoder(
+                &wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Encoder"),
+                }
+            );
+
+        if !batched_objects.is_empty() {
+            device.queue.write_buffer_with(
+                self.object_scene_buffer.buffer(),
+                0,
+                wgpu::BufferSize::new(SceneGroup::Object.size() * batched_objects.len() as u64).unwrap(),
+            )
+                .ok_or(FightishError::BufferWrite("object"))?
+                .copy_from_slice(bytemuck::cast_slice(&batched_objects));
+        }
+
+        let mut view = device
+            .queue
+            .write_buffer_with(
+                &self.world_uniforms_buffer,
+                0,
+                wgpu::BufferSize::new(UniformGroup::World.size()).unwrap(),
+            )
+            .ok_or(FightishError::BufferWrite("world uniforms"))?;
+        view.copy_from_slice(bytemuck::cast_slice(
+            &[uniforms]
+        ));
+        drop(view);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+            label: Some("Frame Preprocessing Pass"),
+            timestamp_writes: timestamp_set.map(|query_set| wgpu::ComputePassTimestampWrites {
+                query_set,
+                beginning_of_pass_write_index: Some(TimestampQueries::COMPUTE_BEGIN),
+                end_of_pass_write_index: Some(TimestampQueries::COMPUTE_END),
+            }),
+        });
+        // one workgroup per object, so a batch can't have more objects than workgroups in a dispatch.
+        let max_workgroups = device.device.limits().max_compute_workgroups_per_dimension;
+        if let Some((_, range)) = batches.iter().find(|(_, range)| range.len() > max_workgroups as usize) {
+            return Err(FightishError::BufferOverflow(format!(
+                "Cannot render: {} objects of one model exceed the {} compute workgroups per dispatch.",
+                range.len(),
+                max_workgroups,
+            )));
+        }
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.frame_bind_group, &[]);
+        for ((model_id, range), bind_group) in batches.iter().zip(&batch_bind_groups) {
+            let model_group = self.models
+                .get(*model_id)
+                .and_then(|l| l.bind_group())
+                .ok_or(FightishError::ModelNotLoaded(*model_id))?;
+            compute_pass.set_bind_group(2, model_group, &[]);
+            compute_pass.set_bind_group(3, bind_group.as_ref().unwrap_or(&self.scene_bind_group), &[]);
+            compute_pass.dispatch_workgroups(range.len() as u32, 1, 1);
+        }
+        drop(compute_pass);
+        // with a hook the views are cleared ahead of it, and the render passes load what it drew
+        let hooked = self.pass_hooks.pre.is_some();
+        if hooked {
+            drop(encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Clear Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: color_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: if clear_color {
+                            wgpu::LoadOp::Clear(self.clear_color)
+                        } else {
+                            wgpu::LoadOp::Load
+                        },
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            }));
+        }
+        if let Some(hook) = self.pass_hooks.pre.as_mut() {
+            hook(&mut encoder, color_view, depth_view);
+        }
+
+        // instancing is off with visibility queries, so these match the drawn objects, besides sprites, when they're used
+        let num_objects = object_indices.len() as u32;
+        // queries of objects that aren't drawn would read back garbage
+        let use_queries = if viewport.scissor(target_size).is_none() {
+            false
+        } else if self.visibility_enabled {
+            if num_objects > wgpu::QUERY_SET_MAX_QUERIES {
+                warn!(
+                    "Scene objects {} exceed the maximum query count {}, skipping visibility queries.",
+                    num_objects,
+                    wgpu::QUERY_SET_MAX_QUERIES,
+                );
+                false
+            } else {
+                let capacity = self.visibility_queries.as_ref().map(|q| q.capacity);
+                if capacity.is_none_or(|c| c < num_objects) {
+                    let capacity = num_objects.next_power_of_two().min(wgpu::QUERY_SET_MAX_QUERIES);
+                    self.visibility_queries = VisibilityQueries::new(device, capacity);
+                    if self.visibility_queries.is_none() {
+                        warn!("Disabling visibility queries.");
+                        self.visibility_enabled = false;
+                    }
+                }
+                // can't write the readback buffer while the previous results are still mapped
+                self.visibility_queries.as_ref().is_some_and(|q| q.in_flight.is_none())
+            }
+        } else {
+            false
+        };
+        let query_set = self.visibility_queries
+            .as_ref()
+            .filter(|_| use_queries)
+            .map(|q| &q.query_set);
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: color_view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: if clear_color && !hooked {
+                        wgpu::LoadOp::Clear(self.clear_color)
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: if hooked { wgpu::LoadOp::Load } else { wgpu::LoadOp::Clear(0.0) },
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: query_set,
+            timestamp_writes: timestamp_set.map(|query_set| wgpu::RenderPassTimestampWrites {
+                query_set,
+                beginning_of_pass_write_index: Some(TimestampQueries::RENDER_BEGIN),
+                end_of_pass_write_index: Some(TimestampQueries::RENDER_END),
+            }),
+        });
+        // a viewport off the target draws nothing, but the passes still clear and time the scene
+        if viewport.apply(&mut render_pass, target_size) {
+            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.frame_read_bind_group, &[]);
+            if self.draw_grid {
+                render_pass.set_pipeline(&self.render_pipelines.grid);
+                render_pass.draw(0..3, 0..1);
+            }
+            for run in &draw_runs {
+                match run {
+                    DrawRun::Objects(range) => {
+                        render_pass.set_pipeline(&self.render_pipelines.render);
+                        render_pass.set_bind_group(1, &self.frame_read_bind_group, &[]);
+                        if use_queries {
+                            // one draw per object so each gets its own sample count
+                            for i in range.clone() {
+                                render_pass.begin_occlusion_query(i as u32);
+                                render_pass.draw(object_draw_ranges[i].clone(), 0..1);
+                                render_pass.end_occlusion_query();
+                            }
+                        } else {
+                            // the shards of consecutive objects are consecutive in the frame buffer
+                            render_pass.draw(object_draw_ranges[range.start].start..object_draw_ranges[range.end - 1].end, 0..1);
+                        }
+                    }
+                    DrawRun::Sprites(range) => {
+                        // checked when the sprites were gathered
+                        let Some(atlas) = self.sprite_atlas.as_ref() else { continue };
+                        render_pass.set_pipeline(&self.render_pipelines.sprite);
+                        render_pass.set_bind_group(1, atlas, &[]);
+                        render_pass.set_bind_group(2, &self.sprite_bind_group, &[]);
+                        render_pass.draw(0..6, range.start as u32..range.end as u32);
+                    }
+                }
+            }
+            // instanced objects only come up without blending or antialiasing, where depth orders them against the rest
+            self.draw_instances(&mut render_pass, &self.render_pipelines.instanced, &instance_batches)?;
+            // the overlays only cover objects drawn through the compute pass
+            if !instance_batches.is_empty() || !sprites.is_empty() {
+                render_pass.set_bind_group(1, &self.frame_read_bind_group, &[]);
+            }
+            if let Some(wireframe) = self.render_pipelines.wireframe.as_ref().filter(|_| self.wireframe) {
+                render_pass.set_pipeline(wireframe);
+                render_pass.draw(0..(shard_extent * 6), 0..1);
+            }
+            if self.debug_draw_bounds {
+                // 4 edges of 2 vertices per shard
+                render_pass.set_pipeline(&self.render_pipelines.bounds);
+                render_pass.draw(0..(shard_extent * 8), 0..1);
+            }
+        }
+        drop(render_pass);
+        if let Some(hook) = self.pass_hooks.post.as_mut() {
+            hook(&mut encoder, target_surface_view, depth_view);
+        }
+
+        let queries = self.visibility_queries
+            .as_mut()
+            .filter(|_| use_queries);
+        if let Some(queries) = queries.as_ref() {
+            queries.resolve(&mut encoder, num_objects);
+        }
+        let mut timestamp_queries = self.timestamp_queries
+            .as_mut()
+            .filter(|q| !q.in_flight);
+        if let Some(queries) = timestamp_queries.as_mut() {
+            queries.resolve(&mut encoder);
+        }
+
+        device.queue.submit(std::iter::once(encoder.finish()));
+
+        if let Some(queries) = queries {
+            queries.map(object_indices);
+        }
+        if let Some(queries) = timestamp_queries {
+            queries.map();
+        }
+        Ok(())
+    }
+
+    /// Draws each batch's instances of a frame straight from the model, leaving the pipeline changed.
+    fn draw_instances(
+        &self,
+        pass: &mut wgpu::RenderPass,
+        pipeline: &wgpu::RenderPipeline,
+        instance_batches: &[(usize, u32, Range<u32>)],
+    ) -> Result<()> {
+        if instance_batches.is_empty() { return Ok(()); }
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        pass.set_bind_group(2, &self.instance_bind_group, &[]);
+        for (model_id, shard_size, instances) in instance_batches {
+            let model_group = self.models
+                .get(*model_id)
+                .and_then(|l| l.bind_group())
+                .ok_or(FightishError::ModelNotLoaded(*model_id))?;
+            pass.set_bind_group(1, model_group, &[]);
+            pass.draw(0..(shard_size * 6), instances.clone());
+        }
+        Ok(())
+    }
+
+    /// Fails if the camera transform isn't invertible, e.g. at zero scale.
+    fn get_uniforms(
+        &self,
+        scene_data: &SceneData,
+        camera_tf: &cgmath::Matrix4<f32>,
+        viewport: &Viewport,
+    ) -> Result<Uniforms> {
+        let frag_clip_tf = frag_clip_tf(viewport);
+        let world_clip_tf = *camera_tf;
+        let clip_world_tf = clip_world_tf(&world_clip_tf)?;
+
+        Ok(Uniforms {
+            clip_world_tf: clip_world_tf.into(),
+            frag_clip_tf: frag_clip_tf.into(),
+            winding_epsilon: self.winding_epsilon,
+            time: scene_data.time,
+            viewport_size: [viewport.width as f32, viewport.height as f32],
+            aa_width: self.aa_width,
+            premultiplied: (self.render_pipelines.options.blend_mode == BlendMode::PremultipliedAlpha) as u32,
+            filler: [0.0; 2],
+            global_tint: scene_data.global_tint,
+            world_clip_tf: world_clip_tf.into(),
+            grid_color: [
+                self.grid_color.r as f32,
+                self.grid_color.g as f32,
+                self.grid_color.b as f32,
+                self.grid_color.a as f32,
+            ],
+            grid_spacing: if self.draw_grid { self.grid_spacing } else { 0.0 },
+            grid_filler: [0.0; 3],
+        })
+    }
+}
+
+impl RenderEngine<SimpleLoader> {
+    /// Swaps in a new model, which is drawn from the next render on.
+    pub fn reload_model(&mut self, device: &DeviceHandle, model_id: usize, model: Model) -> Result<()> {
+        self.models
+            .get_mut(model_id)
+            .ok_or(FightishError::UnknownModel(model_id))?
+            .reload(device, model)
+    }
+}
+
+/// How [`RenderEngine::render`] treats objects whose `frame_index` is outside the loaded model's frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameIndexPolicy {
+    /// Fail the render with an error.
+    #[default]
+    Error,
+    /// Use the nearest valid frame.
+    Clamp,
+    /// Wrap around modulo the frame count, so animations can overshoot.
+    Wrap,
+    /// Don't draw the object.
+    Skip,
+}
+
+impl FrameIndexPolicy {
+    /// Resolves a frame index against `num_frames` frames, `None` meaning the object isn't drawn.
+    pub fn resolve(&self, frame_index: i32, num_frames: usize) -> Result<Option<usize>> {
+        if frame_index >= 0 && (frame_index as usize) < num_frames {
+            return Ok(Some(frame_index as usize));
+        }
+        if num_frames == 0 && *self != Self::Error {
+            return Ok(None);
+        }
+        match self {
+            Self::Error => Err(FightishError::FrameOutOfRange { frame_index, num_frames }),
+            Self::Clamp => Ok(Some((frame_index.max(0) as usize).min(num_frames - 1))),
+            Self::Wrap => Ok(Some((frame_index as i64).rem_euclid(num_frames as i64) as usize)),
+            Self::Skip => Ok(None),
+        }
+    }
+}
+
+/// The preprocessed frame buffers of the last rendered scene, see [`RenderEngine::dump_frame_buffers`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameBufferDump {
+    /// 6 vertices per drawn shard.
+    pub shard_vertices: Vec<u8>,
+    pub segments: Vec<u8>,
+}
+
+/// Bytes of GPU buffer memory held by a [`RenderEngine`], by category.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// The world uniforms buffer.
+    pub uniforms: u64,
+    /// The per-object scene buffer.
+    pub scene: u64,
+    /// The preprocessed shard vertex and segment buffers.
+    pub frame: u64,
+    /// The loaded model's buffers.
+    pub model: u64,
+    /// The visibility query resolve and readback buffers.
+    pub queries: u64,
+}
+
+impl MemoryReport {
+    pub fn total(&self) -> u64 {
+        self.uniforms + self.scene + self.frame + self.model + self.queries
+    }
+}
+
+/// Inverts the camera transform, failing instead of producing a singular or non-finite transform.
+fn clip_world_tf(world_clip_tf: &cgmath::Matrix4<f32>) -> Result<cgmath::Matrix4<f32>> {
+    world_clip_tf
+        .invert()
+        .filter(|tf| AsRef::<[f32; 16]>::as_ref(tf).iter().all(|c| c.is_finite()))
+        .ok_or(FightishError::SingularCamera)
+}
+
+/// The transform from clip coordinates to fragment coordinates, in pixels from the target's top left.
+fn frag_clip_tf(viewport: &Viewport) -> cgmath::Matrix4<f32> {
+    // frag coords scaled from vp_x/y to width + vp_x / height + vp_y;
+    cgmath::Matrix4::from_translation(cgmath::vec3(
+        viewport.x as f32,
+        viewport.y as f32,
+        0f32,
+    ))
+        * // scaled from 0 to width/height
+        cgmath::Matrix4::from_nonuniform_scale(
+            viewport.width as f32 / 2.0,
+            -(viewport.height as f32 / 2.0),
+            1f32,
+        )
+        * // scaled from 0 to +2 for x and -2 to 0 for y
+        cgmath::Matrix4::from_translation(cgmath::vec3(1f32, -1f32, 0f32))
+    // scaled -1 to +1 (clip coords)
+}
+
+/// The largest storage buffer the device can bind.
+fn max_binding_size(device: &DeviceHandle) -> u64 {
+    let limits = device.device.limits();
+    (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size)
+}
+
+/// The frame preprocessing pipeline, with `workgroup_size` invocations per object.
+fn create_compute_pipeline(
+    device: &DeviceHandle,
+    layout: &wgpu::PipelineLayout,
+    workgroup_size: u32,
+) -> wgpu::ComputePipeline {
+    // naga can't override workgroup sizes yet, so the size is a constant prepended to the shader.
+    let source = format!(
+        "const WORKGROUP_SIZE: u32 = {}u;\n{}{}{}",
+        workgroup_size,
+        include_str!("frame_preprocess.wgsl"),
+        include_str!("snap.wgsl"),
+        include_str!("animation.wgsl"),
+    );
+    let compute_shader = device
+        .device
+        .create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: Some("Frame preprocessing compute shader"),
+                source: wgpu::ShaderSource::Wgsl(source.into())
+            }
+        );
+    device
+        .device
+        .create_compute_pipeline(&wgpu::ComputePipelineDescriptor{
+            label: Some("Compute pipeline"),
+            layout: Some(layout),
+            module: &compute_shader,
+            entry_point: "main",
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        })
+}
+
+/// Checks the dongle's color view, if any, matches the target format and sample count.
+fn check_color_view<D: TargetTextureDongle>(dongle: &D, format: &wgpu::TextureFormat, sample_count: u32) -> Result<()> {
+    match dongle.color_view_index() {
+        None if sample_count > 1 => {
+            Err(FightishError::InvalidTarget("Multisampling needs a color view, but the dongle has none.".into()))
+        }
+        Some(i) if i >= dongle.num_views() => {
+            Err(FightishError::InvalidTarget(format!(
+                "Color view index {} out of range, the dongle has {} views.",
+                i,
+                dongle.num_views(),
+            )))
+        }
+        Some(i) if dongle.view_format(i) != *format || dongle.view_sample_count(i) != sample_count => {
+            Err(FightishError::InvalidTarget(format!(
+                "Color view {} has format {:?} with {} samples, expected {:?} with {}.",
+                i,
+                dongle.view_format(i),
+                dongle.view_sample_count(i),
+                format,
+                sample_count,
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The pipelines drawing shards, which depend on the target format.
+#[derive(Debug)]
+struct RenderPipelines {
+    render: wgpu::RenderPipeline,
+    wireframe: Option<wgpu::RenderPipeline>, // None without line polygon mode support
+    bounds: wgpu::RenderPipeline,
+    grid: wgpu::RenderPipeline,
+    instanced: wgpu::RenderPipeline,
+    sprite: wgpu::RenderPipeline,
+    options: PipelineOptions,
+}
+
+/// How shard colors combine with the colors already in the target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Colors replace what's below them, ignoring alpha.
+    #[default]
+    Replace,
+    /// Alpha-over blending of colors with straight alpha.
+    Alpha,
+    /// Alpha-over blending of colors already multiplied by their alpha. Tints keep straight alpha.
+    PremultipliedAlpha,
+}
+
+/// An RGBA image, with straight alpha in sRGB, that sprite objects are textured from, see [`Object::sprite`].
+/// It's sampled linearly, so sprites packed next to each other need a pixel of padding between them.
+#[derive(Debug, Clone)]
+pub struct SpriteAtlas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>, // rows from the top, 4 bytes per pixel
+}
+
+impl SpriteAtlas {
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// An atlas from tightly packed rows of RGBA pixels, from the top, failing if the size doesn't match.
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(FightishError::InvalidArgument(format!("Sprite atlas {}x{} is empty.", width, height)));
+        }
+        if pixels.len() as u64 != 4 * width as u64 * height as u64 {
+            return Err(FightishError::InvalidArgument(format!(
+                "Sprite atlas has {} bytes, but {}x{} pixels need {}.",
+                pixels.len(),
+                width,
+                height,
+                4 * width as u64 * height as u64,
+            )));
+        }
+        Ok(Self { width, height, pixels })
+    }
+
+    #[cfg(feature = "image")]
+    pub fn from_image(image: image::RgbaImage) -> Result<Self> {
+        let (width, height) = image.dimensions();
+        Self::new(width, height, image.into_raw())
+    }
+
+    /// Reads an atlas from an image file.
+    #[cfg(feature = "image")]
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let image = image::open(path).map_err(anyhow::Error::from)?;
+        Self::from_image(image.into_rgba8())
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// Settings the render pipelines are built with, besides the target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PipelineOptions {
+    antialiased: bool, // whether the color passes alpha blend, for edge coverage
+    cull_mode: Option<wgpu::Face>,
+    front_face: wgpu::FrontFace,
+    blend_mode: BlendMode,
+}
+
+impl PipelineOptions {
+    fn blend_state(&self) -> wgpu::BlendState {
+        match self.blend_mode {
+            BlendMode::Replace if !self.antialiased => wgpu::BlendState::REPLACE,
+            BlendMode::Replace | BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::PremultipliedAlpha => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+        }
+    }
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        Self {
+            antialiased: false,
+            cull_mode: None,
+            front_face: wgpu::FrontFace::Ccw,
+            blend_mode: BlendMode::default(),
+        }
+    }
+}
+
+impl RenderPipelines {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        device: &DeviceHandle,
+        layout: &wgpu::PipelineLayout,
+        instanced_layout: &wgpu::PipelineLayout,
+        sprite_layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        sample_count: u32,
+        options: PipelineOptions,
+    ) -> Self {
+        let shader = device
+            .device
+            .create_shader_module(
+                wgpu::ShaderModuleDescriptor {
+                    label: Some("Shader"),
+                    source: wgpu::ShaderSource::Wgsl(
+                        concat!(
+                            include_str!("shader.wgsl"),
+                            include_str!("winding.wgsl"),
+                            include_str!("depth.wgsl"),
+                        ).into()
+                    ),
+                }
+            );
+        let instanced_shader = device
+            .device
+            .create_shader_module(
+                wgpu::ShaderModuleDescriptor {
+                    label: Some("Instanced shader"),
+                    source: wgpu::ShaderSource::Wgsl(
+                        concat!(
+                            include_str!("instanced.wgsl"),
+                            include_str!("winding.wgsl"),
+                            include_str!("snap.wgsl"),
+                            include_str!("animation.wgsl"),
+                            include_str!("depth.wgsl"),
+                        ).into()
+                    ),
+                }
+            );
+        let sprite_shader = device
+            .device
+            .create_shader_module(
+                wgpu::ShaderModuleDescriptor {
+                    label: Some("Sprite shader"),
+                    source: wgpu::ShaderSource::Wgsl(
+                        concat!(include_str!("sprite.wgsl"), include_str!("depth.wgsl")).into()
+                    ),
+                }
+            );
+        let color_targets = [Some(wgpu::ColorTargetState {
+            format,
+            blend: Some(options.blend_state()),
+            write_mask: wgpu::ColorWrites::ALL,
+        })];
+        // blended shards are drawn back to front, and written depth would hide those behind them that are
+        // drawn later, within a frame or in later objects.
+        let depth_writes = options.blend_mode == BlendMode::Replace;
+        let render = create_shard_pipeline(
+            device,
+            "Render Pipeline",
+            layout,
+            &shader,
+            "vs_main",
+            "fs_main",
+            &color_targets,
+            depth_writes,
+            depth_format,
+            wgpu::CompareFunction::GreaterEqual,
+            sample_count,
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::PolygonMode::Fill,
+            &options,
+        );
+        // drawn over everything, so it neither tests nor writes depth.
+        let wireframe = device
+            .device
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+            .then(|| create_shard_pipeline(
+                device,
+                "Wireframe pipeline",
+                layout,
+                &shader,
+                "vs_main",
+                "fs_wireframe",
+                &color_targets,
+                false,
+                depth_format,
+                wgpu::CompareFunction::Always,
+                sample_count,
+                wgpu::PrimitiveTopology::TriangleList,
+                wgpu::PolygonMode::Line,
+                &options,
+            ));
+        // line lists don't need line polygon mode, so the bounds work on every device.
+        let bounds = create_shard_pipeline(
+            device,
+            "Shard bounds pipeline",
+            layout,
+            &shader,
+            "vs_bounds",
+            "fs_wireframe",
+            &color_targets,
+            false,
+            depth_format,
+            wgpu::CompareFunction::Always,
+            sample_count,
+            wgpu::PrimitiveTopology::LineList,
+            wgpu::PolygonMode::Fill,
+            &options,
+        );
+        // drawn first and behind everything, so it neither tests nor writes depth. Its single triangle
+        // covers the viewport whatever the winding, so it's never culled.
+        let grid = create_shard_pipeline(
+            device,
+            "Grid pipeline",
+            layout,
+            &shader,
+            "vs_grid",
+            "fs_grid",
+            &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            false,
+            depth_format,
+            wgpu::CompareFunction::Always,
+            sample_count,
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::PolygonMode::Fill,
+            &PipelineOptions { cull_mode: None, ..options },
+        );
+        // the instanced path mirrors the render pipeline above
+        let instanced = create_shard_pipeline(
+            device,
+            "Instanced render pipeline",
+            instanced_layout,
+            &instanced_shader,
+            "vs_instanced",
+            "fs_instanced",
+            &color_targets,
+            depth_writes,
+            depth_format,
+            wgpu::CompareFunction::GreaterEqual,
+            sample_count,
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::PolygonMode::Fill,
+            &options,
+        );
+        let sprite = create_shard_pipeline(
+            device,
+            "Sprite pipeline",
+            sprite_layout,
+            &sprite_shader,
+            "vs_sprite",
+            "fs_sprite",
+            &color_targets,
+            depth_writes,
+            depth_format,
+            wgpu::CompareFunction::GreaterEqual,
+            sample_count,
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::PolygonMode::Fill,
+            &options,
+        );
+        Self {
+            render,
+            wireframe,
+            bounds,
+            grid,
+            instanced,
+            sprite,
+            options,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_shard_pipeline(
+    device: &DeviceHandle,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    vertex_entry_point: &str,
+    fragment_entry_point: &str,
+    targets: &[Option<wgpu::ColorTargetState>],
+    depth_write_enabled: bool,
+    depth_format: wgpu::TextureFormat,
+    depth_compare: wgpu::CompareFunction,
+    sample_count: u32,
+    topology: wgpu::PrimitiveTopology,
+    polygon_mode: wgpu::PolygonMode,
+    options: &PipelineOptions,
+) -> wgpu::RenderPipeline {
+    device
+        .device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: vertex_entry_point,
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: fragment_entry_point,
+                targets,
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology,
+                strip_index_format: None,
+                front_face: options.front_face,
+                cull_mode: options.cull_mode,
+                polygon_mode,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled,
+                depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        })
+}
+
+/// The shard and segment offsets after an object drawing `frame` from the given offsets.
+/// A wrapped offset would draw garbage, so an oversized scene fails instead.
+fn next_frame_offsets(shard_offset: i32, segment_offset: i32, frame: &FrameInfo) -> Result<(i32, i32)> {
+    let max_shard_extent = RenderEngine::<SimpleLoader>::MAX_SHARD_EXTENT;
+    let next_shard_offset = i32::try_from(frame.shard_size)
+        .ok()
+        .and_then(|s| shard_offset.checked_add(s))
+        .filter(|&s| s as u32 <= max_shard_extent)
+        .ok_or(FightishError::BufferOverflow(format!("Cannot render: scene has more than {} shards.", max_shard_extent)))?;
+    let next_segment_offset = i32::try_from(frame.segment_size)
+        .ok()
+        .and_then(|s| segment_offset.checked_add(s))
+        .ok_or(FightishError::BufferOverflow(format!("Cannot render: scene has more than {} segments.", i32::MAX)))?;
+    Ok((next_shard_offset, next_segment_offset))
+}
+
+/// The clip offset of each object, given its layer and clip size. Each object gets clip depths above those
+/// of every object in a lower layer, or earlier in the same layer.
+/// Consecutive draws of one kind, in the order the scene is drawn.
+enum DrawRun {
+    /// Indices into the draw ranges of the objects drawn through the compute pass.
+    Objects(Range<usize>),
+    /// Indices into the scene's sprites.
+    Sprites(Range<usize>),
+}
+
+impl DrawRun {
+    /// Adds the run to the end of the runs, merging it with the last one when they're of one kind.
+    fn push(runs: &mut Vec<DrawRun>, run: DrawRun) {
+        match (runs.last_mut(), run) {
+            (Some(DrawRun::Objects(last)), DrawRun::Objects(next)) if last.end == next.start => last.end = next.end,
+            (Some(DrawRun::Sprites(last)), DrawRun::Sprites(next)) if last.end == next.start => last.end = next.end,
+            (_, run) => runs.push(run),
+        }
+    }
+}
+
+fn layered_clip_offsets(objects: impl Iterator<Item = (f32, u32)>) -> Result<Vec<u32>> {
+    let objects: Vec<(f32, u32)> = objects.collect();
+    let mut order: Vec<usize> = (0..objects.len()).collect();
+    // stable, so the scene order is kept within a layer
+    order.sort_by(|&a, &b| objects[a].0.total_cmp(&objects[b].0));
+    let mut offsets = vec![0; objects.len()];
+    let mut clip_offset = 0u32;
+    for i in order {
+        offsets[i] = clip_offset;
+        clip_offset = clip_offset
+            .checked_add(objects[i].1)
+            .ok_or(FightishError::BufferOverflow("Scene clip depths overflow.".into()))?;
+    }
+    if clip_offset > MAX_CLIP_DEPTH {
+        return Err(FightishError::BufferOverflow(format!(
+            "Scene clip depth {} exceeds the limit of {}.",
+            clip_offset,
+            MAX_CLIP_DEPTH,
+        )));
+    }
+    Ok(offsets)
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// How the engine's buffers grow when a scene doesn't fit them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BufferGrowth {
+    /// What the capacity is multiplied by until the scene fits, 2 by default. Factors of 1 or less
+    /// grow buffers to exactly the needed size, which saves memory but reallocates on every bigger scene.
+    pub factor: f32,
+    /// The most bytes any one buffer may take, beyond which rendering fails instead of allocating.
+    /// Unbounded by default, apart from the device's limits.
+    pub max_capacity: Option<u64>,
+}
+
+impl Default for BufferGrowth {
+    fn default() -> Self {
+        Self { factor: 2.0, max_capacity: None }
+    }
+}
+
+/// A buffer of `T` elements which grows by the growth factor when too small,
+/// and shrinks back once it has stayed below a quarter full for a while.
+#[derive(Debug)]
+struct GrowableBuffer<T: LayoutEnum> {
+    ty: T,
+    label: &'static str,
+    capacity: u64,
+    growth: BufferGrowth,
+    buffer: wgpu::Buffer,
+    underused: u32, // consecutive reserves below a quarter of the capacity
+    extra_usage: wgpu::BufferUsages, // on top of the layout's usage
+    stale: bool, // the buffer lacks the extra usage, and is recreated on the next reserve
+}
+
+impl<T: LayoutEnum> GrowableBuffer<T> {
+    /// Reserves in a row below a quarter of the capacity before shrinking.
+    const SHRINK_AFTER: u32 = 300;
+
+    fn new(device: &DeviceHandle, ty: T, label: &'static str) -> Self {
+        Self {
+            buffer: device.create_buffer_with_layout_enum(&ty, 1),
+            ty,
+            label,
+            capacity: 1,
+            growth: BufferGrowth::default(),
+            underused: 0,
+            extra_usage: wgpu::BufferUsages::empty(),
+            stale: false,
+        }
+    }
+
+    fn set_extra_usage(&mut self, usage: wgpu::BufferUsages) {
+        self.stale |= usage != self.extra_usage;
+        self.extra_usage = usage;
+    }
+
+    fn create(&self, device: &DeviceHandle) -> wgpu::Buffer {
+        let mut descriptor = self.ty.buffer_descriptor(self.capacity);
+        descriptor.usage |= self.extra_usage;
+        device.device.create_buffer(&descriptor)
+    }
+
+    /// Copies the first `len` elements into a readback buffer and maps it, blocking until the GPU is done.
+    fn read(&self, device: &DeviceHandle, len: u64) -> Result<Vec<u8>> {
+        if !self.buffer.usage().contains(wgpu::BufferUsages::COPY_SRC) {
+            return Err(FightishError::DebugReadbackOff(self.label));
+        }
+        let size = self.ty.size() * len;
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        let readback = device.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug readback buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Debug readback encoder"),
+            });
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &readback, 0, size);
+        device.queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        readback
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |r| { let _ = sender.send(r); });
+        device.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().map_err(anyhow::Error::from)??;
+        let bytes = readback.slice(..).get_mapped_range().to_vec();
+        readback.unmap();
+        Ok(bytes)
+    }
+
+    fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    fn bytes(&self) -> u64 {
+        self.ty.size() * self.capacity
+    }
+
+    /// Makes room for `len` elements, returning whether the buffer was recreated,
+    /// in which case bind groups using it must be rebuilt.
+    /// Fails if `len` elements would exceed the growth's maximum capacity.
+    fn reserve(&mut self, device: &DeviceHandle, len: u64) -> Result<bool> {
+        let old_capacity = self.capacity;
+        let max_capacity = self.growth.max_capacity.map_or(u64::MAX, |bytes| (bytes / self.ty.size()).max(1));
+        if len > max_capacity {
+            return Err(FightishError::BufferOverflow(format!(
+                "{} requested {} exceeds the maximum capacity {}.",
+                self.label,
+                len,
+                max_capacity,
+            )));
+        }
+        if len > self.capacity {
+            self.underused = 0;
+            while self.capacity < len {
+                let grown = (self.capacity as f64 * self.growth.factor as f64).ceil() as u64;
+                self.capacity = if grown > self.capacity { grown } else { len };
+            }
+            // larger factors could overshoot what the device can bind, though the scene itself fits
+            let device_capacity = (max_binding_size(device) / self.ty.size()).max(len);
+            self.capacity = self.capacity.min(max_capacity).min(device_capacity);
+            info!(
+                "{} requested {} exceeds capacity {}, resizing buffer to capacity {}.",
+                self.label,
+                len,
+                old_capacity,
+                self.capacity,
+            );
+        } else if len * 4 < self.capacity {
+            self.underused += 1;
+            if self.underused >= Self::SHRINK_AFTER {
+                self.underused = 0;
+                // keep headroom, so usage has to double before growing again
+                self.capacity = (len.max(1) * 2).next_power_of_two().min(max_capacity);
+                info!(
+                    "{} stayed below {} of capacity {}, shrinking buffer to capacity {}.",
+                    self.label,
+                    old_capacity / 4,
+                    old_capacity,
+                    self.capacity,
+                );
+            } else if !self.stale {
+                return Ok(false);
+            }
+        } else if !self.stale {
+            self.underused = 0;
+            return Ok(false);
+        }
+        self.stale = false;
+        self.buffer.destroy();
+        self.buffer = self.create(device);
+        Ok(true)
+    }
+}
+
+/// Whether a local bounding box, transformed to clip space by `clip_local_tf`, lies entirely outside
+/// the clip square grown by `margin` on each side.
+fn outside_clip(clip_local_tf: &cgmath::Matrix4<f32>, bb: &[f32; 4], margin: [f32; 2]) -> bool {
+    let behind = [(bb[0], bb[1]), (bb[0], bb[3]), (bb[2], bb[1]), (bb[2], bb[3])]
+        .into_iter()
+        .any(|(x, y)| (clip_local_tf * cgmath::vec4(x, y, 0.0, 1.0)).w <= 0.0);
+    // past the viewer the projection wraps around, so the box says nothing
+    if behind { return false; }
+    let [x0, y0, x1, y1] = world_bounds(clip_local_tf, bb);
+    x0 > 1.0 + margin[0] || x1 < -1.0 - margin[0] || y0 > 1.0 + margin[1] || y1 < -1.0 - margin[1]
+}
+
+/// Occlusion queries used to read back per-object visible sample counts.
+/// Results are mapped asynchronously and picked up at the start of a later frame.
+#[derive(Debug)]
+struct VisibilityQueries {
+    capacity: u32,
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    in_flight: Option<Vec<usize>>, // scene object index of each query being read back
+    mapped: Arc<AtomicU8>, // a MAP_* state, set by the readback's callback
+    last: Vec<(usize, u32)>,
+}
+
+// where the mapping of a query readback buffer stands
+const MAP_PENDING: u8 = 0;
+const MAP_DONE: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+/// Maps a query readback buffer for reading, recording the outcome in `mapped` as a `MAP_*` state.
+fn map_readback(slice: wgpu::BufferSlice, mapped: &Arc<AtomicU8>, what: &'static str) {
+    let mapped = mapped.clone();
+    slice.map_async(wgpu::MapMode::Read, move |r| {
+        let state = if let Err(e) = r {
+            warn!("Could not map {what} query results: {e}");
+            MAP_FAILED
+        } else {
+            MAP_DONE
+        };
+        mapped.store(state, Ordering::Release);
+    });
+}
+
+impl VisibilityQueries {
+    /// `None` if the device can't make the query set.
+    fn new(device: &DeviceHandle, capacity: u32) -> Option<Self> {
+        let size = capacity as u64 * wgpu::QUERY_SIZE as u64;
+        device.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let queries = Self {
+            capacity,
+            query_set: device
+                .device
+                .create_query_set(&wgpu::QuerySetDescriptor {
+                    label: Some("Visibility query set"),
+                    ty: wgpu::QueryType::Occlusion,
+                    count: capacity,
+                }),
+            resolve_buffer: device
+                .device
+                .create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Visibility query resolve buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+            readback_buffer: device
+                .device
+                .create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Visibility query readback buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+            in_flight: None,
+            mapped: Arc::new(AtomicU8::new(MAP_PENDING)),
+            last: Vec::new(),
+        };
+        if let Some(e) = pollster::block_on(device.device.pop_error_scope()) {
+            warn!("Cannot create visibility queries: {e}");
+            return None;
+        }
+        Some(queries)
+    }
+
+    fn resolve(&self, encoder: &mut wgpu::CommandEncoder, count: u32) {
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            count as u64 * wgpu::QUERY_SIZE as u64,
+        );
+    }
+
+    fn map(&mut self, object_indices: Vec<usize>) {
+        let slice = self.readback_buffer.slice(..(object_indices.len() as u64 * wgpu::QUERY_SIZE as u64));
+        map_readback(slice, &self.mapped, "visibility");
+        self.in_flight = Some(object_indices);
+    }
+
+    fn collect(&mut self, device: &DeviceHandle) {
+        let Some(object_indices) = self.in_flight.as_ref() else { return };
+        device.device.poll(wgpu::Maintain::Poll);
+        match self.mapped.swap(MAP_PENDING, Ordering::Acquire) {
+            MAP_DONE => {}
+            // the buffer isn't mapped, so the next frame can use it again
+            MAP_FAILED => {
+                self.in_flight = None;
+                return;
+            }
+            _ => return,
+        }
+        let slice = self.readback_buffer.slice(..(object_indices.len() as u64 * wgpu::QUERY_SIZE as u64));
+        self.last = bytemuck::cast_slice::<u8, u64>(&slice.get_mapped_range())
+            .iter()
+            .zip(object_indices)
+            .map(|(&samples, &i)| (i, samples.min(u32::MAX as u64) as u32))
+            .collect();
+        self.readback_buffer.unmap();
+        self.in_flight = None;
+    }
+}
+
+/// A callback recording commands into the engine's encoder around a scene's render pass, given the color and depth
+/// views, see [`RenderEngine::set_pre_pass`] and [`RenderEngine::set_post_pass`].
+pub type PassHook = Box<dyn FnMut(&mut wgpu::CommandEncoder, &wgpu::TextureView, &wgpu::TextureView)>;
+
+#[derive(Default)]
+struct PassHooks {
+    pre: Option<PassHook>,
+    post: Option<PassHook>,
+}
+
+impl std::fmt::Debug for PassHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PassHooks")
+            .field("pre", &self.pre.is_some())
+            .field("post", &self.post.is_some())
+            .finish()
+    }
+}
+
+/// GPU time spent in each part of a frame, in nanoseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PassTimings {
+    /// The compute pass preparing the frame's shards and segments.
+    pub compute_ns: f64,
+    /// The render pass.
+    pub render_ns: f64,
+}
+
+/// Timestamp queries around the compute and render passes, read back like [`VisibilityQueries`].
+#[derive(Debug)]
+struct TimestampQueries {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    period: f32, // nanoseconds per timestamp tick
+    in_flight: bool,
+    mapped: Arc<AtomicU8>, // a MAP_* state, set by the readback's callback
+    last: Option<PassTimings>,
+    average: Option<PassTimings>,
+}
+
+impl TimestampQueries {
+    const COMPUTE_BEGIN: u32 = 0;
+    const COMPUTE_END: u32 = 1;
+    const RENDER_BEGIN: u32 = 2;
+    const RENDER_END: u32 = 3;
+    const COUNT: u32 = 4;
+    // weight of the newest frame in the average
+    const SMOOTHING: f64 = 0.1;
+
+    fn new(device: &DeviceHandle) -> Self {
+        let size = Self::COUNT as u64 * wgpu::QUERY_SIZE as u64;
+        Self {
+            query_set: device
+                .device
+                .create_query_set(&wgpu::QuerySetDescriptor {
+                    label: Some("Timestamp query set"),
+                    ty: wgpu::QueryType::Timestamp,
+                    count: Self::COUNT,
+                }),
+            resolve_buffer: device
+                .device
+                .create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Timestamp query resolve buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+            readback_buffer: device
+                .device
+                .create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Timestamp query readback buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+            period: device.queue.get_timestamp_period(),
+            in_flight: false,
+            mapped: Arc::new(AtomicU8::new(MAP_PENDING)),
+            last: None,
+            average: None,
+        }
+    }
+
+    fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..Self::COUNT, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            Self::COUNT as u64 * wgpu::QUERY_SIZE as u64,
+        );
+    }
+
+    fn map(&mut self) {
+        map_readback(self.readback_buffer.slice(..), &self.mapped, "timestamp");
+        self.in_flight = true;
+    }
+
+    fn collect(&mut self, device: &DeviceHandle) {
+        if !self.in_flight { return; }
+        device.device.poll(wgpu::Maintain::Poll);
+        match self.mapped.swap(MAP_PENDING, Ordering::Acquire) {
+            MAP_DONE => {}
+            MAP_FAILED => {
+                self.in_flight = false;
+                return;
+            }
+            _ => return,
+        }
+        let ticks: [u64; Self::COUNT as usize] = bytemuck::pod_read_unaligned(
+            &self.readback_buffer.slice(..).get_mapped_range()
+        );
+        self.readback_buffer.unmap();
+        self.in_flight = false;
+
+        let ns = |begin: u32, end: u32| {
+            ticks[end as usize].wrapping_sub(ticks[begin as usize]) as f64 * self.period as f64
+        };
+        let timings = PassTimings {
+            compute_ns: ns(Self::COMPUTE_BEGIN, Self::COMPUTE_END),
+            render_ns: ns(Self::RENDER_BEGIN, Self::RENDER_END),
+        };
+        self.last = Some(timings);
+        self.average = Some(match self.average {
+            Some(a) => PassTimings {
+                compute_ns: a.compute_ns + (timings.compute_ns - a.compute_ns) * Self::SMOOTHING,
+                render_ns: a.render_ns + (timings.render_ns - a.render_ns) * Self::SMOOTHING,
+            },
+            None => timings,
+        });
+    }
+}
+
+/// Provides the engine's depth buffer and, when multisampling, a multisampled color buffer.
+#[derive(Debug)]
+pub struct RenderDongle {
+    depth_format: wgpu::TextureFormat,
+    sample_count: u32,
+    color_format: wgpu::TextureFormat, // set by the target in `configure`
+}
+
+impl RenderDongle {
+    /// The view holding the depth buffer.
+    pub const DEPTH_VIEW_INDEX: usize = 0;
+    /// The view holding the multisampled color buffer, if multisampling.
+    pub const COLOR_VIEW_INDEX: usize = 1;
+
+    /// A dongle with a `depth_format` depth buffer, which must match the engine's depth format.
+    /// Fails if `depth_format` has no depth aspect.
+    pub fn new(depth_format: wgpu::TextureFormat) -> Result<Self> {
+        if !depth_format.has_depth_aspect() {
+            return Err(FightishError::InvalidTarget(format!("{:?} is not a depth format.", depth_format)));
+        }
+        Ok(Self {
+            depth_format,
+            sample_count: 1,
+            color_format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        })
+    }
+
+    /// Makes the dongle multisampled. Counts the device doesn't support fall back to 1.
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count.max(1);
+        self
+    }
+
+    pub fn depth_format(&self) -> wgpu::TextureFormat {
+        self.depth_format
+    }
+
+    /// The sample count in use, after any fallback once the target was created.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+}
+
+impl TargetTextureDongle for RenderDongle {
+    fn configure(&mut self, device: &DeviceHandle, format: wgpu::TextureFormat) {
+        self.color_format = format;
+        let sample_count = device.supported_sample_count(&[format, self.depth_format], self.sample_count);
+        if sample_count != self.sample_count {
+            warn!("Sample count {} unsupported, falling back to {}.", self.sample_count, sample_count);
+            self.sample_count = sample_count;
+        }
+    }
+
+    fn num_textures(&self) -> usize {
+        if self.sample_count > 1 { 2 } else { 1 }
+    }
+
+    fn texture_desc(&self, index: usize, width: u32, height: u32) -> wgpu::TextureDescriptor<'_> {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let (label, format) = match index {
+            Self::DEPTH_VIEW_INDEX => ("Depth buffer", self.depth_format),
+            _ => ("Multisampled color buffer", self.color_format),
+        };
+        wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: self.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        }
+    }
+
+    fn attachment_view(&self, name: &str) -> Option<usize> {
+        match name {
+            DEPTH_ATTACHMENT => Some(Self::DEPTH_VIEW_INDEX),
+            COLOR_ATTACHMENT => (self.sample_count > 1).then_some(Self::COLOR_VIEW_INDEX),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{AttachmentDongle, AttachmentSpec};
+
+    /// Width and height of the offscreen test targets.
+    const SIZE: u32 = 16;
+
+    /// An offscreen target and an engine drawing `model` into it, with linear colors so pixels can be compared
+    /// exactly. `None` where the system has no adapter, in which case the rendering tests check nothing.
+    fn offscreen(model: Model) -> Option<(RenderContext, OffscreenTarget<RenderDongle>, RenderEngine)> {
+        let mut context = RenderContext::with_options(wgpu::Backends::all(), wgpu::PowerPreference::LowPower);
+        let dongle = RenderDongle::new(DEFAULT_DEPTH_FORMAT).unwrap();
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let target = match pollster::block_on(OffscreenTarget::create(&mut context, dongle, format, SIZE, SIZE)) {
+            Ok(target) => target,
+            Err(FightishError::NoCompatibleDevice) => {
+                eprintln!("No adapter, skipping the rendering test.");
+                return None;
+            }
+            Err(e) => panic!("{e}"),
+        };
+        let engine = RenderEngine::new(
+            &context,
+            target.device_id(),
+            target.format(),
+            target.dongle(),
+            DEFAULT_DEPTH_FORMAT,
+            1,
+            SimpleLoader::new(model).unwrap(),
+        ).unwrap();
+        Some((context, target, engine))
+    }
+
+    /// A scene showing world space from -1 to 1 over the whole target.
+    fn scene(objects: Vec<Object>) -> SceneData {
+        SceneData {
+            vp_x: 0,
+            vp_y: 0,
+            vp_width: SIZE,
+            vp_height: SIZE,
+            camera_tf: cgmath::Matrix4::identity(),
+            time: 0.0,
+            global_tint: [1.0; 4],
+            objects,
+        }
+    }
+
+    /// The RGBA pixel at column `x` and row `y` from the top.
+    fn pixel(pixels: &[u8], x: u32, y: u32) -> [u8; 4] {
+        let i = 4 * (y * SIZE + x) as usize;
+        pixels[i..i + 4].try_into().unwrap()
+    }
+
+    /// Asserts the pixels match to within a step, which blending may round either way.
+    fn assert_blended(pixel: [u8; 4], expected: [u8; 4]) {
+        assert!(pixel.iter().zip(expected).all(|(&a, b)| a.abs_diff(b) <= 1), "{:?} != {:?}", pixel, expected);
+    }
+
+    /// A model whose frame 0 is a square from -0.5 to 0.5 of `color`.
+    fn square(color: [f32; 4]) -> Model {
+        let mut model = Model::default();
+        model.push_rounded_rect([-0.5, -0.5], [0.5, 0.5], 0.0, color, 0).unwrap();
+        model
+    }
+
+    #[test]
+    fn back_face_culling_keeps_unmirrored_quads() {
+        let Some((context, target, mut engine)) = offscreen(square([1.0, 0.0, 0.0, 1.0])) else { return };
+        let device = target.device(&context);
+        engine.set_culling(device, Some(wgpu::Face::Back), wgpu::FrontFace::Ccw).unwrap();
+        let unmirrored = Object::new(cgmath::Matrix4::identity(), 0);
+        let pixels = engine.render_to_buffer(&context, &target, &scene(vec![unmirrored.clone()])).unwrap();
+        assert_eq!(pixel(&pixels, SIZE / 2, SIZE / 2), [255, 0, 0, 255]);
+        // drawn instanced
+        let copies = vec![unmirrored; RenderEngine::<SimpleLoader>::MIN_INSTANCES];
+        let pixels = engine.render_to_buffer(&context, &target, &scene(copies)).unwrap();
+        assert_eq!(pixel(&pixels, SIZE / 2, SIZE / 2), [255, 0, 0, 255]);
+
+        let mirrored = Object::new(cgmath::Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0), 0);
+        let pixels = engine.render_to_buffer(&context, &target, &scene(vec![mirrored])).unwrap();
+        assert_eq!(pixel(&pixels, SIZE / 2, SIZE / 2), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn instanced_objects_snap_like_the_others() {
+        let Some((context, target, mut engine)) = offscreen(square([1.0; 4])) else { return };
+        // the square's left edge lands at 4.4 pixels, before the center of pixel 4, and snaps past it to 5
+        engine.set_winding_epsilon(5.0);
+        let object = Object::new(cgmath::Matrix4::from_scale(0.9), 0);
+        let single = engine.render_to_buffer(&context, &target, &scene(vec![object.clone()])).unwrap();
+        let copies = vec![object; RenderEngine::<SimpleLoader>::MIN_INSTANCES];
+        let instanced = engine.render_to_buffer(&context, &target, &scene(copies)).unwrap();
+        assert_eq!(pixel(&single, 4, SIZE / 2), [0, 0, 0, 255]);
+        assert_eq!(pixel(&single, 5, SIZE / 2), [255; 4]);
+        assert_eq!(single, instanced);
+    }
+
+    #[test]
+    fn shared_edges_cover_seam_pixels_once() {
+        let half_green = [0.0, 1.0, 0.0, 0.5];
+        let mut model = Model::default();
+        model.push_rounded_rect([-0.5, -0.5], [0.03, 0.5], 0.0, half_green, 0).unwrap();
+        model.push_rounded_rect([0.03, -0.5], [0.5, 0.5], 0.0, half_green, 0).unwrap();
+        let Some((context, target, mut engine)) = offscreen(model) else { return };
+        engine.set_blend_mode(target.device(&context), BlendMode::Alpha).unwrap();
+        // the seam runs diagonally through the middle, off the pixel grid
+        let tf = cgmath::Matrix4::from_scale(1.2) * cgmath::Matrix4::from_angle_z(cgmath::Rad(0.3));
+        for epsilon in [0.0, 1.0 / 256.0] {
+            engine.set_winding_epsilon(epsilon);
+            let pixels = engine.render_to_buffer(&context, &target, &scene(vec![Object::new(tf, 0)])).unwrap();
+            // a pixel covered by both shards would be blended twice, one covered by neither would stay black
+            for x in 5..SIZE - 5 {
+                assert_blended(pixel(&pixels, x, SIZE / 2), [0, 128, 0, 255]);
+            }
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    let p = pixel(&pixels, x, y);
+                    assert!(p == [0, 0, 0, 255] || p[1].abs_diff(128) <= 1, "{:?} at {}, {}", p, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn antialiasing_fades_outer_edges_only() {
+        let mut model = Model::default();
+        model.push_rounded_rect([-0.5, -0.5], [0.0, 0.5], 0.0, [1.0; 4], 0).unwrap();
+        model.push_rounded_rect([0.0, -0.5], [0.5, 0.5], 0.0, [1.0; 4], 0).unwrap();
+        let Some((context, target, mut engine)) = offscreen(model) else { return };
+        engine.set_aa_width(target.device(&context), 4.0).unwrap();
+        let pixels = engine.render_to_buffer(&context, &target, &scene(vec![Object::new(cgmath::Matrix4::identity(), 0)]))
+            .unwrap();
+        // the shards meet between pixels 7 and 8, and neither fades there
+        for x in 6..10 {
+            assert_eq!(pixel(&pixels, x, SIZE / 2), [255; 4]);
+        }
+        // the outer edge lies between pixels 3 and 4, and fades on both sides, past the bounding box
+        let [outside, inside] = [3, 4].map(|x| pixel(&pixels, x, SIZE / 2)[0]);
+        assert!(0 < outside && outside < inside && inside < 255, "{} {}", outside, inside);
+    }
+
+    #[test]
+    fn pre_pass_draws_stay_under_the_scene() {
+        let Some((context, target, mut engine)) = offscreen(square([1.0, 0.0, 0.0, 1.0])) else { return };
+        engine.set_pre_pass(Some(Box::new(|encoder, color_view, _| {
+            drop(encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Test background"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: color_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::GREEN), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            }));
+        })));
+        let object = Object::new(cgmath::Matrix4::identity(), 0);
+        let pixels = engine.render_to_buffer(&context, &target, &scene(vec![object])).unwrap();
+        assert_eq!(pixel(&pixels, 0, 0), [0, 255, 0, 255]);
+        assert_eq!(pixel(&pixels, SIZE / 2, SIZE / 2), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn textures_render_with_attachments_of_their_size() {
+        let Some((context, target, mut engine)) = offscreen(square([1.0, 0.0, 0.0, 1.0])) else { return };
+        let device = target.device(&context);
+        let create = |desc: &wgpu::TextureDescriptor| device.device.create_texture(desc);
+        let texture = create(&wgpu::TextureDescriptor {
+            label: Some("Test texture"),
+            size: wgpu::Extent3d { width: SIZE, height: SIZE, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let dongle = RenderDongle::new(DEFAULT_DEPTH_FORMAT).unwrap();
+        let scene = scene(vec![Object::new(cgmath::Matrix4::identity(), 0)]);
+        let small = [create(&dongle.texture_desc(RenderDongle::DEPTH_VIEW_INDEX, SIZE / 2, SIZE))];
+        assert!(engine.render_to_texture(device, &texture, &dongle, &small, &scene).is_err());
+        assert!(engine.render_to_texture(device, &texture, &dongle, &[], &scene).is_err());
+        let depth = [create(&dongle.texture_desc(RenderDongle::DEPTH_VIEW_INDEX, SIZE, SIZE))];
+        engine.render_to_texture(device, &texture, &dongle, &depth, &scene).unwrap();
+    }
+
+    #[test]
+    fn dongles_need_a_depth_format() {
+        assert!(RenderDongle::new(wgpu::TextureFormat::Rgba8Unorm).is_err());
+        assert!(RenderDongle::new(wgpu::TextureFormat::Depth32Float).is_ok());
+    }
+
+    #[test]
+    fn sprite_atlases_need_a_size_matching_their_pixels() {
+        assert!(SpriteAtlas::new(0, 2, Vec::new()).is_err());
+        assert!(SpriteAtlas::new(2, 2, vec![0; 15]).is_err());
+        assert!(SpriteAtlas::new(2, 2, vec![0; 17]).is_err());
+        assert!(SpriteAtlas::new(2, 2, vec![0; 16]).is_ok());
+    }
+
+    #[test]
+    fn sprite_uvs_map_the_atlas_top_to_the_quad_top() {
+        let Some((context, target, mut engine)) = offscreen(square([1.0; 4])) else { return };
+        // red, green on the top row, blue, white on the bottom one
+        let pixels = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255; 4]].concat();
+        engine.set_sprite_atlas(target.device(&context), &SpriteAtlas::new(2, 2, pixels).unwrap()).unwrap();
+        // the square scaled over the whole target, showing the whole atlas
+        let sprite = Object { sprite: Some([0.0, 0.0, 1.0, 1.0]), ..Object::new(cgmath::Matrix4::from_scale(2.0), 0) };
+        let pixels = engine.render_to_buffer(&context, &target, &scene(vec![sprite.clone()])).unwrap();
+        assert_eq!(pixel(&pixels, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, SIZE - 1, 0), [0, 255, 0, 255]);
+        assert_eq!(pixel(&pixels, 0, SIZE - 1), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, SIZE - 1, SIZE - 1), [255; 4]);
+
+        // nothing to span on an empty frame
+        let empty = Object { frame_index: 1, ..sprite };
+        let mut model = square([1.0; 4]);
+        model.push_frame();
+        engine.reload_model(target.device(&context), 0, model).unwrap();
+        let pixels = engine.render_to_buffer(&context, &target, &scene(vec![empty])).unwrap();
+        assert_eq!(pixel(&pixels, SIZE / 2, SIZE / 2), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn viewports_are_clipped_to_the_target() {
+        let Some((context, target, mut engine)) = offscreen(square([1.0; 4])) else { return };
+        let tinted = |tint| vec![Object { tint, ..Object::new(cgmath::Matrix4::identity(), 0) }];
+        let (main, corner, outside) = (tinted([1.0, 0.0, 0.0, 1.0]), tinted([0.0, 1.0, 0.0, 1.0]), tinted([0.0; 4]));
+        let (main, corner, outside) = (scene(main), scene(corner), scene(outside));
+        let scenes = [
+            (&main, Viewport { x: 0, y: 0, width: SIZE, height: SIZE }),
+            // half over the bottom right corner, so its square's top left quarter shows there
+            (&corner, Viewport { x: SIZE as i32 / 2, y: SIZE as i32 / 2, width: SIZE, height: SIZE }),
+            (&outside, Viewport { x: -(SIZE as i32), y: 0, width: SIZE, height: SIZE }),
+        ];
+        let views = (target.color_view(), target.texture_views());
+        engine.render_multi(target.device(&context), views.0, views.1, [SIZE, SIZE], &scenes).unwrap();
+        let pixels = target.read_pixels(&context).unwrap();
+        assert_eq!(pixel(&pixels, SIZE / 2 - 1, SIZE / 2 - 1), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, SIZE - 1, SIZE - 1), [0, 255, 0, 255]);
+        assert_eq!(pixel(&pixels, SIZE / 4, SIZE / 2), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn off_screen_objects_have_no_visible_samples() {
+        let Some((context, target, mut engine)) = offscreen(square([1.0; 4])) else { return };
+        let device = target.device(&context);
+        engine.set_visibility_queries(true);
+        let on_screen = Object::new(cgmath::Matrix4::identity(), 0);
+        let off_screen = Object::new(cgmath::Matrix4::from_translation(cgmath::vec3(5.0, 0.0, 0.0)), 0);
+        let scene = scene(vec![on_screen, off_screen]);
+        // each render collects the results of an earlier one, once they're mapped
+        for _ in 0..10 {
+            engine.render_to_buffer(&context, &target, &scene).unwrap();
+            device.device.poll(wgpu::Maintain::Wait);
+            if !engine.last_visibility().is_empty() { break; }
+        }
+        assert!(engine.visibility_queries());
+        let visibility = engine.last_visibility();
+        assert!(matches!(visibility[..], [(0, samples), (1, 0)] if samples > 0), "{:?}", visibility);
+    }
+
+    #[test]
+    fn frame_indices_out_of_range_do_not_panic() {
+        let Some((context, target, mut engine)) = offscreen(square([1.0, 0.0, 0.0, 1.0])) else { return };
+        let object = |frame_index| Object::new(cgmath::Matrix4::identity(), frame_index);
+        for frame_index in [1, -1, i32::MAX, i32::MIN] {
+            let scene = scene(vec![object(0), object(frame_index)]);
+            engine.set_frame_index_policy(FrameIndexPolicy::Error);
+            assert!(engine.render_to_buffer(&context, &target, &scene).is_err());
+            engine.set_frame_index_policy(FrameIndexPolicy::Skip);
+            let pixels = engine.render_to_buffer(&context, &target, &scene).unwrap();
+            assert_eq!(pixel(&pixels, SIZE / 2, SIZE / 2), [255, 0, 0, 255]);
+            engine.set_frame_index_policy(FrameIndexPolicy::Clamp);
+            assert!(engine.render_to_buffer(&context, &target, &scene).is_ok());
+        }
+    }
+
+    #[test]
+    fn stroke_joins_and_caps() {
+        use crate::model::{LineCap, LineJoin, StrokeStyle};
+        // a right angle turning left at (0.5, -0.5), so the outer corner is towards the bottom right
+        let points = [[-0.75, -0.5], [0.5, -0.5], [0.5, 0.75]];
+        let render = |join, cap| {
+            let mut model = Model::default();
+            let style = StrokeStyle { width: 0.48, join, cap, ..StrokeStyle::default() };
+            model.push_stroke(&points, style, [1.0; 4], 0).unwrap();
+            let (context, target, mut engine) = offscreen(model)?;
+            let scene = scene(vec![Object::new(cgmath::Matrix4::identity(), 0)]);
+            let pixels = engine.render_to_buffer(&context, &target, &scene).unwrap();
+            // the corner, then pixels 3/16 right and 1/16 or 3/16 below it, and one just past the start
+            Some([(12, 12), (13, 12), (13, 13), (1, 12)].map(|(x, y)| pixel(&pixels, x, y)[0] == 255))
+        };
+        let Some(miter) = render(LineJoin::Miter, LineCap::Butt) else { return };
+        assert_eq!(miter, [true, true, true, false]);
+        // within half the width of the corner, but not the farther pixel
+        assert_eq!(render(LineJoin::Round, LineCap::Square).unwrap(), [true, true, false, true]);
+        assert_eq!(render(LineJoin::Bevel, LineCap::Round).unwrap(), [true, false, false, true]);
+    }
+
+    #[test]
+    fn translucent_shards_blend_in_draw_order() {
+        let Some((context, target, mut engine)) = offscreen(square([1.0; 4])) else { return };
+        let device = target.device(&context);
+        engine.set_blend_mode(device, BlendMode::Alpha).unwrap();
+        let tinted = |tint, layer| Object { tint, layer, ..Object::new(cgmath::Matrix4::identity(), 0) };
+        let green = tinted([0.0, 1.0, 0.0, 0.5], 1.0);
+        let red = tinted([1.0, 0.0, 0.0, 1.0], 0.0);
+        // earlier in the scene but a layer up, so it's drawn after the red. Enough red copies to be drawn
+        // instanced without blending must still come before it.
+        for copies in [1, RenderEngine::<SimpleLoader>::MIN_INSTANCES] {
+            let mut objects = vec![green.clone()];
+            objects.extend(vec![red.clone(); copies]);
+            let pixels = engine.render_to_buffer(&context, &target, &scene(objects)).unwrap();
+            assert_blended(pixel(&pixels, SIZE / 2, SIZE / 2), [128, 128, 0, 255]);
+        }
+
+        // the later shard is below the earlier one, but blends over it since depth isn't written
+        let mut model = Model::default();
+        model.push_rounded_rect([-0.5, -0.5], [0.5, 0.5], 0.0, [1.0, 0.0, 0.0, 0.5], 1).unwrap();
+        model.push_rounded_rect([-0.5, -0.5], [0.5, 0.5], 0.0, [0.0, 1.0, 0.0, 0.5], 0).unwrap();
+        let (context, target, mut engine) = offscreen(model).unwrap();
+        engine.set_blend_mode(target.device(&context), BlendMode::Alpha).unwrap();
+        let object = Object::new(cgmath::Matrix4::identity(), 0);
+        let pixels = engine.render_to_buffer(&context, &target, &scene(vec![object])).unwrap();
+        assert_blended(pixel(&pixels, SIZE / 2, SIZE / 2), [64, 128, 0, 255]);
+    }
+
+    #[test]
+    fn premultiplied_blending_premultiplies_tints() {
+        let Some((context, target, mut engine)) = offscreen(square([1.0; 4])) else { return };
+        engine.set_blend_mode(target.device(&context), BlendMode::PremultipliedAlpha).unwrap();
+        let object = Object { tint: [1.0, 0.0, 0.0, 0.5], ..Object::new(cgmath::Matrix4::identity(), 0) };
+        let pixels = engine.render_to_buffer(&context, &target, &scene(vec![object])).unwrap();
+        assert_blended(pixel(&pixels, SIZE / 2, SIZE / 2), [128, 0, 0, 255]);
+    }
+
+    #[test]
+    fn singular_camera_transform_is_an_error() {
+        let singular = cgmath::Matrix4::from_nonuniform_scale(1.0, 0.0, 1.0);
+        assert!(clip_world_tf(&singular).is_err());
+        assert!(clip_world_tf(&cgmath::Matrix4::from_scale(f32::NAN)).is_err());
+        let tf = cgmath::Matrix4::from_translation(cgmath::vec3(1.0, 2.0, 0.0)) * cgmath::Matrix4::from_scale(2.0);
+        assert_eq!(clip_world_tf(&tf).unwrap() * tf, cgmath::Matrix4::identity());
+    }
+
+    /// Sums the extents of `frames` the way a scene does, returning the final offsets.
+    fn sum_extents(frames: &[FrameInfo]) -> Result<(i32, i32)> {
+        frames.iter().try_fold((0, 0), |(shards, segments), frame| next_frame_offsets(shards, segments, frame))
+    }
+
+    #[test]
+    fn frame_extents_overflow_without_wrapping() {
+        let frame = |shard_size, segment_size| FrameInfo { shard_size, segment_size, ..Default::default() };
+        let max_shard_extent = RenderEngine::<SimpleLoader>::MAX_SHARD_EXTENT;
+        let half = frame(max_shard_extent / 2, 1);
+        assert_eq!(sum_extents(&[half, half]).unwrap(), (max_shard_extent as i32 / 2 * 2, 2));
+        assert!(matches!(sum_extents(&[half, half, half]), Err(FightishError::BufferOverflow(_))));
+        assert!(matches!(sum_extents(&[frame(u32::MAX, 0)]), Err(FightishError::BufferOverflow(_))));
+        assert!(matches!(sum_extents(&[frame(1, u32::MAX)]), Err(FightishError::BufferOverflow(_))));
+        let segments = frame(0, i32::MAX as u32 - 1);
+        assert_eq!(sum_extents(&[segments, frame(0, 1)]).unwrap(), (0, i32::MAX));
+        assert!(matches!(sum_extents(&[segments, frame(0, 2)]), Err(FightishError::BufferOverflow(_))));
+    }
+
+    #[test]
+    fn layers_order_clip_offsets() {
+        let offsets = layered_clip_offsets([(1.0, 3), (0.0, 2), (1.0, 4), (-1.0, 1)].into_iter()).unwrap();
+        assert_eq!(offsets, vec![3, 1, 6, 0]);
+    }
+
+    #[test]
+    fn clip_offsets_fail_past_the_depth_limit() {
+        let half = MAX_CLIP_DEPTH / 2;
+        assert_eq!(layered_clip_offsets([(0.0, half), (0.0, half)].into_iter()).unwrap(), vec![0, half]);
+        assert!(layered_clip_offsets([(0.0, half), (0.0, half + 1)].into_iter()).is_err());
+        assert!(layered_clip_offsets([(0.0, MAX_CLIP_DEPTH)].into_iter()).is_ok());
+        assert!(layered_clip_offsets([(0.0, MAX_CLIP_DEPTH + 1)].into_iter()).is_err());
+        assert!(layered_clip_offsets([(0.0, u32::MAX), (1.0, 1)].into_iter()).is_err());
+    }
+
+    #[test]
+    fn frame_index_error_policy() {
+        assert_eq!(FrameIndexPolicy::Error.resolve(2, 5).unwrap(), Some(2));
+        assert!(FrameIndexPolicy::Error.resolve(5, 5).is_err());
+        assert!(FrameIndexPolicy::Error.resolve(-1, 5).is_err());
+        assert!(FrameIndexPolicy::Error.resolve(0, 0).is_err());
+    }
+
+    #[test]
+    fn frame_index_clamp_policy() {
+        assert_eq!(FrameIndexPolicy::Clamp.resolve(2, 5).unwrap(), Some(2));
+        assert_eq!(FrameIndexPolicy::Clamp.resolve(7, 5).unwrap(), Some(4));
+        assert_eq!(FrameIndexPolicy::Clamp.resolve(-3, 5).unwrap(), Some(0));
+        assert_eq!(FrameIndexPolicy::Clamp.resolve(0, 0).unwrap(), None);
+    }
+
+    #[test]
+    fn frame_index_wrap_policy() {
+        assert_eq!(FrameIndexPolicy::Wrap.resolve(2, 5).unwrap(), Some(2));
+        assert_eq!(FrameIndexPolicy::Wrap.resolve(7, 5).unwrap(), Some(2));
+        assert_eq!(FrameIndexPolicy::Wrap.resolve(-1, 5).unwrap(), Some(4));
+        assert_eq!(FrameIndexPolicy::Wrap.resolve(i32::MIN, 5).unwrap(), Some(2));
+        assert_eq!(FrameIndexPolicy::Wrap.resolve(3, 0).unwrap(), None);
+    }
+
+    #[test]
+    fn frame_index_skip_policy() {
+        assert_eq!(FrameIndexPolicy::Skip.resolve(2, 5).unwrap(), Some(2));
+        assert_eq!(FrameIndexPolicy::Skip.resolve(5, 5).unwrap(), None);
+        assert_eq!(FrameIndexPolicy::Skip.resolve(-1, 5).unwrap(), None);
+        assert_eq!(FrameIndexPolicy::Skip.resolve(0, 0).unwrap(), None);
+    }
+
+    #[test]
+    fn memory_estimate_adds_up_the_buffer_sizes() {
+        let model = square([1.0; 4]);
+        let [vertices, segments, shards, frames] =
+            [model.vertices.len(), model.segments.len(), model.shards.len(), model.frames.len()].map(|n| n as u64);
+        let Some((context, target, mut engine)) = offscreen(model) else { return };
+        engine.set_buffer_growth(BufferGrowth { factor: 1.0, max_capacity: None });
+        let objects = vec![Object::new(cgmath::Matrix4::identity(), 0); 3];
+        engine.render_to_buffer(&context, &target, &scene(objects)).unwrap();
+
+        let report = engine.gpu_memory_estimate();
+        assert_eq!(report.uniforms, 272);
+        // 96 bytes for each of the 3 objects, and the instance and sprite buffers at their initial single element
+        assert_eq!(report.scene, 3 * 96 + 160 + 128);
+        // 6 vertices of 48 bytes per drawn shard, 32 bytes per drawn segment
+        assert_eq!(report.frame, 3 * shards * 6 * 48 + 3 * segments * 32);
+        assert_eq!(report.model, vertices * 8 + segments * 16 + shards * 48 + frames * 16);
+        assert_eq!(report.queries, 0);
+        assert_eq!(report.total(), report.uniforms + report.scene + report.frame + report.model);
+    }
+
+    #[test]
+    fn uniforms_match_the_shader_layout() {
+        let Some((_context, _target, mut engine)) = offscreen(square([1.0; 4])) else { return };
+        engine.set_grid_spacing(0.5);
+        engine.set_draw_grid(true);
+        let scene_data = SceneData { time: 2.5, vp_width: 12, global_tint: [0.5; 4], ..scene(vec![]) };
+        let viewport = Viewport::from_scene(&scene_data);
+        let uniforms = engine.get_uniforms(&scene_data, &scene_data.camera_tf, &viewport).unwrap();
+        let bytes = bytemuck::bytes_of(&uniforms);
+        let f32_at = |offset: usize| f32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+        // offsets as WGSL lays out the struct, with vec4s and matrices 16 byte aligned
+        assert_eq!(bytes.len(), 272);
+        assert_eq!(f32_at(132), 2.5);
+        assert_eq!((f32_at(136), f32_at(140)), (12.0, SIZE as f32));
+        assert_eq!(f32_at(144), engine.aa_width);
+        assert_eq!(bytes[152..160], [0; 8]);
+        assert_eq!([160, 164, 168, 172].map(f32_at), [0.5; 4]);
+        assert_eq!(f32_at(256), 0.5);
+        assert_eq!(bytes[260..272], [0; 12]);
+    }
+
+    #[test]
+    fn warmed_up_engines_render_the_same() {
+        let Some((context, target, mut engine)) = offscreen(square([0.0, 1.0, 0.0, 1.0])) else { return };
+        let objects = vec![Object::new(cgmath::Matrix4::identity(), 0)];
+        let cold = engine.render_to_buffer(&context, &target, &scene(objects.clone())).unwrap();
+
+        let Some((context, target, mut engine)) = offscreen(square([0.0, 1.0, 0.0, 1.0])) else { return };
+        engine.warm_up(target.device(&context)).unwrap();
+        let warm = engine.render_to_buffer(&context, &target, &scene(objects)).unwrap();
+        assert_eq!(pixel(&warm, SIZE / 2, SIZE / 2), [0, 255, 0, 255]);
+        assert_eq!(pixel(&warm, 0, 0), [0, 0, 0, 255]);
+        assert!(warm == cold);
+    }
+
+    #[test]
+    fn models_from_bytes_render_like_models_from_vecs() {
+        let model = crate::model::check::model();
+        let bytes = model.to_bytes().unwrap();
+        let Some((context, target, mut from_vecs)) = offscreen(model) else { return };
+        let loader = SimpleLoader::from_bytes(target.device(&context), &bytes).unwrap();
+        let mut from_bytes = RenderEngine::new(
+            &context,
+            target.device_id(),
+            target.format(),
+            target.dongle(),
+            DEFAULT_DEPTH_FORMAT,
+            1,
+            loader,
+        ).unwrap();
+        let scene_data = scene(vec![Object::new(cgmath::Matrix4::from_angle_z(cgmath::Rad(0.3)), 0)]);
+        let expected = from_vecs.render_to_buffer(&context, &target, &scene_data).unwrap();
+        let pixels = from_bytes.render_to_buffer(&context, &target, &scene_data).unwrap();
+        // both shard colors show
+        assert!(pixels.chunks(4).any(|p| p == [255, 0, 0, 255]) && pixels.chunks(4).any(|p| p == [0, 0, 255, 255]));
+        assert!(pixels == expected);
+        assert_eq!(from_bytes.gpu_memory_estimate().model, from_vecs.gpu_memory_estimate().model);
+    }
+
+    #[test]
+    fn depth_attachments_can_come_after_other_views() {
+        let Some((mut context, reference, mut expected_engine)) = offscreen(square([1.0; 4])) else { return };
+        let dongle = AttachmentDongle::new()
+            .with_attachment(AttachmentSpec::new("glow", Some(wgpu::TextureFormat::Rgba8Unorm))).unwrap()
+            .with_attachment(AttachmentSpec::new(DEPTH_ATTACHMENT, Some(DEFAULT_DEPTH_FORMAT))).unwrap();
+        let format = *reference.format();
+        let target = pollster::block_on(OffscreenTarget::create(&mut context, dongle, format, SIZE, SIZE)).unwrap();
+        assert_eq!(target.dongle().attachment_view(DEPTH_ATTACHMENT), Some(1));
+        let mut engine = RenderEngine::new(
+            &context,
+            target.device_id(),
+            target.format(),
+            target.dongle(),
+            DEFAULT_DEPTH_FORMAT,
+            1,
+            SimpleLoader::new(square([1.0; 4])).unwrap(),
+        ).unwrap();
+
+        // the red square is listed first but lies in front, so only depth testing keeps it on top
+        let front = Object { tint: [1.0, 0.0, 0.0, 1.0], layer: 1.0, ..Object::new(cgmath::Matrix4::identity(), 0) };
+        let back = Object { tint: [0.0, 1.0, 0.0, 1.0], ..Object::new(cgmath::Matrix4::from_scale(1.5), 0) };
+        let scene_data = scene(vec![front, back]);
+        let pixels = engine.render_to_buffer(&context, &target, &scene_data).unwrap();
+        assert_eq!(pixel(&pixels, SIZE / 2, SIZE / 2), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 3, SIZE / 2), [0, 255, 0, 255]);
+        assert!(pixels == expected_engine.render_to_buffer(&context, &reference, &scene_data).unwrap());
+
+        // a depth attachment of a color format is rejected
+        let dongle = AttachmentDongle::new()
+            .with_attachment(AttachmentSpec::new(DEPTH_ATTACHMENT, Some(wgpu::TextureFormat::Rgba8Unorm))).unwrap();
+        let target = pollster::block_on(OffscreenTarget::create(&mut context, dongle, format, SIZE, SIZE)).unwrap();
+        let loader = SimpleLoader::new(square([1.0; 4])).unwrap();
+        let engine = RenderEngine::new(
+            &context, target.device_id(), target.format(), target.dongle(), DEFAULT_DEPTH_FORMAT, 1, loader,
+        );
+        assert!(engine.is_err());
     }
 }
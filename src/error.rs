@@ -0,0 +1,90 @@
+use crate::render::DeviceError;
+
+/// What went wrong in the crate's public functions, for matching on the failures callers can handle.
+/// Converts into [`anyhow::Error`] like any other error.
+#[derive(Debug, thiserror::Error)]
+pub enum FightishError {
+    /// No adapter suits the surface, or the system has none at all.
+    #[error("No compatible device.")]
+    NoCompatibleDevice,
+    /// A device couldn't be opened, or was lost after opening.
+    #[error(transparent)]
+    Device(#[from] DeviceError),
+    /// A window surface couldn't be created.
+    #[error("Cannot create surface: {0}")]
+    CreateSurface(#[from] wgpu::CreateSurfaceError),
+    /// The surface texture couldn't be acquired, e.g. because the surface was lost.
+    #[error("Cannot acquire surface texture: {0}")]
+    Surface(#[from] wgpu::SurfaceError),
+    /// A window or offscreen target had no area.
+    #[error("Cannot create zero size target.")]
+    ZeroSizeTarget,
+    /// A model's indices or ranges are out of bounds, or its data couldn't be parsed.
+    #[error("Invalid model: {0}")]
+    ModelValidation(String),
+    /// Reading a model or other data failed.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// Reading data back from the GPU failed.
+    #[error("Cannot read back from the GPU: {0}")]
+    Readback(#[from] wgpu::BufferAsyncError),
+    /// A scene needs more buffer space, clip depth or dispatches than the engine or device allows.
+    #[error("{0}")]
+    BufferOverflow(String),
+    /// A buffer couldn't be mapped for writing.
+    #[error("Cannot write the {0} buffer.")]
+    BufferWrite(&'static str),
+    /// A buffer was read back without [`crate::engine::RenderEngine::set_debug_readback`] on when it was written.
+    #[error("Cannot read back {0}: debug readback was off when it was written.")]
+    DebugReadbackOff(&'static str),
+    /// No model was added under the id.
+    #[error("Unknown model {0}.")]
+    UnknownModel(usize),
+    /// The model hasn't been uploaded to the device yet.
+    #[error("Model {0} is not loaded.")]
+    ModelNotLoaded(usize),
+    /// The model has no CPU side copy, e.g. because it was loaded straight from bytes.
+    #[error("Model {0} has no CPU copy.")]
+    NoCpuModel(usize),
+    /// A frame index outside the model's frames, under [`crate::FrameIndexPolicy::Error`].
+    #[error("Frame index {frame_index} out of range for {num_frames} frames.")]
+    FrameOutOfRange { frame_index: i32, num_frames: usize },
+    /// The object was removed from the scene.
+    #[error("Object removed.")]
+    UnknownObject,
+    /// An object is a sprite, but the engine has no sprite atlas.
+    #[error("Object {0} is a sprite, but there's no sprite atlas.")]
+    MissingSpriteAtlas(usize),
+    /// The app has no render engine, since it has no window or target yet.
+    #[error("No render engine yet.")]
+    MissingEngine,
+    /// The render target doesn't have a view the engine renders with.
+    #[error("Target has no {kind} view {index}.")]
+    MissingView { kind: &'static str, index: usize },
+    /// A target, texture or dongle doesn't suit the engine, e.g. because of its format or sample count.
+    #[error("{0}")]
+    InvalidTarget(String),
+    /// The camera transform can't be inverted, e.g. because its scale is 0.
+    #[error("Camera transform is not invertible.")]
+    SingularCamera,
+    /// A render or compute pipeline failed validation.
+    #[error("Cannot create pipeline: {0}")]
+    Pipeline(String),
+    /// An argument is out of range or otherwise unusable.
+    #[error("{0}")]
+    InvalidArgument(String),
+    /// An input recording couldn't be parsed.
+    #[error("{0}")]
+    InvalidRecording(String),
+    /// A session couldn't be saved or parsed.
+    #[error("{0}")]
+    Session(String),
+    /// Gamepad input couldn't be initialized.
+    #[error("Cannot initialize gamepad input: {0}")]
+    Gamepad(String),
+    /// Anything else, e.g. errors of other crates.
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+pub type Result<T, E = FightishError> = std::result::Result<T, E>;
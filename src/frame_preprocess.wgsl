@@ -4,6 +4,24 @@ struct Uniforms {
     clip_world_tf: mat4x4<f32>,
     @location(1)
     frag_clip_tf: mat4x4<f32>,
+    @location(2)
+    winding_epsilon: f32,
+    @location(3)
+    time: f32,
+    @location(4)
+    viewport_size: vec2<f32>,
+    @location(5)
+    aa_width: f32,
+    @location(6)
+    premultiplied: u32,
+    @location(7)
+    global_tint: vec4<f32>,
+    @location(8)
+    world_clip_tf: mat4x4<f32>,
+    @location(9)
+    grid_color: vec4<f32>,
+    @location(10)
+    grid_spacing: f32,
 }
 
 struct Object {
@@ -12,6 +30,7 @@ struct Object {
     clip_offset: u32,
     shard_offset: i32,
     segment_offset: i32,
+    tint: vec4<f32>,
 }
 
 struct ShardVertex {
@@ -25,7 +44,7 @@ struct FrameSegment {
     s: vec2<f32>,
     e: vec2<f32>,
     m: vec2<f32>,
-    flags: u32,
+    flags: u32, // 1 for straight lines, 2 for edges shared with another shard
 }
 
 struct Shard {
@@ -33,6 +52,7 @@ struct Shard {
     color: vec4<f32>,
     segment_range: vec2<i32>,
     clip_depth: u32,
+    anim_mode: u32,
 }
 
 struct Frame {
@@ -60,24 +80,29 @@ var<storage, read> model_frames: array<Frame>;
 @group(3) @binding(0)
 var<storage, read> objects: array<Object>; // maybe convert to a uniform buffer
 
-// stupidest possible algorithm...
-@compute @workgroup_size(1) fn main(
-    @builtin(global_invocation_id) id: vec3<u32>,
+// one workgroup per object, its invocations striding over the shards and segments.
+// WORKGROUP_SIZE is prepended by the engine.
+@compute @workgroup_size(WORKGROUP_SIZE) fn main(
+    @builtin(workgroup_id) group: vec3<u32>,
+    @builtin(local_invocation_index) lane: u32,
 ) {
-    let object = objects[id.x];
+    let object = objects[group.x];
     let frame = model_frames[object.frame_index];
-    for (var i = 0; i < frame.shard_range.y - frame.shard_range.x; i++) {
+    let stride = i32(WORKGROUP_SIZE);
+    for (var i = i32(lane); i < frame.shard_range.y - frame.shard_range.x; i += stride) {
         let shard = model_shards[i + frame.shard_range.x];
         let j = i + object.shard_offset;
-        frame_shards[6 * j + 0] = get_shard_vert(object, shard, frame, vec2(shard.bb.x, shard.bb.y));
-        frame_shards[6 * j + 1] = get_shard_vert(object, shard, frame, vec2(shard.bb.x, shard.bb.w));
-        frame_shards[6 * j + 2] = get_shard_vert(object, shard, frame, vec2(shard.bb.z, shard.bb.y));
-        frame_shards[6 * j + 3] = get_shard_vert(object, shard, frame, vec2(shard.bb.z, shard.bb.w));
-        frame_shards[6 * j + 4] = get_shard_vert(object, shard, frame, vec2(shard.bb.z, shard.bb.y));
-        frame_shards[6 * j + 5] = get_shard_vert(object, shard, frame, vec2(shard.bb.x, shard.bb.w));
+        // two counterclockwise triangles, so unmirrored quads face the front
+        let corners = quad_corners(object, shard);
+        frame_shards[6 * j + 0] = get_shard_vert(object, shard, frame, corners[0]);
+        frame_shards[6 * j + 1] = get_shard_vert(object, shard, frame, corners[1]);
+        frame_shards[6 * j + 2] = get_shard_vert(object, shard, frame, corners[2]);
+        frame_shards[6 * j + 3] = get_shard_vert(object, shard, frame, corners[3]);
+        frame_shards[6 * j + 4] = get_shard_vert(object, shard, frame, corners[2]);
+        frame_shards[6 * j + 5] = get_shard_vert(object, shard, frame, corners[1]);
     }
     let frag_tex_tf = uniforms.frag_clip_tf * uniforms.clip_world_tf * object.world_tex_tf;
-    for (var i = frame.segment_range.x; i < frame.segment_range.y; i++) {
+    for (var i = frame.segment_range.x + i32(lane); i < frame.segment_range.y; i += stride) {
         var model_segment = model_segments[i];
         var segment: FrameSegment;
         segment.s = get_xy(frag_tex_tf * vec4(model_vertex[model_segment.x], 0.0, 1.0));
@@ -85,17 +110,54 @@ var<storage, read> objects: array<Object>; // maybe convert to a uniform buffer
         segment.m = get_xy(frag_tex_tf * vec4(model_vertex[
             select(model_segment.z, model_segment.x, model_segment.z < 0)
         ], 0.0, 1.0));
-        segment.flags = select(0u, 1u, model_segment.z < 0);
+        segment.flags = select(0u, 1u, model_segment.z < 0) | select(0u, 2u, model_segment.w > 0);
         frame_segment[i - frame.segment_range.x + object.segment_offset] = segment;
     }
 }
 
-fn get_xy(v: vec4<f32>) -> vec2<f32> { return v.xy / v.w; }
+// the corners (x, y), (z, y), (x, w), (z, w) of the shard's bounding box in clip space. With antialiasing
+// they move out by its width, so the fade past the shard's edges isn't cut off at the box.
+fn quad_corners(object: Object, shard: Shard) -> array<vec4<f32>, 4> {
+    let tf = uniforms.clip_world_tf * object.world_tex_tf;
+    let c = array<vec4<f32>, 4>(
+        tf * vec4(shard.bb.xy, 0.0, 1.0),
+        tf * vec4(shard.bb.zy, 0.0, 1.0),
+        tf * vec4(shard.bb.xw, 0.0, 1.0),
+        tf * vec4(shard.bb.zw, 0.0, 1.0),
+    );
+    if uniforms.aa_width <= 0.0 { return c; }
+    return array<vec4<f32>, 4>(
+        grown_corner(c[0], c[1], c[2]),
+        grown_corner(c[1], c[0], c[3]),
+        grown_corner(c[2], c[3], c[0]),
+        grown_corner(c[3], c[2], c[1]),
+    );
+}
+
+// a quad corner moved away from its neighbours `a` and `b` by the antialiasing width, in pixels.
+fn grown_corner(corner: vec4<f32>, a: vec4<f32>, b: vec4<f32>) -> vec4<f32> {
+    // pixels per clip unit, y pointing down like fragment coordinates
+    let scale = uniforms.viewport_size * vec2(0.5, -0.5);
+    // corners behind the camera don't map to pixels
+    if min(corner.w, min(a.w, b.w)) <= 0.0 || scale.x <= 0.0 || scale.y >= 0.0 { return corner; }
+    let p = corner.xy / corner.w * scale;
+    let away = away_from(p, a.xy / a.w * scale) + away_from(p, b.xy / b.w * scale);
+    return vec4((p + away * uniforms.aa_width) / scale * corner.w, corner.zw);
+}
+
+fn away_from(p: vec2<f32>, q: vec2<f32>) -> vec2<f32> {
+    let d = p - q;
+    return select(vec2(0.0), normalize(d), dot(d, d) > 0.0);
+}
 
-fn get_shard_vert(object: Object, shard: Shard, frame: Frame, bb_vert: vec2<f32>) -> ShardVertex {
+fn get_shard_vert(object: Object, shard: Shard, frame: Frame, pos: vec4<f32>) -> ShardVertex {
     var out: ShardVertex;
-    out.pos = uniforms.clip_world_tf * object.world_tex_tf * vec4(bb_vert, 0.0, 1.0);
-    out.color = shard.color;
+    out.pos = pos;
+    // tints have straight alpha, so they're premultiplied to match premultiplied shard colors
+    let tint = object.tint * uniforms.global_tint;
+    let premultiplied_tint = vec4(tint.rgb * tint.a, tint.a);
+    out.color = animated_color(shard.color, shard.anim_mode, uniforms.time)
+        * select(tint, premultiplied_tint, uniforms.premultiplied != 0u);
     out.segment_range = shard.segment_range - frame.segment_range.x + object.segment_offset;
     out.clip_depth = shard.clip_depth + object.clip_offset;
     return out;
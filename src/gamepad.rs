@@ -0,0 +1,82 @@
+use crate::error::{FightishError, Result};
+use gilrs::{Axis, Button, Gilrs};
+
+/// Which gamepad controls drive the camera, and how fast.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputBindings {
+    pub pan_x: Axis,
+    pub pan_y: Axis,
+    pub zoom_in: Button,
+    pub zoom_out: Button,
+    pub rotate_ccw: Button,
+    pub rotate_cw: Button,
+    /// Stick deflections below this are ignored.
+    pub dead_zone: f32,
+    /// View heights per second at full deflection.
+    pub pan_speed: f32,
+    /// Natural log of the scale change per second at full trigger.
+    pub zoom_speed: f32,
+    /// Radians per second.
+    pub rotate_speed: f32,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        Self {
+            pan_x: Axis::LeftStickX,
+            pan_y: Axis::LeftStickY,
+            zoom_in: Button::RightTrigger2,
+            zoom_out: Button::LeftTrigger2,
+            rotate_ccw: Button::West,
+            rotate_cw: Button::East,
+            dead_zone: 0.15,
+            pan_speed: 1.0,
+            zoom_speed: 1.0,
+            rotate_speed: 2.0,
+        }
+    }
+}
+
+/// Camera motion rates requested by the gamepads, already scaled by the binding speeds.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GamepadMotion {
+    pub pan: [f32; 2],
+    pub zoom: f32,
+    pub rotate: f32,
+}
+
+/// Polls all connected gamepads through gilrs.
+#[derive(Debug)]
+pub struct GamepadInput {
+    gilrs: Gilrs,
+    pub bindings: InputBindings,
+}
+
+impl GamepadInput {
+    pub fn new(bindings: InputBindings) -> Result<Self> {
+        let gilrs = Gilrs::new().map_err(|e| FightishError::Gamepad(e.to_string()))?;
+        Ok(Self { gilrs, bindings })
+    }
+
+    /// Updates the gamepad states and returns the summed motion of all gamepads.
+    pub fn poll(&mut self) -> GamepadMotion {
+        // gilrs only updates the cached gamepad state while events are drained
+        while self.gilrs.next_event().is_some() {}
+
+        let b = &self.bindings;
+        let mut motion = GamepadMotion::default();
+        for (_, gamepad) in self.gilrs.gamepads() {
+            let axis = |a| {
+                let v = gamepad.value(a);
+                if v.abs() < b.dead_zone { 0.0 } else { v }
+            };
+            // analog triggers report how far they're pressed, other buttons 0 or 1
+            let button = |btn| gamepad.button_data(btn).map_or(0.0, |d| d.value());
+            motion.pan[0] += axis(b.pan_x) * b.pan_speed;
+            motion.pan[1] += axis(b.pan_y) * b.pan_speed;
+            motion.zoom += (button(b.zoom_in) - button(b.zoom_out)) * b.zoom_speed;
+            motion.rotate += (button(b.rotate_ccw) - button(b.rotate_cw)) * b.rotate_speed;
+        }
+        motion
+    }
+}
@@ -0,0 +1,159 @@
+use crate::error::Result;
+use crate::engine::{DEFAULT_DEPTH_FORMAT, RenderDongle, RenderEngine};
+use crate::model::{Model, SimpleLoader};
+use crate::render::{OffscreenTarget, RenderContext};
+use crate::scene::SceneData;
+
+/// Color format of headless captures, 8 bit sRGB RGBA.
+pub const CAPTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Converts tightly packed pixels of a color `format` the engine renders to into [`CAPTURE_FORMAT`].
+/// Plain unorm formats hold the linear colors the shader writes, so those are encoded to sRGB.
+pub(crate) fn to_capture_format(mut pixels: Vec<u8>, format: wgpu::TextureFormat) -> Result<Vec<u8>> {
+    use wgpu::TextureFormat as F;
+    let (bgra, linear) = match format {
+        F::Rgba8UnormSrgb => (false, false),
+        F::Bgra8UnormSrgb => (true, false),
+        F::Rgba8Unorm => (false, true),
+        F::Bgra8Unorm => (true, true),
+        _ => {
+            return Err(crate::FightishError::InvalidTarget(format!(
+                "Cannot convert {format:?} pixels to 8 bit sRGB RGBA."
+            )))
+        }
+    };
+    for pixel in pixels.chunks_exact_mut(4) {
+        if bgra {
+            pixel.swap(0, 2);
+        }
+        if linear {
+            for c in &mut pixel[..3] {
+                *c = crate::model::linear_to_srgb8(*c as f32 / 255.0);
+            }
+        }
+    }
+    Ok(pixels)
+}
+
+/// Renders each scene into a fresh `width` x `height` offscreen frame without a window,
+/// returning the frames as tightly packed RGBA8 pixels.
+/// Scenes should cover the whole frame, i.e. have viewport `0, 0, width, height`.
+pub fn capture_sequence(model: Model, scenes: &[SceneData], width: u32, height: u32) -> Result<Vec<Vec<u8>>> {
+    capture_with(RenderContext::new(), model, scenes, width, height)
+}
+
+/// [`capture_sequence`] on a device from `context`.
+fn capture_with(
+    mut context: RenderContext,
+    model: Model,
+    scenes: &[SceneData],
+    width: u32,
+    height: u32,
+) -> Result<Vec<Vec<u8>>> {
+    let target = pollster::block_on(
+        OffscreenTarget::create(&mut context, RenderDongle::new(DEFAULT_DEPTH_FORMAT)?, CAPTURE_FORMAT, width, height)
+    )?;
+    let mut engine = RenderEngine::new(
+        &context,
+        target.device_id(),
+        target.format(),
+        target.dongle(),
+        target.dongle().depth_format(),
+        target.dongle().sample_count(),
+        SimpleLoader::new(model)?,
+    )?;
+    scenes
+        .iter()
+        .map(|scene| engine.render_to_buffer(&context, &target, scene))
+        .collect()
+}
+
+/// Like [`capture_sequence`], but returning images.
+#[cfg(feature = "image")]
+pub fn render_sequence(model: Model, scenes: &[SceneData], width: u32, height: u32) -> Result<Vec<image::RgbaImage>> {
+    capture_sequence(model, scenes, width, height)?
+        .into_iter()
+        .map(|pixels| {
+            image::RgbaImage::from_raw(width, height, pixels)
+                .ok_or(crate::FightishError::InvalidTarget("Captured frame has the wrong size.".into()))
+        })
+        .collect()
+}
+
+/// Packs equally sized frames into a grid `columns` wide, left to right and top to bottom.
+/// Save the result with `RgbaImage::save` to get a PNG sprite sheet.
+#[cfg(feature = "image")]
+pub fn sprite_sheet(frames: &[image::RgbaImage], columns: u32) -> image::RgbaImage {
+    let columns = columns.max(1);
+    let (width, height) = frames.first().map(|f| f.dimensions()).unwrap_or((0, 0));
+    let rows = (frames.len() as u32).div_ceil(columns);
+    let mut sheet = image::RgbaImage::new(width * columns.min(frames.len() as u32), height * rows);
+    for (i, frame) in frames.iter().enumerate() {
+        let (x, y) = (i as u32 % columns, i as u32 / columns);
+        image::imageops::replace(&mut sheet, frame, (x * width) as i64, (y * height) as i64);
+    }
+    sheet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::check;
+    use crate::Object;
+
+    #[test]
+    fn sequences_capture_each_scene() {
+        // the check model turned further in each frame, and the first frame again
+        let scenes: Vec<SceneData> = [0.0, 0.5, 1.0, 0.0]
+            .map(|angle| SceneData {
+                vp_x: 0,
+                vp_y: 0,
+                vp_width: 32,
+                vp_height: 32,
+                camera_tf: cgmath::Matrix4::from_scale(1.5),
+                time: 0.0,
+                global_tint: [1.0; 4],
+                objects: vec![Object::new(cgmath::Matrix4::from_angle_z(cgmath::Rad(angle)), 0)],
+            })
+            .into();
+        let context = RenderContext::with_options(wgpu::Backends::all(), wgpu::PowerPreference::LowPower);
+        let frames = match capture_with(context, check::model(), &scenes, 32, 32) {
+            Ok(frames) => frames,
+            Err(crate::FightishError::NoCompatibleDevice) => {
+                eprintln!("No adapter, skipping the rendering test.");
+                return;
+            }
+            Err(e) => panic!("{e}"),
+        };
+        assert_eq!(frames.len(), 4);
+        assert!(frames.iter().all(|f| f.len() == 32 * 32 * 4));
+        assert!(frames[0] != frames[1] && frames[1] != frames[2] && frames[0] != frames[2]);
+        assert_eq!(frames[3], frames[0]);
+        // something of the model shows in every frame
+        assert!(frames.iter().all(|f| f.chunks(4).any(|p| p != [0, 0, 0, 255])));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn sprite_sheets_fill_rows_first() {
+        let frames: Vec<image::RgbaImage> = (0..3u8)
+            .map(|i| image::RgbaImage::from_pixel(2, 1, image::Rgba([i, 0, 0, 255])))
+            .collect();
+        let sheet = sprite_sheet(&frames, 2);
+        assert_eq!(sheet.dimensions(), (4, 2));
+        assert_eq!(sheet.get_pixel(3, 0)[0], 1);
+        assert_eq!(sheet.get_pixel(1, 1)[0], 2);
+        // the unused cell stays transparent
+        assert_eq!(sheet.get_pixel(2, 1)[3], 0);
+    }
+
+    #[test]
+    fn window_formats_convert_to_the_capture_format() {
+        let pixel = vec![10, 128, 255, 128];
+        assert_eq!(to_capture_format(pixel.clone(), CAPTURE_FORMAT).unwrap(), pixel);
+        assert_eq!(to_capture_format(pixel.clone(), wgpu::TextureFormat::Bgra8UnormSrgb).unwrap(), [255, 128, 10, 128]);
+        // linear half intensity is about 188 in sRGB, and alpha stays linear
+        assert_eq!(to_capture_format(pixel.clone(), wgpu::TextureFormat::Bgra8Unorm).unwrap(), [255, 188, 56, 128]);
+        assert!(to_capture_format(pixel, wgpu::TextureFormat::Rgba16Float).is_err());
+    }
+}
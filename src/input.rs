@@ -0,0 +1,262 @@
+use std::io::{BufRead, Write};
+use std::time::Duration;
+use crate::error::{FightishError, Result};
+use winit::keyboard::KeyCode;
+
+/// A logical camera control, decoupled from the key that triggered it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    ZoomIn,
+    ZoomOut,
+    PanUp,
+    PanLeft,
+    PanDown,
+    PanRight,
+    RotateCcw,
+    RotateCw,
+    /// Returns the camera to its initial view.
+    ResetCamera,
+}
+
+impl Action {
+    pub const ALL: [Action; 9] = [
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::PanUp,
+        Action::PanLeft,
+        Action::PanDown,
+        Action::PanRight,
+        Action::RotateCcw,
+        Action::RotateCw,
+        Action::ResetCamera,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::ZoomIn => "zoom_in",
+            Action::ZoomOut => "zoom_out",
+            Action::PanUp => "pan_up",
+            Action::PanLeft => "pan_left",
+            Action::PanDown => "pan_down",
+            Action::PanRight => "pan_right",
+            Action::RotateCcw => "rotate_ccw",
+            Action::RotateCw => "rotate_cw",
+            Action::ResetCamera => "reset_camera",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Self::ALL.into_iter().find(|a| a.name() == name)
+    }
+}
+
+/// Which physical key triggers each action. Keys are matched by position, so the default
+/// QWEASDZC layout, and R to reset, sit in the same place on non-QWERTY keyboards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBindings {
+    pub zoom_in: KeyCode,
+    pub zoom_out: KeyCode,
+    pub pan_up: KeyCode,
+    pub pan_left: KeyCode,
+    pub pan_down: KeyCode,
+    pub pan_right: KeyCode,
+    pub rotate_ccw: KeyCode,
+    pub rotate_cw: KeyCode,
+    pub reset_camera: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            zoom_in: KeyCode::KeyQ,
+            zoom_out: KeyCode::KeyE,
+            pan_up: KeyCode::KeyW,
+            pan_left: KeyCode::KeyA,
+            pan_down: KeyCode::KeyS,
+            pan_right: KeyCode::KeyD,
+            rotate_ccw: KeyCode::KeyZ,
+            rotate_cw: KeyCode::KeyC,
+            reset_camera: KeyCode::KeyR,
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn key(&self, action: Action) -> KeyCode {
+        match action {
+            Action::ZoomIn => self.zoom_in,
+            Action::ZoomOut => self.zoom_out,
+            Action::PanUp => self.pan_up,
+            Action::PanLeft => self.pan_left,
+            Action::PanDown => self.pan_down,
+            Action::PanRight => self.pan_right,
+            Action::RotateCcw => self.rotate_ccw,
+            Action::RotateCw => self.rotate_cw,
+            Action::ResetCamera => self.reset_camera,
+        }
+    }
+
+    /// The action bound to `key`, the first in [`Action::ALL`] order if several share it.
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        Action::ALL.into_iter().find(|&a| self.key(a) == key)
+    }
+}
+
+/// A jump of the camera from dragging or the mouse wheel, which moves it directly instead of over the update steps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraMove {
+    /// World units the camera position moves by, after zooming.
+    pub offset: [f32; 2],
+    /// Factor the camera scale is multiplied by, 1 for a drag.
+    pub zoom: f32,
+}
+
+/// The actions held during one rendered frame, the camera moves from the mouse, and the time since the previous frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct RecordedFrame {
+    pub dt: Duration,
+    pub actions: Vec<Action>,
+    /// Whether Shift was held, speeding up the actions.
+    pub fast: bool,
+    /// Whether Ctrl was held, slowing down the actions.
+    pub slow: bool,
+    /// Mouse moves of the camera since the previous frame, in order.
+    pub moves: Vec<CameraMove>,
+}
+
+/// A frame by frame record of input, which replays to the same sequence of camera states.
+///
+/// The text format is a version header followed by one line per frame,
+/// holding the frame time in nanoseconds and then the names of the actions held during the frame,
+/// `fast` or `slow` if Shift or Ctrl were held, and a `move=x,y,zoom` for each camera move from the mouse.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputRecording {
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl InputRecording {
+    // version 1 recorded key presses as discrete steps, which don't replay as held keys.
+    // versions 2 and 3 had no camera moves, and 2 no modifiers, so they read as version 4 without them.
+    const HEADER: &'static str = "fightish-input 4";
+    const OLD_HEADERS: [&'static str; 2] = ["fightish-input 2", "fightish-input 3"];
+    const FAST: &'static str = "fast";
+    const SLOW: &'static str = "slow";
+    const MOVE: &'static str = "move=";
+
+    pub fn write_to<W: Write>(&self, mut w: W) -> Result<()> {
+        writeln!(w, "{}", Self::HEADER)?;
+        for frame in &self.frames {
+            write!(w, "{}", frame.dt.as_nanos())?;
+            for action in &frame.actions {
+                write!(w, " {}", action.name())?;
+            }
+            if frame.fast {
+                write!(w, " {}", Self::FAST)?;
+            }
+            if frame.slow {
+                write!(w, " {}", Self::SLOW)?;
+            }
+            // floats print as the shortest text that parses back to the same value, so replays stay exact
+            for m in &frame.moves {
+                write!(w, " {}{},{},{}", Self::MOVE, m.offset[0], m.offset[1], m.zoom)?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: BufRead>(r: R) -> Result<Self> {
+        let mut lines = r.lines();
+        let header = lines.next().ok_or(FightishError::InvalidRecording("Empty input recording.".into()))??;
+        if header.trim() != Self::HEADER && !Self::OLD_HEADERS.contains(&header.trim()) {
+            return Err(FightishError::InvalidRecording(format!(
+                "Unsupported input recording header \"{}\".",
+                header.trim(),
+            )));
+        }
+        let mut frames = Vec::new();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            let mut words = line.split_whitespace();
+            let Some(dt) = words.next() else { continue };
+            let dt = dt
+                .parse::<u64>()
+                .map_err(|e| FightishError::InvalidRecording(format!("Frame {}: invalid frame time \"{}\": {}", i, dt, e)))?;
+            let mut frame = RecordedFrame { dt: Duration::from_nanos(dt), ..Default::default() };
+            for word in words {
+                match word {
+                    Self::FAST => frame.fast = true,
+                    Self::SLOW => frame.slow = true,
+                    _ if word.starts_with(Self::MOVE) => frame.moves.push(Self::parse_move(&word[Self::MOVE.len()..]).ok_or(
+                        FightishError::InvalidRecording(format!("Frame {}: invalid camera move \"{}\".", i, word)),
+                    )?),
+                    _ => frame.actions.push(
+                        Action::from_name(word).ok_or(FightishError::InvalidRecording(format!(
+                            "Frame {}: unknown action \"{}\".",
+                            i,
+                            word,
+                        )))?
+                    ),
+                }
+            }
+            frames.push(frame);
+        }
+        Ok(Self { frames })
+    }
+
+    fn parse_move(text: &str) -> Option<CameraMove> {
+        let mut numbers = text.split(',').map(|n| n.parse::<f32>().ok());
+        let (x, y, zoom) = (numbers.next()??, numbers.next()??, numbers.next()??);
+        numbers.next().is_none().then_some(CameraMove { offset: [x, y], zoom })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_2_recordings_read_without_modifiers() {
+        let text = "fightish-input 2\n16000000 zoom_in pan_left\n\n8000000\n";
+        let recording = InputRecording::read_from(text.as_bytes()).unwrap();
+        assert_eq!(recording.frames, vec![
+            RecordedFrame { dt: Duration::from_millis(16), actions: vec![Action::ZoomIn, Action::PanLeft], ..Default::default() },
+            RecordedFrame { dt: Duration::from_millis(8), ..Default::default() },
+        ]);
+    }
+
+    #[test]
+    fn version_3_recordings_read_without_moves() {
+        let text = "fightish-input 3\n16000000 pan_up fast\n";
+        let recording = InputRecording::read_from(text.as_bytes()).unwrap();
+        assert_eq!(recording.frames, vec![
+            RecordedFrame { dt: Duration::from_millis(16), actions: vec![Action::PanUp], fast: true, ..Default::default() },
+        ]);
+    }
+
+    #[test]
+    fn recordings_round_trip() {
+        let drag = CameraMove { offset: [0.1, -1.0 / 3.0], zoom: 1.0 };
+        let scroll = CameraMove { offset: [1e-7, 2.5e5], zoom: 0.9 };
+        let recording = InputRecording {
+            frames: vec![
+                RecordedFrame { dt: Duration::from_nanos(16_666_667), actions: vec![Action::RotateCw], fast: true, slow: false, moves: vec![] },
+                RecordedFrame { dt: Duration::from_millis(20), actions: vec![], fast: false, slow: true, moves: vec![drag, scroll] },
+                RecordedFrame { dt: Duration::ZERO, actions: Action::ALL.to_vec(), fast: true, slow: true, moves: vec![scroll] },
+            ],
+        };
+        let mut text = Vec::new();
+        recording.write_to(&mut text).unwrap();
+        assert!(text.starts_with(b"fightish-input 4\n"));
+        assert_eq!(InputRecording::read_from(text.as_slice()).unwrap(), recording);
+    }
+
+    #[test]
+    fn unknown_headers_and_actions_are_rejected() {
+        assert!(InputRecording::read_from("fightish-input 1\n".as_bytes()).is_err());
+        assert!(InputRecording::read_from("fightish-input 3\n100 jump\n".as_bytes()).is_err());
+        assert!(InputRecording::read_from("fightish-input 4\n100 move=1,2\n".as_bytes()).is_err());
+        assert!(InputRecording::read_from("fightish-input 4\n100 move=1,2,x\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+struct Uniforms {
+    // mat3x3's are EVIL
+    @location(0)
+    clip_world_tf: mat4x4<f32>,
+    @location(1)
+    frag_clip_tf: mat4x4<f32>,
+    @location(2)
+    winding_epsilon: f32,
+    @location(3)
+    time: f32,
+    @location(4)
+    viewport_size: vec2<f32>,
+    @location(5)
+    aa_width: f32,
+    @location(6)
+    premultiplied: u32,
+    @location(7)
+    global_tint: vec4<f32>,
+    @location(8)
+    world_clip_tf: mat4x4<f32>,
+    @location(9)
+    grid_color: vec4<f32>,
+    @location(10)
+    grid_spacing: f32,
+}
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+struct Shard {
+    bb: vec4<f32>,
+    color: vec4<f32>,
+    segment_range: vec2<i32>,
+    clip_depth: u32,
+    anim_mode: u32,
+}
+
+struct Frame {
+    shard_range: vec2<i32>,
+    segment_range: vec2<i32>,
+}
+
+struct Instance {
+    world_tex_tf: mat4x4<f32>,
+    frag_tex_tf: mat4x4<f32>,
+    frame_index: i32,
+    clip_offset: u32,
+    tint: vec4<f32>,
+}
+
+@group(1) @binding(0)
+var<storage, read> model_vertex: array<vec2<f32>>;
+@group(1) @binding(1)
+var<storage, read> model_segments: array<vec4<i32>>;
+@group(1) @binding(2)
+var<storage, read> model_shards: array<Shard>;
+@group(1) @binding(3)
+var<storage, read> model_frames: array<Frame>;
+
+@group(2) @binding(0)
+var<storage, read> instances: array<Instance>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) color: vec4<f32>,
+    @location(1) @interpolate(flat) segment_range: vec2<i32>,
+    @location(2) @interpolate(flat) instance: u32,
+};
+
+// draws the model's shards directly, instead of the copies the compute pass makes per object.
+@vertex
+fn vs_instanced(
+    @builtin(vertex_index) index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    let instance = instances[instance_index];
+    let frame = model_frames[instance.frame_index];
+    let shard = model_shards[frame.shard_range.x + i32(index / 6u)];
+    let bb = shard.bb;
+    // the same corners as the compute pass writes
+    var corners = array<vec2<f32>, 6>(bb.xy, bb.zy, bb.xw, bb.zw, bb.xw, bb.zy);
+    let pos = uniforms.clip_world_tf * instance.world_tex_tf * vec4(corners[index % 6u], 0.0, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = vec4(pos.xy / pos.w, clip_depth_z(shard.clip_depth + instance.clip_offset), 1.0);
+    // tints have straight alpha, so they're premultiplied to match premultiplied shard colors
+    let tint = instance.tint * uniforms.global_tint;
+    let premultiplied_tint = vec4(tint.rgb * tint.a, tint.a);
+    out.color = animated_color(shard.color, shard.anim_mode, uniforms.time)
+        * select(tint, premultiplied_tint, uniforms.premultiplied != 0u);
+    out.segment_range = shard.segment_range;
+    out.instance = instance_index;
+    return out;
+}
+
+// the engine only draws instanced without antialiasing, so there's no edge coverage here.
+@fragment
+fn fs_instanced(in: VertexOutput) -> @location(0) vec4<f32> {
+    if winding(in) == 0 { discard; }
+    return in.color;
+}
+
+// a model vertex in fragment coordinates, snapped like the compute pass snaps the segments it writes.
+fn frag_vertex(in: VertexOutput, index: i32) -> vec2<f32> {
+    return get_xy(instances[in.instance].frag_tex_tf * vec4(model_vertex[index], 0.0, 1.0));
+}
+
+// transforms the segments per fragment, which the compute pass does once per object.
+fn winding(in: VertexOutput) -> i32 {
+    let v0 = in.clip_position.xy / in.clip_position.w;
+    var winding: i32 = 0;
+
+    for (var segment_index: i32 = in.segment_range.x; segment_index < in.segment_range.y; segment_index++) {
+        let segment = model_segments[segment_index];
+        let s = frag_vertex(in, segment.x);
+        let e = frag_vertex(in, segment.y);
+        if (segment.z < 0) {
+            winding += winding_line(v0, s, e);
+        } else {
+            winding += winding_quad(v0, s, frag_vertex(in, segment.z), e);
+        }
+    }
+    return winding;
+}
@@ -1,54 +1,346 @@
-use anyhow::anyhow;
+use std::collections::HashSet;
 use std::sync::Arc;
-use winit::window::{Window, WindowId};
+use std::time::{Duration, Instant};
+use winit::window::{Window, WindowAttributes, WindowId};
 use winit::application::ApplicationHandler;
-use winit::event_loop::ActiveEventLoop;
-use winit::event::{KeyEvent, WindowEvent};
-use log::{info, warn};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop, EventLoopProxy};
+use winit::event::{KeyEvent, MouseButton, MouseScrollDelta, WindowEvent};
+use log::{error, info, warn};
 
 use cgmath::SquareMatrix;
 
-mod scene;
-mod render;
-mod engine;
-mod model;
+pub mod scene;
+pub mod error;
+/// Devices, and the window and offscreen targets the engine draws to.
+pub mod render;
+/// The renderer behind [`App`], for drawing from an existing event loop instead. Per window:
+///
+/// 1. Create a [`render::RenderContext`] once, a surface with [`render::RenderContext::create_surface`],
+///    a device for it with [`render::RenderContext::device`], and then a [`render::RenderTarget`]
+///    with [`render::RenderTarget::from_surface`] and an [`engine::RenderDongle`],
+///    or a [`render::AttachmentDongle`] for other attachments.
+/// 2. Create a [`engine::RenderEngine`] with the target's device, surface format and dongle.
+/// 3. On each redraw, [`render::RenderTarget::acquire`] the surface texture, [`engine::RenderEngine::render`]
+///    a [`SceneData`] into a view of it along with the target's texture views, and present it.
+/// 4. On each resize, call [`render::RenderTarget::resize`].
+pub mod engine;
+pub mod model;
 mod buffer_structs;
+mod input;
+mod stats;
+#[cfg(feature = "serde")]
+mod session;
+pub mod headless;
+#[cfg(feature = "gilrs")]
+mod gamepad;
 
-use scene::SceneData;
+pub use error::FightishError;
+pub use scene::{Animation, AnimationFrame, Object, ObjectHandle, ObjectList, SceneData, SceneIndex, Transform2D};
 use render::{
+    DeviceHandle,
+    DeviceId,
+    OffscreenTarget,
     RenderContext,
     RenderTarget,
     TargetData,
 };
 use engine::{RenderEngine, RenderDongle};
-#[derive(Debug)]
-struct AppState {
+use scene::world_bounds;
+pub use input::{Action, CameraMove, InputRecording, KeyBindings, RecordedFrame};
+pub use stats::FrameStats;
+#[cfg(feature = "gilrs")]
+pub use gamepad::{GamepadInput, GamepadMotion, InputBindings};
+pub use engine::{
+    BufferGrowth, DEFAULT_DEPTH_FORMAT, DEFAULT_WINDING_EPSILON, FrameIndexPolicy, MAX_CLIP_DEPTH, MemoryReport, PassHook,
+    PassTimings, SpriteAtlas,
+};
+/// The camera state showing all of `boxes`, `[min_x, min_y, max_x, max_y]` in the frame of a camera turned by
+/// `rot`, with `margin` of their size left on each side, for a view `half` the world extent at scale 1 across.
+/// The initial view if there are no boxes.
+fn fit_camera_state(boxes: impl Iterator<Item = [f32; 4]>, half: cgmath::Vector2<f32>, margin: f32, rot: f32) -> CameraState {
+    let bounds = boxes.reduce(|a, b| [a[0].min(b[0]), a[1].min(b[1]), a[2].max(b[2]), a[3].max(b[3])]);
+    let Some([x0, y0, x1, y1]) = bounds else { return CameraState::default() };
+    let padding = 1.0 + 2.0 * margin;
+    CameraState {
+        scale: ((x1 - x0) / (2.0 * half.x)).max((y1 - y0) / (2.0 * half.y)) * padding,
+        pos: [(x0 + x1) / 2.0, (y0 + y1) / 2.0],
+        rot,
+    }
+}
+
+/// The part of the state that changes smoothly over time, and is interpolated between update steps.
+#[derive(Debug, Clone, Copy)]
+struct Camera {
     scale: f32,
     pos: cgmath::Vector2<f32>,
     rot: f32,
+    time: f32, // seconds of frame time accumulated so far
 }
 
-impl AppState {
+impl Camera {
     fn new() -> Self {
         Self {
             scale: 1.0f32,
             pos: cgmath::Zero::zero(),
             rot: 0.0,
+            time: 0.0,
+        }
+    }
+
+    fn lerp(&self, other: &Camera, alpha: f32) -> Camera {
+        Camera {
+            scale: self.scale + (other.scale - self.scale) * alpha,
+            pos: self.pos + (other.pos - self.pos) * alpha,
+            rot: self.rot + (other.rot - self.rot) * alpha,
+            time: self.time + (other.time - self.time) * alpha,
+        }
+    }
+
+    /// The transform from clip coordinates to world coordinates, for a view `half` the visible
+    /// world extent at scale 1 across.
+    fn tf(&self, half: cgmath::Vector2<f32>) -> cgmath::Matrix4<f32> {
+        // world
+        cgmath::Matrix4::from_translation(
+            cgmath::Vector3::new(self.pos.x, self.pos.y, 0.0)
+        )
+        * // scaled, untranslated
+        cgmath::Matrix4::from_nonuniform_scale(
+            half.x * self.scale,
+            half.y * self.scale,
+            1f32,
+        ) // clip coords
+    }
+}
+
+/// Where the camera looks, e.g. to save a view and restore it later.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraState {
+    /// World units per clip unit, so larger values show more of the world.
+    pub scale: f32,
+    /// World position at the center of the view.
+    pub pos: [f32; 2],
+    /// Radians.
+    pub rot: f32,
+}
+
+impl Default for CameraState {
+    /// The initial view, centered on the origin at scale 1.
+    fn default() -> Self {
+        Self { scale: 1.0, pos: [0.0; 2], rot: 0.0 }
+    }
+}
+
+/// How the camera's view region, [`App::set_view_aspect`] wide for each unit high, maps to a viewport
+/// with a different aspect ratio. Without a view aspect the region takes the viewport's, and the modes agree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AspectMode {
+    /// The region fills the viewport, stretching the content.
+    Stretch,
+    /// The whole region is shown without stretching, leaving clear color margins on two sides.
+    Fit,
+    /// The region covers the viewport without stretching, cropping it on two sides.
+    #[default]
+    Fill,
+}
+
+/// When the app redraws its windows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ControlFlowMode {
+    /// Redraw continuously.
+    #[default]
+    Poll,
+    /// Redraw on input, and continuously only while something moves by itself, i.e. while keys are held,
+    /// an animation, input playback or gamepad is active. Sleeps otherwise, saving power when idle.
+    Wait,
+}
+
+#[derive(Debug)]
+struct AppState {
+    camera: Camera,
+    previous_camera: Camera, // camera before the last update step
+    step_alpha: f32, // fraction of an update step elapsed since the last one, for interpolation
+    objects: ObjectList,
+    counter_rotated: Option<ObjectHandle>, // the second demo object, which turns against the camera rotation
+    #[cfg(feature = "gilrs")]
+    motion: GamepadMotion, // gamepad motion applied in each update step of the current frame
+
+    dragging: bool,
+    cursor: Option<winit::dpi::PhysicalPosition<f64>>, // None while the cursor is outside the window
+    pointer_moves: Vec<CameraMove>, // camera moves from the mouse since the last frame, for recordings
+    camera_bounds: Option<(cgmath::Vector2<f32>, cgmath::Vector2<f32>)>, // world min and max corners
+    aspect_mode: AspectMode,
+    view_aspect: Option<f32>, // width over height of the view region, None to follow the viewport
+    key_bindings: KeyBindings,
+    held: HashSet<Action>, // actions whose keys are held down
+    modifiers: winit::keyboard::ModifiersState,
+    modifier_speeds: (f32, f32), // pan and zoom speed multipliers while Shift and Ctrl are held
+    scale_factor: f64, // physical pixels per logical pixel of the main window
+    global_tint: [f32; 4],
+    pan_grid: Option<f32>, // world units the shown camera position snaps to
+    scale_limits: (f32, f32), // min and max camera scale
+}
+
+impl AppState {
+    const DEFAULT_SCALE_LIMITS: (f32, f32) = (1.0e-3, 1.0e3);
+    const DEFAULT_MODIFIER_SPEEDS: (f32, f32) = (4.0, 0.25);
+    // logical pixel scroll distance counted as one wheel line
+    const PIXELS_PER_LINE: f64 = 50.0;
+    // rates of the held key controls, pan in view heights, zoom as the log of the scale change, per second
+    const KEY_PAN_SPEED: f32 = 0.5;
+    const KEY_ZOOM_SPEED: f32 = 1.5;
+    const KEY_ROTATE_SPEED: f32 = 1.5;
+
+    fn new() -> Self {
+        Self {
+            camera: Camera::new(),
+            previous_camera: Camera::new(),
+            step_alpha: 0.0,
+            objects: ObjectList::new(),
+            counter_rotated: None,
+            #[cfg(feature = "gilrs")]
+            motion: GamepadMotion::default(),
+
+            dragging: false,
+            cursor: None,
+            pointer_moves: Vec::new(),
+            camera_bounds: None,
+            aspect_mode: AspectMode::default(),
+            view_aspect: None,
+            key_bindings: KeyBindings::default(),
+            held: HashSet::new(),
+            modifiers: winit::keyboard::ModifiersState::empty(),
+            modifier_speeds: Self::DEFAULT_MODIFIER_SPEEDS,
+            scale_factor: 1.0,
+            global_tint: [1.0; 4],
+            pan_grid: None,
+            scale_limits: Self::DEFAULT_SCALE_LIMITS,
         }
     }
 
-    fn create_scene_data(&self, target_data: &TargetData) -> SceneData {
-        let camera_tf = // world
-            cgmath::Matrix4::from_translation(
-                cgmath::Vector3::new(self.pos.x, self.pos.y, 0.0)
-            )
-            * // scaled, untranslated
-            cgmath::Matrix4::from_nonuniform_scale(
-                target_data.vp_width as f32 / target_data.vp_height as f32 * self.scale,
-                self.scale,
-                1f32,
-            ); // clip coords
-        let object_tf = cgmath::Matrix4::from_angle_z(cgmath::Rad(self.rot));
+    fn camera_state(&self) -> CameraState {
+        CameraState {
+            scale: self.camera.scale,
+            pos: self.camera.pos.into(),
+            rot: self.camera.rot,
+        }
+    }
+
+    /// Moves the camera, keeping the time. Unlike a jump in the update steps, it isn't interpolated.
+    fn set_camera_state(&mut self, state: CameraState) {
+        self.set_camera(state);
+        self.previous_camera = self.camera;
+    }
+
+    fn set_camera(&mut self, state: CameraState) {
+        self.camera.scale = self.clamp_scale(state.scale);
+        self.camera.pos = state.pos.into();
+        self.camera.rot = state.rot;
+    }
+
+    /// Clamps a camera scale to the limits, keeping the current scale for NaN,
+    /// since near zero or huge scales make the camera transform singular.
+    fn clamp_scale(&self, scale: f32) -> f32 {
+        if scale.is_nan() { return self.camera.scale; }
+        scale.clamp(self.scale_limits.0, self.scale_limits.1)
+    }
+
+    /// Resets the camera and time, keeping the objects and camera bounds.
+    fn reset_view(&mut self) {
+        *self = Self {
+            objects: std::mem::take(&mut self.objects),
+            counter_rotated: self.counter_rotated,
+            camera_bounds: self.camera_bounds,
+            aspect_mode: self.aspect_mode,
+            view_aspect: self.view_aspect,
+            key_bindings: self.key_bindings.clone(),
+            scale_factor: self.scale_factor,
+            global_tint: self.global_tint,
+            pan_grid: self.pan_grid,
+            scale_limits: self.scale_limits,
+            modifier_speeds: self.modifier_speeds,
+            ..Self::new()
+        };
+    }
+
+    /// Moves the camera so the visible region stays inside the camera bounds,
+    /// or centers it on the bounds along an axis where the visible region is larger.
+    fn clamp_to_bounds(&mut self, target_data: &TargetData) {
+        let Some((min, max)) = self.camera_bounds else { return };
+        let (_, half) = self.view(target_data);
+        let half = half * self.camera.scale;
+        let clamp_axis = |pos: f32, min: f32, max: f32, half: f32| {
+            if max - min <= 2.0 * half {
+                (min + max) / 2.0
+            } else {
+                pos.clamp(min + half, max - half)
+            }
+        };
+        self.camera.pos.x = clamp_axis(self.camera.pos.x, min.x, max.x, half.x);
+        self.camera.pos.y = clamp_axis(self.camera.pos.y, min.y, max.y, half.y);
+    }
+
+    /// Advances the simulation by one fixed update step of `dt` seconds.
+    fn step(&mut self, dt: f32) {
+        self.previous_camera = self.camera;
+        self.apply_held(dt);
+        #[cfg(feature = "gilrs")]
+        {
+            let motion = self.motion;
+            self.apply_motion(&motion, dt);
+        }
+        self.camera.time += dt;
+    }
+
+    /// The viewport the scene is drawn to within the target's, and half the world extent it shows at scale 1.
+    /// Clip space spans -1 to 1 across the viewport, and is scaled by the half extent and the camera scale.
+    fn view(&self, target_data: &TargetData) -> (TargetData, cgmath::Vector2<f32>) {
+        let ratio = target_data.vp_width as f32 / target_data.vp_height.max(1) as f32;
+        let aspect = self.view_aspect.unwrap_or(ratio);
+        match self.aspect_mode {
+            AspectMode::Stretch => (*target_data, cgmath::Vector2::new(aspect, 1.0)),
+            AspectMode::Fit => {
+                let (width, height) = if ratio > aspect {
+                    ((target_data.vp_height as f32 * aspect).round() as u32, target_data.vp_height)
+                } else {
+                    (target_data.vp_width, (target_data.vp_width as f32 / aspect).round() as u32)
+                };
+                let viewport = TargetData {
+                    vp_x: target_data.vp_x + (target_data.vp_width - width) as i32 / 2,
+                    vp_y: target_data.vp_y + (target_data.vp_height - height) as i32 / 2,
+                    vp_width: width,
+                    vp_height: height,
+                };
+                (viewport, cgmath::Vector2::new(aspect, 1.0))
+            }
+            AspectMode::Fill => {
+                let half = if ratio > aspect {
+                    cgmath::Vector2::new(aspect, aspect / ratio)
+                } else {
+                    cgmath::Vector2::new(ratio, 1.0)
+                };
+                (*target_data, half)
+            }
+        }
+    }
+
+    /// The camera as currently shown, between the last two update steps.
+    fn displayed_camera(&self) -> Camera {
+        self.previous_camera.lerp(&self.camera, self.step_alpha)
+    }
+
+    /// The scene as seen by the app's camera, or by a window's own `view` at the same time.
+    fn create_scene_data(&self, target_data: &TargetData, view: Option<CameraState>) -> SceneData {
+        let mut camera = self.displayed_camera();
+        if let Some(view) = view {
+            camera = Camera { scale: view.scale, pos: view.pos.into(), rot: view.rot, ..camera };
+        }
+        // only the shown position snaps, so pans smaller than the grid still add up
+        if let Some(grid) = self.pan_grid {
+            camera.pos = camera.pos.map(|c| (c / grid).round() * grid);
+        }
+        let (target_data, half) = self.view(target_data);
+        let camera_tf = camera.tf(half);
         SceneData {
             vp_x: target_data.vp_x,
             vp_y: target_data.vp_y,
@@ -56,37 +348,170 @@ impl AppState {
             vp_height: target_data.vp_height,
 
             camera_tf,
+            time: camera.time,
+            global_tint: self.global_tint,
+
+            objects: self
+                .objects
+                .handles()
+                .zip(self.objects.iter())
+                .map(|(handle, o)| Object {
+                    world_local_tf: self.object_rotation(handle, camera.rot) * o.local_tf_at(camera.time),
+                    frame_index: o.frame_index_at(camera.time),
+                    ..o.clone()
+                })
+                .collect(),
+        }
+    }
 
-            objects: vec![
-                scene::Object{world_local_tf: object_tf, frame_index: 0},
-                scene::Object{world_local_tf: object_tf.invert().unwrap(), frame_index: 1},
-            ]
+    /// How a camera turned by `rot` radians rotates an object about the world origin, from world space into
+    /// the frame the camera position and extent are in.
+    fn object_rotation(&self, handle: ObjectHandle, rot: f32) -> cgmath::Matrix4<f32> {
+        let rotation = cgmath::Matrix4::from_angle_z(cgmath::Rad(rot));
+        if Some(handle) == self.counter_rotated {
+            rotation.invert().unwrap()
+        } else {
+            rotation
         }
     }
 
-    fn handle_input(&mut self, event: WindowEvent) {
+    /// Applies an input event. Keys only update the held actions, which move the camera in the update steps.
+    /// Dragging with the left mouse button pans the camera, and the wheel zooms it, right away as camera moves.
+    fn handle_input(&mut self, event: WindowEvent, target_data: &TargetData) {
         match event {
             WindowEvent::KeyboardInput {
                 event: KeyEvent {
                     physical_key: winit::keyboard::PhysicalKey::Code(keycode),
+                    state,
                     ..
                 },
                 ..
             } => {
-                match keycode {
-                    winit::keyboard::KeyCode::KeyQ => { self.scale *= 1.1 },
-                    winit::keyboard::KeyCode::KeyE => { self.scale *= 0.9 },
-                    winit::keyboard::KeyCode::KeyW => { self.pos.y += self.scale * 0.1 },
-                    winit::keyboard::KeyCode::KeyA => { self.pos.x -= self.scale * 0.1 },
-                    winit::keyboard::KeyCode::KeyS => { self.pos.y -= self.scale * 0.1 },
-                    winit::keyboard::KeyCode::KeyD => { self.pos.x += self.scale * 0.1 },
-                    winit::keyboard::KeyCode::KeyZ => { self.rot += 0.1 },
-                    winit::keyboard::KeyCode::KeyC => { self.rot -= 0.1 },
-                    _ => {}
+                let Some(action) = self.key_bindings.action(keycode) else { return };
+                if state.is_pressed() {
+                    self.held.insert(action);
+                } else {
+                    self.held.remove(&action);
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            // releases aren't delivered while unfocused, so nothing may stay held
+            WindowEvent::Focused(false) => {
+                self.held.clear();
+                self.modifiers = winit::keyboard::ModifiersState::empty();
+                self.dragging = false;
+            }
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                self.dragging = state.is_pressed();
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                // the first move after (re-)entering only sets the cursor, so a drag can't jump.
+                if let Some(last) = self.cursor.replace(position) {
+                    if self.dragging {
+                        self.pan_pixels(position.x - last.x, position.y - last.y, target_data);
+                    }
                 }
             }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y as f64,
+                    MouseScrollDelta::PixelDelta(p) => p.y / (Self::PIXELS_PER_LINE * self.scale_factor),
+                };
+                self.zoom_at_cursor(0.9f32.powf(lines as f32 * self.modifier_speed()), target_data);
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+            }
             _ => {}
         }
+        self.clamp_to_bounds(target_data);
+    }
+
+    /// What pan and zoom speeds are multiplied by for the held modifiers, Shift speeding up and Ctrl slowing down.
+    /// Dragging isn't affected, so the scene keeps following the cursor.
+    fn modifier_speed(&self) -> f32 {
+        let (fast, slow) = self.modifier_speeds;
+        let mut speed = 1.0;
+        if self.modifiers.shift_key() { speed *= fast; }
+        if self.modifiers.control_key() { speed *= slow; }
+        speed
+    }
+
+    /// Moves the camera so the scene follows a cursor moved by (dx, dy) pixels.
+    fn pan_pixels(&mut self, dx: f64, dy: f64, target_data: &TargetData) {
+        // clip space spans 2 units over the viewport, and is scaled by the view into world space.
+        // The cursor moves over the shown scene, so the displayed scale sizes the pixels.
+        let (viewport, half) = self.view(target_data);
+        let scale = self.displayed_camera().scale;
+        let pixel_width = 2.0 * half.x * scale / viewport.vp_width.max(1) as f32;
+        let pixel_height = 2.0 * half.y * scale / viewport.vp_height.max(1) as f32;
+        self.apply_move(CameraMove { offset: [-dx as f32 * pixel_width, dy as f32 * pixel_height], zoom: 1.0 });
+    }
+
+    /// Zooms and then shifts the camera, keeping the move for a recording. Replaying the same moves
+    /// from the same camera gives the same camera exactly, whatever the window size.
+    fn apply_move(&mut self, m: CameraMove) {
+        self.camera.scale = self.clamp_scale(self.camera.scale * m.zoom);
+        self.camera.pos += cgmath::Vector2::from(m.offset);
+        self.pointer_moves.push(m);
+    }
+
+    /// Multiplies the scale by `factor`, keeping the world point under the cursor in place.
+    fn zoom_at_cursor(&mut self, factor: f32, target_data: &TargetData) {
+        let Some(cursor) = self.cursor else {
+            self.apply_move(CameraMove { offset: [0.0; 2], zoom: factor });
+            return;
+        };
+        let (viewport, half) = self.view(target_data);
+        let clip = cgmath::Vector4::new(
+            2.0 * (cursor.x as f32 - viewport.vp_x as f32) / viewport.vp_width.max(1) as f32 - 1.0,
+            1.0 - 2.0 * (cursor.y as f32 - viewport.vp_y as f32) / viewport.vp_height.max(1) as f32,
+            0.0,
+            1.0,
+        );
+        // the point under the cursor is the one shown, which lags the camera between update steps
+        let displayed = self.displayed_camera();
+        let world = displayed.tf(half) * clip;
+        let scale = self.clamp_scale(self.camera.scale * factor);
+        // zoom the shown camera by the same factor, and move the camera as far as it takes the shown one
+        // to map the cursor to the same world point again.
+        let zoomed = Camera { scale: displayed.scale * scale / self.camera.scale, ..displayed };
+        let moved = zoomed.tf(half) * clip;
+        self.apply_move(CameraMove { offset: [world.x - moved.x, world.y - moved.y], zoom: factor });
+    }
+
+    /// Moves the camera continuously for `dt` seconds, like holding the matching keys does.
+    #[cfg(feature = "gilrs")]
+    fn apply_motion(&mut self, motion: &GamepadMotion, dt: f32) {
+        // 2 clip units span the view height
+        self.camera.pos.x += motion.pan[0] * 2.0 * self.camera.scale * dt;
+        self.camera.pos.y += motion.pan[1] * 2.0 * self.camera.scale * dt;
+        self.camera.scale = self.clamp_scale(self.camera.scale * (motion.zoom * dt).exp());
+        self.camera.rot += motion.rotate * dt;
+    }
+
+    /// Moves the camera for `dt` seconds of the held actions.
+    fn apply_held(&mut self, dt: f32) {
+        if self.held.contains(&Action::ResetCamera) {
+            self.set_camera(CameraState::default());
+            return;
+        }
+        let axis = |positive: Action, negative: Action| {
+            self.held.contains(&positive) as i32 as f32 - self.held.contains(&negative) as i32 as f32
+        };
+        let pan_x = axis(Action::PanRight, Action::PanLeft);
+        let pan_y = axis(Action::PanUp, Action::PanDown);
+        // zooming in shrinks the scale
+        let zoom = axis(Action::ZoomOut, Action::ZoomIn);
+        let rotate = axis(Action::RotateCcw, Action::RotateCw);
+        let speed = self.modifier_speed();
+        // 2 clip units span the view height
+        self.camera.pos.x += pan_x * speed * Self::KEY_PAN_SPEED * 2.0 * self.camera.scale * dt;
+        self.camera.pos.y += pan_y * speed * Self::KEY_PAN_SPEED * 2.0 * self.camera.scale * dt;
+        self.camera.scale = self.clamp_scale(self.camera.scale * (zoom * speed * Self::KEY_ZOOM_SPEED * dt).exp());
+        self.camera.rot += rotate * Self::KEY_ROTATE_SPEED * dt;
     }
 }
 
@@ -98,73 +523,1540 @@ impl AppState {
 //     const NUM_BINDINGS: usize = 8;
 // }
 
+/// Configures an [`App`] before it runs. Anything left unset is as in [`App::new`].
+#[derive(Debug, Default)]
+pub struct AppBuilder {
+    model: Option<model::Model>,
+    clear_color: Option<wgpu::Color>,
+    present_mode: Option<wgpu::PresentMode>,
+    objects: Option<Vec<Object>>,
+    window_attributes: Option<WindowAttributes>,
+}
+
+impl AppBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The model the engine is created with, which objects use as model 0.
+    /// It's checked once the window is created, and the app exits if it's invalid.
+    pub fn model(mut self, model: model::Model) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    /// The background color, black by default.
+    pub fn clear_color(mut self, color: wgpu::Color) -> Self {
+        self.clear_color = Some(color);
+        self
+    }
+
+    /// See [`App::set_present_mode`].
+    pub fn present_mode(mut self, mode: wgpu::PresentMode) -> Self {
+        self.present_mode = Some(mode);
+        self
+    }
+
+    /// The objects the scene starts with, instead of two demo objects.
+    pub fn initial_objects(mut self, objects: Vec<Object>) -> Self {
+        self.objects = Some(objects);
+        self
+    }
+
+    /// The attributes the main window is created with, e.g. for a transparent window.
+    pub fn window_attributes(mut self, attributes: WindowAttributes) -> Self {
+        self.window_attributes = Some(attributes);
+        self
+    }
+
+    pub fn build<'s>(self) -> App<'s> {
+        let mut state = AppState::new();
+        match self.objects {
+            Some(objects) => {
+                for object in objects {
+                    state.objects.insert(object);
+                }
+            }
+            None => {
+                state.objects.insert(Object::new(cgmath::Matrix4::identity(), 0));
+                state.counter_rotated = Some(state.objects.insert(Object::new(cgmath::Matrix4::identity(), 1)));
+            }
+        }
+        App {
+            windows: Vec::new(),
+            pending_windows: Vec::new(),
+            active: false,
+            proxy: None,
+            device_pending: false,
+            context: RenderContext::new(),
+            engine: None,
+            offscreen: None,
+            model: self.model,
+            added_models: Vec::new(),
+            clear_color: self.clear_color,
+            state,
+            visibility_queries: false,
+            frustum_culling: false,
+            buffer_growth: BufferGrowth::default(),
+            sprite_atlas: None,
+            winding_epsilon: DEFAULT_WINDING_EPSILON,
+            aa_width: 0.0,
+            wireframe: false,
+            debug_draw_bounds: false,
+            pre_pass: None,
+            post_pass: None,
+            frame_index_policy: FrameIndexPolicy::default(),
+            fit_margin: 0.05,
+            present_mode: self.present_mode,
+            frame_latency: None,
+            alpha_mode: None,
+            window_attributes: self.window_attributes.unwrap_or_default(),
+            sample_count: 1,
+            depth_format: DEFAULT_DEPTH_FORMAT,
+            surface_format: None,
+
+            control_flow_mode: ControlFlowMode::default(),
+            pause_when_unfocused: false,
+
+            last_frame: None,
+            frame_stats: FrameStats::default(),
+            recording: None,
+            playback: None,
+            #[cfg(feature = "gilrs")]
+            gamepad: None,
+
+            timestep: Duration::from_secs(1) / 120,
+            accumulator: Duration::ZERO,
+        }
+    }
+}
+
+/// A device opened for a window off the event loop thread, see [`App::set_event_loop_proxy`].
 #[derive(Debug)]
-pub struct App<'s> {
+pub struct AppEvent {
+    window_id: WindowId,
+    surface: wgpu::Surface<'static>,
+    device: error::Result<DeviceHandle>,
+}
+
+/// A window of the app, and its render target while not suspended.
+#[derive(Debug)]
+struct AppWindow<'s> {
+    window: Arc<Window>, // kept while suspended, to recreate the target for
     target: Option<RenderTarget<'s, RenderDongle>>,
+    windowed_size: Option<winit::dpi::PhysicalSize<u32>>, // size to restore when leaving fullscreen
+    // had no area when its target was due, e.g. started minimized, so it's attached on the first non-zero resize
+    awaiting_size: bool,
+    focused: bool,
+    camera: Option<CameraState>, // its own fixed view, or None to follow the app's camera
+}
+
+impl AppWindow<'_> {
+    /// Marks the window as awaiting a size if it has no area, returning whether it does.
+    fn defer_if_empty(&mut self) -> bool {
+        let size = self.window.inner_size();
+        self.awaiting_size = size.width == 0 || size.height == 0;
+        self.awaiting_size
+    }
+}
+
+/// A pass hook kept by the app, which every engine it creates calls.
+#[derive(Clone)]
+struct SharedPassHook(std::rc::Rc<std::cell::RefCell<PassHook>>);
+
+impl SharedPassHook {
+    fn new(hook: PassHook) -> Self {
+        Self(std::rc::Rc::new(std::cell::RefCell::new(hook)))
+    }
+
+    /// A hook for an engine, calling the shared one.
+    fn forward(&self) -> PassHook {
+        let hook = self.0.clone();
+        Box::new(move |encoder, color_view, depth_view| (hook.borrow_mut())(encoder, color_view, depth_view))
+    }
+}
+
+impl std::fmt::Debug for SharedPassHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedPassHook")
+    }
+}
+
+#[derive(Debug)]
+pub struct App<'s> {
+    // the first window is the main one, whose redraws advance time and whose view picking and bounds use
+    windows: Vec<AppWindow<'s>>,
+    pending_windows: Vec<(WindowAttributes, Option<CameraState>)>, // requested windows, created once the event loop is active
+    active: bool, // between resumed and suspended, when windows can render
+    proxy: Option<EventLoopProxy<AppEvent>>,
+    device_pending: bool, // a device is being opened for a window, see `begin_attach`
     context: RenderContext,
     engine: Option<RenderEngine>,
+    offscreen: Option<OffscreenTarget<RenderDongle>>, // for App::render_once
+    model: Option<model::Model>, // model 0 of new engines, the demo model if unset
+    added_models: Vec<model::Model>, // models 1 and on, added to new engines in order so their ids stay the same
+    clear_color: Option<wgpu::Color>,
     state: AppState,
+    visibility_queries: bool,
+    frustum_culling: bool,
+    buffer_growth: BufferGrowth,
+    sprite_atlas: Option<SpriteAtlas>, // kept for engines created later
+    winding_epsilon: f32,
+    aa_width: f32,
+    wireframe: bool,
+    debug_draw_bounds: bool,
+    pre_pass: Option<SharedPassHook>,
+    post_pass: Option<SharedPassHook>,
+    frame_index_policy: FrameIndexPolicy,
+    fit_margin: f32, // space left around the content by App::fit_camera, as a fraction of its size
+    present_mode: Option<wgpu::PresentMode>,
+    frame_latency: Option<u32>,
+    alpha_mode: Option<wgpu::CompositeAlphaMode>,
+    window_attributes: WindowAttributes, // of the main window
+    sample_count: u32,
+    depth_format: wgpu::TextureFormat,
+    surface_format: Option<wgpu::TextureFormat>,
+
+    control_flow_mode: ControlFlowMode,
+    pause_when_unfocused: bool,
+
+    last_frame: Option<Instant>,
+    frame_stats: FrameStats,
+    recording: Option<InputRecording>,
+    playback: Option<(InputRecording, usize)>, // recording and next frame to replay
+    #[cfg(feature = "gilrs")]
+    gamepad: Option<GamepadInput>,
+
+    timestep: Duration,
+    accumulator: Duration, // frame time not yet consumed by update steps
 }
 
-impl App<'_> {
+impl Default for App<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'s> App<'s> {
+    const MAX_BACKLOG: Duration = Duration::from_millis(250);
+
+    /// Creates the engine for a new target, with the app's model and engine settings.
+    fn create_engine(
+        &self,
+        device_id: DeviceId,
+        format: &wgpu::TextureFormat,
+        dongle: &RenderDongle,
+    ) -> error::Result<RenderEngine> {
+        let model = self.model.clone().unwrap_or_else(|| model::make_load_test_default(2, 2..5, 3..5));
+        let loader = model::SimpleLoader::new(model)?;
+        let mut engine = RenderEngine::new(
+            &self.context,
+            device_id,
+            format,
+            dongle,
+            dongle.depth_format(),
+            dongle.sample_count(),
+            loader,
+        )?;
+        for model in &self.added_models {
+            engine.add_model(self.context.get_device_by_id(device_id), model::SimpleLoader::new(model.clone())?);
+        }
+        engine.set_visibility_queries(self.visibility_queries);
+        engine.set_frustum_culling(self.frustum_culling);
+        engine.set_buffer_growth(self.buffer_growth);
+        if let Some(atlas) = self.sprite_atlas.as_ref() {
+            engine.set_sprite_atlas(self.context.get_device_by_id(device_id), atlas)?;
+        }
+        engine.set_winding_epsilon(self.winding_epsilon);
+        engine.set_aa_width(self.context.get_device_by_id(device_id), self.aa_width)?;
+        engine.set_wireframe(self.wireframe);
+        engine.set_debug_draw_bounds(self.debug_draw_bounds);
+        engine.set_pre_pass(self.pre_pass.as_ref().map(SharedPassHook::forward));
+        engine.set_post_pass(self.post_pass.as_ref().map(SharedPassHook::forward));
+        engine.set_frame_index_policy(self.frame_index_policy);
+        if let Some(color) = self.clear_color {
+            engine.set_clear_color(color);
+        }
+        let warm_up_start = Instant::now();
+        match engine.warm_up(self.context.get_device_by_id(device_id)) {
+            Ok(()) => info!("Engine warmed up in {:?}.", warm_up_start.elapsed()),
+            Err(e) => warn!("Engine warm up failed: {e}"),
+        }
+        Ok(engine)
+    }
+
+    /// Creates an app showing two demo objects, see [`App::clear_objects`] and [`AppBuilder`].
     pub fn new() -> Self {
-        Self {
-            target: None,
-            context: RenderContext::new(),
-            engine: None,
-            state: AppState::new(),
+        AppBuilder::new().build()
+    }
+
+    /// Starts recording input frame by frame, replacing any recording in progress.
+    /// The camera is reset so that replaying from a reset camera reproduces the session.
+    pub fn start_recording(&mut self) {
+        self.reset_input_state();
+        self.recording = Some(InputRecording::default());
+    }
+
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        self.recording.take()
+    }
+
+    /// Resets the camera and replays a recording, one recorded frame per rendered frame.
+    /// Live input is ignored until playback finishes.
+    pub fn play_recording(&mut self, recording: InputRecording) {
+        self.reset_input_state();
+        self.playback = Some((recording, 0));
+    }
+
+    /// Enables gamepad control of the camera, polled once per frame alongside the keyboard.
+    /// Unlike keyboard and mouse input, gamepad motion isn't part of input recordings.
+    #[cfg(feature = "gilrs")]
+    pub fn enable_gamepad(&mut self, bindings: InputBindings) -> error::Result<()> {
+        self.gamepad = Some(GamepadInput::new(bindings)?);
+        Ok(())
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    fn reset_input_state(&mut self) {
+        self.state.reset_view();
+        self.accumulator = Duration::ZERO;
+        self.recording = None;
+        self.playback = None;
+    }
+
+    /// Finishes the input of the current frame, recording it or applying the next replayed frame.
+    fn advance_input_frame(&mut self) {
+        let now = Instant::now();
+        let mut dt = self.last_frame.map(|t| now - t).unwrap_or_default();
+        // the first frame after a start or resume has no previous frame to time against
+        if self.last_frame.is_some() {
+            self.frame_stats.record(dt);
+        }
+        self.last_frame = Some(now);
+
+        let target_data = self.main_target().map(|t| t.get_data());
+        if let Some(recording) = self.recording.as_mut() {
+            recording.frames.push(RecordedFrame {
+                dt,
+                actions: Action::ALL.into_iter().filter(|a| self.state.held.contains(a)).collect(),
+                fast: self.state.modifiers.shift_key(),
+                slow: self.state.modifiers.control_key(),
+                moves: std::mem::take(&mut self.state.pointer_moves),
+            });
+        }
+        if let Some((recording, next)) = self.playback.as_mut() {
+            match recording.frames.get(*next) {
+                Some(frame) => {
+                    self.state.held = frame.actions.iter().copied().collect();
+                    self.state.modifiers = winit::keyboard::ModifiersState::empty();
+                    self.state.modifiers.set(winit::keyboard::ModifiersState::SHIFT, frame.fast);
+                    self.state.modifiers.set(winit::keyboard::ModifiersState::CONTROL, frame.slow);
+                    // replay the recorded frame time too, so time dependent effects match
+                    dt = frame.dt;
+                    *next += 1;
+                    // live mouse moves land before the frame's update steps, and are clamped one by one
+                    for &m in &frame.moves {
+                        self.state.apply_move(m);
+                        if let Some(target_data) = &target_data {
+                            self.state.clamp_to_bounds(target_data);
+                        }
+                    }
+                }
+                None => {
+                    info!("Input playback finished.");
+                    self.playback = None;
+                    self.state.held.clear();
+                    self.state.modifiers = winit::keyboard::ModifiersState::empty();
+                }
+            }
+        }
+        #[cfg(feature = "gilrs")]
+        {
+            self.state.motion = match self.gamepad.as_mut() {
+                Some(gamepad) if self.playback.is_none() => gamepad.poll(),
+                _ => GamepadMotion::default(),
+            };
+        }
+        self.state.pointer_moves.clear();
+        self.advance_time(dt);
+        // held actions and gamepad motion move the camera outside of `handle_input`
+        if let Some(target_data) = &target_data {
+            self.state.clamp_to_bounds(target_data);
         }
     }
-    fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
-        self.target.as_mut().map(
-            |t| t.resize(&self.context, size)
-        );
+
+    /// Runs as many fixed update steps as fit in the frame time accumulated so far,
+    /// so motion doesn't depend on the frame rate.
+    fn advance_time(&mut self, dt: Duration) {
+        // cap the backlog, so a long stall doesn't take many frames of catching up
+        self.accumulator = (self.accumulator + dt).min(Self::MAX_BACKLOG);
+        while self.accumulator >= self.timestep {
+            self.accumulator -= self.timestep;
+            self.state.step(self.timestep.as_secs_f32());
+        }
+        self.state.step_alpha = self.accumulator.as_secs_f32() / self.timestep.as_secs_f32();
+    }
+
+    /// Sets the length of the fixed update steps, 1/120 s by default.
+    pub fn set_timestep(&mut self, timestep: Duration) {
+        self.timestep = timestep.max(Duration::from_micros(100));
+    }
+
+    pub fn timestep(&self) -> Duration {
+        self.timestep
     }
 
-    fn render(&mut self) -> anyhow::Result<()> {
-        if let Some(target) = self.target.as_ref() {
-            if !target.is_live() { return Ok(()); }
-            let output = target.surface().get_current_texture()?;
-            let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+    /// The current camera view, as of the last update step.
+    pub fn camera_state(&self) -> CameraState {
+        self.state.camera_state()
+    }
+
+    /// Moves the camera to a saved view, e.g. from [`App::camera_state`]. Camera bounds still apply.
+    pub fn set_camera_state(&mut self, state: CameraState) {
+        self.state.set_camera_state(state);
+        if let Some(target) = self.main_target() {
+            self.state.clamp_to_bounds(&target.get_data());
+        }
+    }
+
+    /// Writes the camera and the objects to a JSON file, for [`App::load_session`].
+    /// Plain 2D object transforms are stored as [`Transform2D`]s, others as matrices. Animations aren't saved.
+    /// Fails if the file can't be written, or if the camera or an object has a NaN or infinite number.
+    #[cfg(feature = "serde")]
+    pub fn save_session(&self, path: impl AsRef<std::path::Path>) -> error::Result<()> {
+        let session = session::Session::new(self.camera_state(), self.state.objects.iter());
+        std::fs::write(path, session.to_json()?)?;
+        Ok(())
+    }
 
-            self.engine.as_mut().ok_or(anyhow!("Cannot render: engine missing."))?.render(
-                target.device(&self.context),
-                &view,
-                &target.texture_views(),
-                &self.state.create_scene_data(&target.get_data())
-            )?;
-            output.present();
+    /// Replaces the objects with those of a session file written by [`App::save_session`], and resets the view
+    /// to its camera. Handles of the previous objects go stale. Fails, leaving the app unchanged, if the file
+    /// can't be read or is from a newer version.
+    #[cfg(feature = "serde")]
+    pub fn load_session(&mut self, path: impl AsRef<std::path::Path>) -> error::Result<()> {
+        let session = session::Session::from_json(&std::fs::read_to_string(path)?)?;
+        let objects = session.objects
+            .iter()
+            .map(|o| o.to_object())
+            .collect::<error::Result<Vec<_>>>()?;
+        self.state.reset_view();
+        self.state.objects.clear();
+        for object in objects {
+            self.state.objects.insert(object);
+        }
+        self.set_camera_state(session.camera);
+        Ok(())
+    }
+
+    /// Returns the camera to the initial view, like the reset key, R by default.
+    pub fn reset_camera(&mut self) {
+        self.set_camera_state(CameraState::default());
+    }
+
+    /// Centers the camera on the visible objects and zooms so they fill the view, keeping the rotation.
+    /// Resets the camera if there are none. Frames are known once the models are loaded, so this does nothing
+    /// before the main window is attached. Bound to F.
+    pub fn fit_camera(&mut self) {
+        let (Some(target), Some(engine)) = (self.main_target(), self.engine.as_ref()) else { return };
+        let (_, half) = self.state.view(&target.get_data());
+        // the bounds are taken in the frame of the kept rotation, rather than the one shown between steps
+        let Camera { rot, time, .. } = self.state.camera;
+        let boxes = self.state.objects
+            .handles()
+            .zip(self.state.objects.iter())
+            .filter(|(_, o)| o.visible)
+            .filter_map(|(handle, o)| {
+                let num_frames = engine.num_frames(o.model_id)?;
+                let frame = self.frame_index_policy.resolve(o.frame_index_at(time), num_frames).ok()??;
+                let tf = self.state.object_rotation(handle, rot) * o.local_tf_at(time);
+                let bb = world_bounds(&tf, &engine.frame_bounds(o.model_id, frame)?);
+                bb.iter().all(|c| c.is_finite()).then_some(bb)
+            });
+        let state = fit_camera_state(boxes, half, self.fit_margin, rot);
+        self.set_camera_state(state);
+    }
+
+    /// Sets the space [`App::fit_camera`] leaves on each side of the content, as a fraction of its size,
+    /// 0.05 by default.
+    pub fn set_fit_margin(&mut self, margin: f32) {
+        if margin.is_finite() && margin >= 0.0 {
+            self.fit_margin = margin;
+        } else {
+            warn!("Ignoring invalid fit margin {}.", margin);
+        }
+    }
+
+    /// Keeps the visible region inside the world space box from `min` to `max`, so panning can't lose the content.
+    /// Along an axis where the view is wider than the box, the view is centered on it.
+    pub fn set_camera_bounds(&mut self, min: cgmath::Vector2<f32>, max: cgmath::Vector2<f32>) {
+        self.state.camera_bounds = Some((min, max));
+        if let Some(target) = self.main_target() {
+            self.state.clamp_to_bounds(&target.get_data());
+        }
+    }
+
+    pub fn clear_camera_bounds(&mut self) {
+        self.state.camera_bounds = None;
+    }
+
+    /// Sets what pan and zoom speeds are multiplied by while Shift and while Ctrl are held, 4 and 1/4 by default.
+    /// Holding both multiplies by both.
+    pub fn set_modifier_speeds(&mut self, shift: f32, ctrl: f32) {
+        if shift.is_finite() && ctrl.is_finite() && shift > 0.0 && ctrl > 0.0 {
+            self.state.modifier_speeds = (shift, ctrl);
+        } else {
+            warn!("Ignoring invalid modifier speeds {} and {}.", shift, ctrl);
+        }
+    }
+
+    /// Rebinds the camera control keys. Recordings store actions, so they replay the same under any bindings.
+    pub fn set_key_bindings(&mut self, key_bindings: KeyBindings) {
+        self.state.key_bindings = key_bindings;
+    }
+
+    /// Sets how the view region maps to a window of a different shape, [`AspectMode::Fill`] by default.
+    pub fn set_aspect_mode(&mut self, mode: AspectMode) {
+        self.state.aspect_mode = mode;
+    }
+
+    /// Sets the width over height of the view region, or `None`, the default, to take the viewport's,
+    /// which widens the view with the window. At scale 1 the region spans from -1 to 1 vertically.
+    pub fn set_view_aspect(&mut self, aspect: Option<f32>) {
+        match aspect {
+            Some(aspect) if !aspect.is_finite() || aspect <= 0.0 => warn!("Ignoring invalid view aspect {}.", aspect),
+            _ => self.state.view_aspect = aspect,
+        }
+    }
+
+    /// Limits how far the camera zooms, as the smallest and largest scale, 1e-3 to 1e3 by default.
+    /// The camera is clamped into the new limits right away.
+    pub fn set_scale_limits(&mut self, min: f32, max: f32) {
+        if !(min.is_finite() && max.is_finite() && min > 0.0 && min <= max) {
+            warn!("Ignoring invalid scale limits {} to {}.", min, max);
+            return;
+        }
+        let state = &mut self.state;
+        state.scale_limits = (min, max);
+        state.camera.scale = state.clamp_scale(state.camera.scale);
+        state.previous_camera.scale = state.previous_camera.scale.clamp(min, max);
+    }
+
+    /// Snaps the shown camera position to multiples of `grid` world units, at any zoom, or stops snapping with `None`.
+    /// The camera still pans smoothly underneath, so slow pans move the view once they pass half a grid step.
+    pub fn set_pan_grid(&mut self, grid: Option<f32>) {
+        match grid {
+            Some(g) if !(g.is_finite() && g > 0.0) => warn!("Ignoring invalid pan grid {}.", g),
+            _ => self.state.pan_grid = grid,
+        }
+    }
+
+    /// Returns the topmost object under a cursor position in the window, as of the last rendered camera.
+    /// Objects are hit anywhere within their shards' bounding boxes, not just where they're drawn.
+    pub fn pick(&self, cursor: winit::dpi::PhysicalPosition<f64>) -> Option<ObjectHandle> {
+        let (Some(target), Some(engine)) = (self.main_target(), self.engine.as_ref()) else { return None };
+        let scene_data = self.state.create_scene_data(&target.get_data(), self.main_camera());
+        let viewport = render::Viewport::from_scene(&scene_data);
+        match engine.pick(&scene_data, &viewport, [cursor.x as f32, cursor.y as f32]) {
+            // the scene lists the objects in the object list's order
+            Ok(index) => index.and_then(|i| self.state.objects.handles().nth(i)),
+            Err(e) => {
+                warn!("{e}");
+                None
+            }
+        }
+    }
+
+    /// Adds an object to the scene, returning a handle to change or remove it with.
+    /// The transform is a matrix or a [`Transform2D`].
+    /// The camera rotation controls rotate all objects about the world origin.
+    pub fn add_object(&mut self, world_local_tf: impl Into<cgmath::Matrix4<f32>>, frame_index: i32) -> ObjectHandle {
+        self.add_layered_object(world_local_tf, frame_index, 0.0)
+    }
+
+    /// Adds an object in a draw layer, see [`Object::layer`]. [`App::add_object`] adds objects in layer 0.
+    pub fn add_layered_object(
+        &mut self,
+        world_local_tf: impl Into<cgmath::Matrix4<f32>>,
+        frame_index: i32,
+        layer: f32,
+    ) -> ObjectHandle {
+        self.state.objects.insert(Object { layer, ..Object::new(world_local_tf.into(), frame_index) })
+    }
+
+    /// Removes an object from the scene, returning it, or `None` if it was already removed.
+    pub fn remove_object(&mut self, handle: ObjectHandle) -> Option<Object> {
+        self.state.objects.remove(handle)
+    }
+
+    pub fn object(&self, handle: ObjectHandle) -> Option<&Object> {
+        self.state.objects.get(handle)
+    }
+
+    pub fn object_mut(&mut self, handle: ObjectHandle) -> Option<&mut Object> {
+        self.state.objects.get_mut(handle)
+    }
+
+    /// Shows or hides an object without removing it, see [`Object::visible`].
+    pub fn set_object_visible(&mut self, handle: ObjectHandle, visible: bool) {
+        if let Some(object) = self.state.objects.get_mut(handle) {
+            object.visible = visible;
+        }
+    }
+
+    /// Spins an object about its local y axis in perspective, or stops it with `None`, see [`Object::flip_period`].
+    pub fn set_object_flip(&mut self, handle: ObjectHandle, period: Option<f32>) {
+        if let Some(object) = self.state.objects.get_mut(handle) {
+            object.flip_period = period;
+        }
+    }
+
+    /// Plays an animation on an object from now on, replacing any animation it was playing.
+    /// Fails if the object was removed, or once the models are loaded if a frame index is out of range
+    /// for the object's model.
+    pub fn play_animation(&mut self, handle: ObjectHandle, animation: Arc<Animation>) -> error::Result<()> {
+        let time = self.state.camera.time;
+        let object = self.state.objects.get_mut(handle).ok_or(FightishError::UnknownObject)?;
+        if let Some(engine) = self.engine.as_ref() {
+            let num_frames = engine
+                .num_frames(object.model_id)
+                .ok_or(FightishError::UnknownModel(object.model_id))?;
+            if let Some(f) = animation.frames.iter().find(|f| f.frame_index < 0 || f.frame_index as usize >= num_frames) {
+                return Err(FightishError::FrameOutOfRange { frame_index: f.frame_index, num_frames });
+            }
+        }
+        object.animation = Some((animation, time));
+        Ok(())
+    }
+
+    /// Stops an object's animation, leaving it on its static `frame_index`.
+    pub fn stop_animation(&mut self, handle: ObjectHandle) {
+        if let Some(object) = self.state.objects.get_mut(handle) {
+            object.animation = None;
+        }
+    }
+
+    /// Removes all objects, including the demo objects the app starts with.
+    pub fn clear_objects(&mut self) {
+        self.state.objects.clear();
+    }
 
+    /// Enables or disables per-object occlusion queries, see [`App::last_visibility`].
+    pub fn set_visibility_queries(&mut self, enabled: bool) {
+        self.visibility_queries = enabled;
+        if let Some(engine) = self.engine.as_mut() {
+            engine.set_visibility_queries(enabled);
+        }
+    }
+
+    /// Skips objects outside the view before they reach the GPU, see [`RenderEngine::set_frustum_culling`].
+    pub fn set_frustum_culling(&mut self, enabled: bool) {
+        self.frustum_culling = enabled;
+        if let Some(engine) = self.engine.as_mut() {
+            engine.set_frustum_culling(enabled);
+        }
+    }
+
+    /// Sets how the engine's buffers grow, see [`RenderEngine::set_buffer_growth`].
+    pub fn set_buffer_growth(&mut self, growth: BufferGrowth) {
+        self.buffer_growth = growth;
+        if let Some(engine) = self.engine.as_mut() {
+            engine.set_buffer_growth(growth);
+        }
+    }
+
+    /// Sets the sub-pixel grid segment endpoints are snapped to, see [`DEFAULT_WINDING_EPSILON`].
+    pub fn set_winding_epsilon(&mut self, epsilon: f32) {
+        self.winding_epsilon = epsilon;
+        if let Some(engine) = self.engine.as_mut() {
+            engine.set_winding_epsilon(epsilon);
+        }
+    }
+
+    /// Sets the width in pixels of the antialiased shard edges, 0 for hard edges. Useful without MSAA.
+    pub fn set_aa_width(&mut self, width: f32) -> error::Result<()> {
+        if let Some(engine) = self.engine.as_mut() {
+            engine.set_aa_width(self.context.get_device_by_id(engine.device_id()), width)?;
+        }
+        self.aa_width = width;
+        Ok(())
+    }
+
+    /// Outlines every shard's bounding quad over the scene, for debugging. Toggled with F3.
+    pub fn set_wireframe(&mut self, enabled: bool) {
+        self.wireframe = enabled;
+        if let Some(engine) = self.engine.as_mut() {
+            engine.set_wireframe(enabled);
+            // stays off where unsupported
+            self.wireframe = engine.wireframe();
+        }
+    }
+
+    /// Outlines every shard's transformed bounding box over the scene, for debugging. Toggled with F4.
+    pub fn set_debug_draw_bounds(&mut self, enabled: bool) {
+        self.debug_draw_bounds = enabled;
+        if let Some(engine) = self.engine.as_mut() {
+            engine.set_debug_draw_bounds(enabled);
+        }
+    }
+
+    /// Sets a callback recording commands before each scene's render pass, see [`RenderEngine::set_pre_pass`].
+    /// It's kept for engines created later, e.g. after the device is lost. `None` removes it.
+    pub fn set_pre_pass(&mut self, hook: Option<PassHook>) {
+        self.pre_pass = hook.map(SharedPassHook::new);
+        if let Some(engine) = self.engine.as_mut() {
+            engine.set_pre_pass(self.pre_pass.as_ref().map(SharedPassHook::forward));
+        }
+    }
+
+    /// Sets a callback recording commands after each scene's render pass, see [`RenderEngine::set_post_pass`].
+    /// It's kept for engines created later, e.g. after the device is lost. `None` removes it.
+    pub fn set_post_pass(&mut self, hook: Option<PassHook>) {
+        self.post_pass = hook.map(SharedPassHook::new);
+        if let Some(engine) = self.engine.as_mut() {
+            engine.set_post_pass(self.post_pass.as_ref().map(SharedPassHook::forward));
+        }
+    }
+
+    /// Sets how objects referencing frames outside the model are handled.
+    pub fn set_frame_index_policy(&mut self, policy: FrameIndexPolicy) {
+        self.frame_index_policy = policy;
+        if let Some(engine) = self.engine.as_mut() {
+            engine.set_frame_index_policy(policy);
+        }
+    }
+
+    /// Sets the background color, black by default.
+    pub fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.clear_color = Some(color);
+        if let Some(engine) = self.engine.as_mut() {
+            engine.set_clear_color(color);
+        }
+    }
+
+    /// Sets a tint multiplied into the colors of every object, white by default.
+    pub fn set_global_tint(&mut self, tint: [f32; 4]) {
+        self.state.global_tint = tint;
+    }
+
+    /// Requests a present mode for the window surface, instead of the first one the surface reports.
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) {
+        self.present_mode = Some(mode);
+        for target in self.windows.iter_mut().filter_map(|w| w.target.as_mut()) {
+            target.set_present_mode(&self.context, mode);
+        }
+    }
+
+    /// Sets how many frames the window surfaces may queue, see [`render::RenderTarget::set_frame_latency`].
+    pub fn set_frame_latency(&mut self, latency: u32) {
+        self.frame_latency = Some(latency);
+        for target in self.windows.iter_mut().filter_map(|w| w.target.as_mut()) {
+            target.set_frame_latency(&self.context, latency);
+        }
+    }
+
+    /// Requests how the window surfaces are composited, see [`render::RenderTarget::set_alpha_mode`].
+    /// A see-through window also needs transparent window attributes and clear color.
+    pub fn set_alpha_mode(&mut self, mode: wgpu::CompositeAlphaMode) {
+        self.alpha_mode = Some(mode);
+        for target in self.windows.iter_mut().filter_map(|w| w.target.as_mut()) {
+            target.set_alpha_mode(&self.context, mode);
+        }
+    }
+
+    /// Sets when the windows are redrawn, continuously by default.
+    pub fn set_control_flow_mode(&mut self, mode: ControlFlowMode) {
+        self.control_flow_mode = mode;
+        self.request_redraws();
+    }
+
+    /// Stops redrawing while none of the windows has focus, e.g. to save power in the background.
+    pub fn set_pause_when_unfocused(&mut self, pause: bool) {
+        self.pause_when_unfocused = pause;
+        self.request_redraws();
+    }
+
+    /// Sets the MSAA sample count, e.g. 4. Unsupported counts fall back to 1.
+    /// Only takes effect when the window is created, i.e. call this before running the event loop.
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        self.sample_count = sample_count;
+    }
+
+    /// Sets the depth buffer format, e.g. `Depth32Float` where `Depth24Plus` isn't renderable.
+    /// Both keep every clip depth distinct, up to [`MAX_CLIP_DEPTH`].
+    /// Only takes effect when the window is created, like [`App::set_sample_count`].
+    pub fn set_depth_format(&mut self, depth_format: wgpu::TextureFormat) {
+        self.depth_format = depth_format;
+    }
+
+    /// Requests a window surface format, e.g. a linear one when the colors are already gamma encoded.
+    /// Falls back to the first sRGB format the surface supports. Only takes effect for windows created
+    /// afterwards, like [`App::set_sample_count`].
+    pub fn set_surface_format(&mut self, format: Option<wgpu::TextureFormat>) {
+        self.surface_format = format;
+    }
+
+    /// Renders the current scene once into a `width` x `height` offscreen frame, outside of the event loop,
+    /// and returns its pixels once the GPU is done, e.g. for screenshot tests. Time and input don't advance.
+    /// The pixels are tightly packed 8 bit sRGB RGBA, [`headless::CAPTURE_FORMAT`], whatever the windows' surface format.
+    /// Creates the device and engine if there's no window yet. Fails if the windows render to a format other than
+    /// 8 bit RGBA or BGRA.
+    pub fn render_once(&mut self, width: u32, height: u32) -> error::Result<Vec<u8>> {
+        let format = self.engine.as_ref().map_or(headless::CAPTURE_FORMAT, |e| e.format());
+        let reusable = self.offscreen.as_ref().is_some_and(|t| {
+            let data = t.get_data();
+            (data.vp_width, data.vp_height, *t.format()) == (width, height, format)
+                && t.device(&self.context).lost().is_none()
+        });
+        let target = match self.offscreen.take() {
+            Some(target) if reusable => target,
+            _ => {
+                let dongle = RenderDongle::new(self.depth_format)?.with_sample_count(self.sample_count);
+                pollster::block_on(OffscreenTarget::create(&mut self.context, dongle, format, width, height))?
+            }
+        };
+        if self.engine.as_ref().is_none_or(|e| e.device_id() != target.device_id()) {
+            if self.windows.iter().any(|w| w.target.is_some()) {
+                return Err(FightishError::InvalidTarget(
+                    "Cannot render once: the windows render with another device.".into(),
+                ));
+            }
+            self.engine = Some(self.create_engine(target.device_id(), target.format(), target.dongle())?);
+        }
+        let camera = self.main_camera();
+        let target = &*self.offscreen.insert(target);
+        let engine = self.engine.as_mut().ok_or(FightishError::MissingEngine)?;
+        let pixels = engine.render_to_buffer(&self.context, target, &self.state.create_scene_data(&target.get_data(), camera))?;
+        headless::to_capture_format(pixels, format)
+    }
+
+    /// Replaces a model without restarting, e.g. after editing an asset. Engines created later load it too.
+    /// Fails if the model is invalid or unknown.
+    pub fn reload_model(&mut self, model_id: usize, model: model::Model) -> error::Result<()> {
+        if model_id > self.added_models.len() {
+            return Err(FightishError::UnknownModel(model_id));
+        }
+        if let Some(engine) = self.engine.as_mut() {
+            engine.reload_model(self.context.get_device_by_id(engine.device_id()), model_id, model.clone())?;
+        } else {
+            // the loader validates the model
+            model::SimpleLoader::new(model.clone())?;
+        }
+        match model_id {
+            0 => self.model = Some(model),
+            _ => self.added_models[model_id - 1] = model,
+        }
+        Ok(())
+    }
+
+    /// Sets the atlas that sprite objects are textured from, see [`Object::sprite`].
+    /// Fails if the engine can't upload it, e.g. when it's larger than the device allows.
+    pub fn set_sprite_atlas(&mut self, atlas: SpriteAtlas) -> error::Result<()> {
+        if let Some(engine) = self.engine.as_mut() {
+            engine.set_sprite_atlas(self.context.get_device_by_id(engine.device_id()), &atlas)?;
+        }
+        self.sprite_atlas = Some(atlas);
+        Ok(())
+    }
+
+    /// Loads another model for objects to draw from, returning the id to set as their `model_id`.
+    /// Engines created later load it too. Fails if the model is invalid.
+    pub fn add_model(&mut self, model: model::Model) -> error::Result<usize> {
+        let loader = model::SimpleLoader::new(model.clone())?;
+        if let Some(engine) = self.engine.as_mut() {
+            engine.add_model(self.context.get_device_by_id(engine.device_id()), loader);
+        }
+        self.added_models.push(model);
+        Ok(self.added_models.len())
+    }
+
+    /// Restricts the graphics backends and sets the adapter power preference, e.g. `LowPower` for the
+    /// integrated GPU of a laptop. Only takes effect before the window is created.
+    pub fn set_adapter_options(&mut self, backends: wgpu::Backends, power_preference: wgpu::PowerPreference) {
+        if !self.windows.is_empty() || self.engine.is_some() {
+            warn!("Adapter options can't change once the window is created.");
+            return;
+        }
+        self.context = RenderContext::with_options(backends, power_preference);
+    }
+
+    /// The adapter rendering the window, e.g. to include the GPU in bug reports.
+    /// `None` before the window is created.
+    pub fn gpu_info(&self) -> Option<wgpu::AdapterInfo> {
+        self.main_target().map(|t| self.context.adapter_info(t.device_id()))
+    }
+
+    /// Wall clock frame timing, measured between redraws.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+
+    /// Sets the weight of the newest frame in the smoothed frame time, 0.1 by default.
+    pub fn set_frame_stats_smoothing(&mut self, smoothing: f64) {
+        self.frame_stats.smoothing = smoothing.clamp(0.0, 1.0);
+    }
+
+    /// GPU time of the last read back frame's passes, `None` without timestamp query support.
+    /// With `smoothed` set, the timings are averaged over recent frames.
+    pub fn gpu_timings(&self, smoothed: bool) -> Option<PassTimings> {
+        let engine = self.engine.as_ref()?;
+        if smoothed { engine.average_timings() } else { engine.last_timings() }
+    }
+
+    /// Estimated GPU buffer memory used by the renderer, `None` before the engine is created.
+    pub fn gpu_memory_estimate(&self) -> Option<MemoryReport> {
+        self.engine.as_ref().map(|e| e.gpu_memory_estimate())
+    }
+
+    /// Visible sample counts per scene object from a recent frame, as (object index, samples) pairs.
+    /// An object with zero samples was either off-screen or fully occluded.
+    pub fn last_visibility(&self) -> Vec<(usize, u32)> {
+        self.engine
+            .as_ref()
+            .map(|e| e.last_visibility())
+            .unwrap_or_default()
+    }
+
+    /// Opens the devices for new windows on another thread, sending them back through `proxy`,
+    /// so the event loop doesn't stall meanwhile. The windows stay blank until their device arrives,
+    /// and show the clear color until the engine is ready. Without a proxy, devices are opened on the event loop thread.
+    pub fn set_event_loop_proxy(&mut self, proxy: EventLoopProxy<AppEvent>) {
+        self.proxy = Some(proxy);
+    }
+
+    /// Builds an event loop the app can run on, and opens devices off it, see [`App::set_event_loop_proxy`].
+    /// The app handles [`AppEvent`]s, so a loop from `EventLoop::new()` doesn't fit it; use this instead.
+    pub fn create_event_loop(&mut self) -> Result<EventLoop<AppEvent>, winit::error::EventLoopError> {
+        let event_loop = EventLoop::with_user_event().build()?;
+        self.set_event_loop_proxy(event_loop.create_proxy());
+        Ok(event_loop)
+    }
+
+    /// Requests another window showing the same scene, created once the event loop is active.
+    /// All windows share the engine, so a window needing another device or surface format is closed again.
+    /// The app exits when the last window closes.
+    pub fn open_window(&mut self, attributes: WindowAttributes) {
+        self.pending_windows.push((attributes, None));
+    }
+
+    /// Requests another window showing the same scene from its own fixed `camera`, e.g. a preview
+    /// the camera controls don't move. Dragging and zooming in it are ignored.
+    pub fn open_window_with_camera(&mut self, attributes: WindowAttributes, camera: CameraState) {
+        self.pending_windows.push((attributes, Some(camera)));
+    }
+
+    /// Gives the window at `index` its own fixed camera, or makes it follow the app's camera again with `None`.
+    /// Windows are indexed in the order they were opened, the main window first, and closing one shifts the later ones.
+    /// Returns false if there's no such window.
+    pub fn set_window_camera(&mut self, index: usize, camera: Option<CameraState>) -> bool {
+        let Some(w) = self.windows.get_mut(index) else { return false };
+        w.camera = camera;
+        w.window.request_redraw();
+        true
+    }
+
+    /// The own camera of the window at `index`, `None` if it follows the app's camera or doesn't exist.
+    pub fn window_camera(&self, index: usize) -> Option<CameraState> {
+        self.windows.get(index).and_then(|w| w.camera)
+    }
+
+    pub fn window_count(&self) -> usize {
+        self.windows.len()
+    }
+
+    fn main_camera(&self) -> Option<CameraState> {
+        self.windows.first().and_then(|w| w.camera)
+    }
+
+    fn main_target(&self) -> Option<&RenderTarget<'s, RenderDongle>> {
+        self.windows.first().and_then(|w| w.target.as_ref())
+    }
+
+    fn window_index(&self, id: WindowId) -> Option<usize> {
+        self.windows.iter().position(|w| w.window.id() == id)
+    }
+
+    /// Switches the main window between borderless fullscreen and a window of the size it had before.
+    pub fn toggle_fullscreen(&mut self) {
+        if let Some(id) = self.windows.first().map(|w| w.window.id()) {
+            self.toggle_window_fullscreen(id);
+        }
+    }
+
+    fn toggle_window_fullscreen(&mut self, id: WindowId) {
+        let Some(w) = self.windows.iter_mut().find(|w| w.window.id() == id) else { return };
+        let window = &w.window;
+        let applied_size = if window.fullscreen().is_some() {
+            window.set_fullscreen(None);
+            w.windowed_size
+                .take()
+                .and_then(|size| window.request_inner_size(size))
+        } else {
+            w.windowed_size = Some(window.inner_size());
+            window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
+            None
+        };
+        // a size applied right away doesn't always come with a resize event
+        if let Some(size) = applied_size {
+            self.resize(id, size);
+        }
+    }
+
+    fn resize(&mut self, id: WindowId, size: winit::dpi::PhysicalSize<u32>) {
+        let target = self.windows.iter_mut().find(|w| w.window.id() == id).and_then(|w| w.target.as_mut());
+        if let Some(t) = target {
+            t.resize(&self.context, size)
+        }
+    }
+
+    fn paused(&self) -> bool {
+        self.pause_when_unfocused && !self.windows.iter().any(|w| w.focused)
+    }
+
+    /// Whether the view changes without input, so frames have to keep coming in wait mode.
+    fn animating(&self) -> bool {
+        #[cfg(feature = "gilrs")]
+        if self.gamepad.is_some() {
+            return true;
+        }
+        // shard animations only show on objects drawn from the model
+        let shard_animated = |o: &Object| {
+            o.sprite.is_none() && self.engine.as_ref().is_some_and(|e| e.frame_animated(o.model_id, o.frame_index))
+        };
+        self.playback.is_some()
+            || !self.state.held.is_empty()
+            || self.state.objects.iter().any(|o| {
+                o.visible && (o.animation.is_some() || o.flip_period.is_some() || shard_animated(o))
+            })
+    }
+
+    /// Whether windows should request another redraw once drawn.
+    fn redraw_continuously(&self) -> bool {
+        !self.paused() && (self.control_flow_mode == ControlFlowMode::Poll || self.animating())
+    }
+
+    /// Redraws every window once, e.g. after input while waiting.
+    fn request_redraws(&self) {
+        if self.paused() { return; }
+        for w in &self.windows {
+            w.window.request_redraw();
+        }
+    }
+
+    fn render(&mut self, id: WindowId) -> error::Result<()> {
+        let redraw = self.redraw_continuously();
+        let Some(w) = self.windows.iter_mut().find(|w| w.window.id() == id) else { return Ok(()) };
+        let camera = w.camera;
+        let Some(target) = w.target.as_mut() else { return Ok(()) };
+        if !target.is_live() { return Ok(()); }
+        let Some(output) = target.acquire(&self.context)? else { return Ok(()) };
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        self.engine.as_mut().ok_or(FightishError::MissingEngine)?.render(
+            target.device(&self.context),
+            &view,
+            target.texture_views(),
+            &self.state.create_scene_data(&target.get_data(), camera)
+        )?;
+        output.present();
+
+        if redraw {
             target.window().request_redraw();
         }
         Ok(())
     }
+
+    /// Presents a frame of just the clear color, for a window whose engine isn't ready yet.
+    fn present_placeholder(&self, target: &mut RenderTarget<'s, RenderDongle>) -> error::Result<()> {
+        let Some(output) = target.acquire(&self.context)? else { return Ok(()) };
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let device = target.device(&self.context);
+        let mut encoder = device.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Placeholder encoder"),
+        });
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Placeholder pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.clear_color.unwrap_or(wgpu::Color::BLACK)),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        device.queue.submit(std::iter::once(encoder.finish()));
+        output.present();
+        Ok(())
+    }
+
+    /// Creates the render target for a window from its surface, and the engine if it can't be shared with the target.
+    fn attach_target(&mut self, index: usize, surface: wgpu::Surface<'static>, device_id: DeviceId) -> error::Result<()> {
+        // the window may have been minimized while the device was opened
+        if self.windows[index].defer_if_empty() {
+            info!("Window has no area, deferring its target.");
+            return Ok(());
+        }
+        let mut target = RenderTarget::from_surface(
+            &self.context,
+            self.windows[index].window.clone(),
+            surface,
+            device_id,
+            RenderDongle::new(self.depth_format)?.with_sample_count(self.sample_count),
+            self.surface_format,
+        )?;
+        if let Some(mode) = self.present_mode {
+            target.set_present_mode(&self.context, mode);
+        }
+        if let Some(latency) = self.frame_latency {
+            target.set_frame_latency(&self.context, latency);
+        }
+        if let Some(mode) = self.alpha_mode {
+            target.set_alpha_mode(&self.context, mode);
+        }
+        // creating the engine compiles its pipelines, so the window shows the clear color meanwhile
+        let engine_ready = self.engine.as_ref().is_some_and(|e| {
+            e.device_id() == target.device_id() && e.format() == *target.surface_format()
+        });
+        if !engine_ready {
+            if let Err(e) = self.present_placeholder(&mut target) {
+                warn!("Cannot clear the window: {e}");
+            }
+        }
+        if self.engine.is_none() {
+            let info = self.context.adapter_info(target.device_id());
+            info!("Using {} ({:?}, {:?}), driver {} {}", info.name, info.backend, info.device_type, info.driver, info.driver_info);
+        }
+        // the engine survives a suspend, unless the new surface needs another device,
+        // but while other windows render with it, it can't follow the new surface at all
+        let shared = self.windows.iter().any(|w| w.target.is_some());
+        let engine = match self.engine.take() {
+            Some(engine)
+                if shared
+                    && (engine.device_id() != target.device_id() || engine.format() != *target.surface_format()) =>
+            {
+                self.engine = Some(engine);
+                return Err(FightishError::InvalidTarget(
+                    "Window needs another device or surface format than the other windows.".into(),
+                ));
+            }
+            Some(mut engine) if engine.device_id() == target.device_id() => {
+                engine
+                    .set_format(target.device(&self.context), *target.surface_format(), target.dongle())
+                    .map(|()| engine)
+            }
+            _ => self.create_engine(target.device_id(), target.surface_format(), target.dongle()),
+        };
+        self.engine = Some(engine?);
+        if index == 0 {
+            self.state.scale_factor = self.windows[index].window.scale_factor();
+        }
+        // redraws requested before the target existed went unanswered
+        self.windows[index].window.request_redraw();
+        self.windows[index].target = Some(target);
+        Ok(())
+    }
+
+    /// Starts creating the render target for the window at `index`. A new device is opened on another thread
+    /// when there's an event loop proxy to send it back through, and the target is attached once it arrives.
+    fn begin_attach(&mut self, index: usize) -> error::Result<()> {
+        if self.windows[index].defer_if_empty() {
+            info!("Window has no area, deferring its target.");
+            return Ok(());
+        }
+        let surface = self.context.create_surface(self.windows[index].window.clone())?;
+        if let Some(device_id) = self.context.compatible_device(Some(&surface)) {
+            return self.attach_target(index, surface, device_id);
+        }
+        let opener = self.context.device_opener();
+        let Some(proxy) = self.proxy.clone() else {
+            let device = pollster::block_on(opener.open(Some(&surface)))?;
+            let device_id = self.context.add_device(device);
+            return self.attach_target(index, surface, device_id);
+        };
+        let window_id = self.windows[index].window.id();
+        self.device_pending = true;
+        std::thread::spawn(move || {
+            let device = pollster::block_on(opener.open(Some(&surface)));
+            // only fails once the event loop is gone, and the device with it
+            let _ = proxy.send_event(AppEvent { window_id, surface, device });
+        });
+        Ok(())
+    }
+
+    /// Drops the targets and engine on lost devices, so they can be recreated on a new device,
+    /// and then the lost devices themselves. Returns whether anything was dropped.
+    fn drop_lost_targets(&mut self) -> bool {
+        let context = &self.context;
+        let mut dropped = false;
+        if self.offscreen.as_ref().is_some_and(|t| t.device(context).lost().is_some()) {
+            self.offscreen = None;
+        }
+        for w in &mut self.windows {
+            if w.target.as_ref().is_some_and(|t| t.device(context).lost().is_some()) {
+                w.target = None;
+                dropped = true;
+            }
+        }
+        if self.engine.as_ref().is_some_and(|e| context.get_device_by_id(e.device_id()).lost().is_some()) {
+            // the models are uploaded again with the new engine
+            self.engine = None;
+            dropped = true;
+        }
+        if self.context.prune_lost_devices() > 0 {
+            info!("Dropped the lost devices.");
+        }
+        dropped
+    }
+
+    /// Closes a window whose target can't be created, or exits if it's the main window.
+    fn close_failed_window(&mut self, index: usize, e: FightishError, event_loop: &ActiveEventLoop) {
+        if index == 0 {
+            error!("Cannot render to the main window: {e}");
+            event_loop.exit();
+        } else {
+            warn!("Cannot render to window, closing it: {e}");
+            self.windows.remove(index);
+        }
+    }
+
+    /// Starts attaching targets to the windows without one. While a device is being opened, the other
+    /// windows wait for it, since it likely suits them too.
+    fn attach_targets(&mut self, event_loop: &ActiveEventLoop) {
+        let mut index = 0;
+        while index < self.windows.len() && !self.device_pending {
+            if self.windows[index].target.is_some() || self.windows[index].awaiting_size {
+                index += 1;
+                continue;
+            }
+            match self.begin_attach(index) {
+                Ok(()) => index += 1,
+                Err(e) if index == 0 => return self.close_failed_window(index, e, event_loop),
+                Err(e) => self.close_failed_window(index, e, event_loop),
+            }
+        }
+    }
+
+    fn open_pending_windows(&mut self, event_loop: &ActiveEventLoop) {
+        for (attributes, camera) in std::mem::take(&mut self.pending_windows) {
+            match event_loop.create_window(attributes) {
+                Ok(window) => {
+                    info!("Window created");
+                    self.windows.push(AppWindow {
+                        focused: window.has_focus(),
+                        window: Arc::new(window),
+                        target: None,
+                        windowed_size: None,
+                        awaiting_size: false,
+                        camera,
+                    });
+                }
+                Err(e) => error!("Cannot create window: {e}"),
+            }
+        }
+        if self.windows.is_empty() {
+            event_loop.exit();
+            return;
+        }
+        self.attach_targets(event_loop);
+    }
 }
 
-impl ApplicationHandler for App<'_> {
+impl ApplicationHandler<AppEvent> for App<'_> {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        info!("Window resumed/created, creating window");
-        assert!(self.target.is_none(), "Suspending and resuming are not supported.");
-        let window = event_loop.create_window(Window::default_attributes()).unwrap();
-        let target = pollster::block_on(RenderTarget::create(&mut self.context, Arc::new(window), RenderDongle::new())).unwrap();
-        let loader = model::SimpleLoader::new(model::make_load_test(2, 2..5, 3..5));
-        self.engine = Some(RenderEngine::new(&self.context, target.device_id(), target.surface_format(), loader));
-        self.target = Some(target);
+        self.active = true;
+        if self.windows.is_empty() {
+            self.pending_windows.insert(0, (self.window_attributes.clone(), None));
+        } else {
+            info!("Resumed, recreating surfaces");
+        }
+        // also attaches targets to the existing windows
+        self.open_pending_windows(event_loop);
+    }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: AppEvent) {
+        self.device_pending = false;
+        let AppEvent { window_id, surface, device } = event;
+        let device_id = device.map(|device| self.context.add_device(device));
+        // the window may have closed, or the app suspended, while the device was opened
+        let index = self.window_index(window_id).filter(|_| self.active);
+        if let Some(index) = index {
+            let attached = device_id
+                .and_then(|device_id| self.attach_target(index, surface, device_id));
+            if let Err(e) = attached {
+                self.close_failed_window(index, e, event_loop);
+                if index == 0 { return; }
+            }
+        }
+        if self.active {
+            self.attach_targets(event_loop);
+        }
     }
 
-    fn window_event(&mut self, event_loop: &ActiveEventLoop, _window_id: WindowId, event: WindowEvent) {
+    /// Drops the surfaces, which may be destroyed while suspended, keeping the windows, engine and state.
+    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+        info!("Suspended, dropping surfaces");
+        self.active = false;
+        for w in &mut self.windows {
+            w.target = None;
+        }
+        self.last_frame = None;
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.active && !self.pending_windows.is_empty() {
+            self.open_pending_windows(event_loop);
+        }
+        event_loop.set_control_flow(if self.redraw_continuously() { ControlFlow::Poll } else { ControlFlow::Wait });
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, event: WindowEvent) {
+        // events can still arrive for a window that was just closed
+        let Some(index) = self.window_index(window_id) else { return };
+        // while waiting, anything but a redraw may change what's shown
+        if !matches!(event, WindowEvent::RedrawRequested) && !self.redraw_continuously() {
+            self.request_redraws();
+        }
         match event {
             WindowEvent::CloseRequested => {
-                info!("Close requested, shutting down.");
-                event_loop.exit();
+                self.windows.remove(index);
+                if self.windows.is_empty() {
+                    info!("Close requested, shutting down.");
+                    event_loop.exit();
+                } else {
+                    info!("Window closed.");
+                }
             }
             WindowEvent::RedrawRequested => {
-                self.render().err().map(|e| warn!("{e}"));
+                // every window redraws each frame, time only advances with the main one
+                if index == 0 {
+                    self.advance_input_frame();
+                }
+                if self.drop_lost_targets() {
+                    warn!("Device lost, recreating the render targets.");
+                    self.attach_targets(event_loop);
+                }
+                if let Err(e) = self.render(window_id) { warn!("{e}"); }
             }
             WindowEvent::Resized(size) => {
-                self.resize(size);
+                if self.windows[index].awaiting_size && size.width > 0 && size.height > 0 {
+                    self.windows[index].awaiting_size = false;
+                    // while suspended, resuming attaches it
+                    if self.active {
+                        self.attach_targets(event_loop);
+                    }
+                } else {
+                    self.resize(window_id, size);
+                }
+            }
+            WindowEvent::Focused(focused) => {
+                // tracked per window, since focus moving between them may arrive as the gain before the loss
+                let paused = self.paused();
+                self.windows[index].focused = focused;
+                if paused && !self.paused() {
+                    // the time spent paused isn't a frame
+                    self.last_frame = None;
+                    self.request_redraws();
+                }
+            }
+            // moved to a monitor with another DPI, the physical size changes with the scale factor
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                if index == 0 {
+                    self.state.scale_factor = scale_factor;
+                }
+                let size = self.windows[index].window.inner_size();
+                self.resize(window_id, size);
             }
-            _ => {self.state.handle_input(event);}
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::F11),
+                    state: winit::event::ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.toggle_window_fullscreen(window_id);
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::F3),
+                    state: winit::event::ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.set_wireframe(!self.wireframe);
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::F4),
+                    state: winit::event::ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.set_debug_draw_bounds(!self.debug_draw_bounds);
+            }
+            // camera controls rebound to F take precedence
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyF),
+                    state: winit::event::ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+                ..
+            } if self.playback.is_none()
+                && self.state.key_bindings.action(winit::keyboard::KeyCode::KeyF).is_none() => {
+                self.fit_camera();
+            }
+            _ => {
+                if self.playback.is_some() { return; }
+                let window = &self.windows[index];
+                let Some(target_data) = window.target.as_ref().map(|t| t.get_data()) else { return };
+                // a window with its own camera has nothing to drag or zoom
+                let pointer = matches!(
+                    event,
+                    WindowEvent::MouseInput { .. } | WindowEvent::CursorMoved { .. } | WindowEvent::MouseWheel { .. }
+                );
+                if window.camera.is_some() && pointer { return; }
+                self.state.handle_input(event, &target_data);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 4], b: [f32; 4]) {
+        assert!(a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-5), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn fitting_covers_every_box() {
+        let boxes = [[0.0, 0.0, 2.0, 1.0], [-1.0, -1.0, 0.0, 0.0]];
+        let state = fit_camera_state(boxes.into_iter(), cgmath::Vector2::new(2.0, 1.0), 0.0, 0.5);
+        // 3 wide in a view 4 wide at scale 1, and 2 high in one 2 high, so the height decides
+        assert_eq!(state, CameraState { scale: 1.0, pos: [0.5, 0.0], rot: 0.5 });
+        let state = fit_camera_state(boxes.into_iter(), cgmath::Vector2::new(1.0, 2.0), 0.25, 0.5);
+        assert_eq!(state.scale, 1.5 * 1.5);
+    }
+
+    #[test]
+    fn views_follow_the_viewport_by_default() {
+        let wide = TargetData { vp_x: 0, vp_y: 0, vp_width: 800, vp_height: 400 };
+        let mut state = AppState::new();
+        for mode in [AspectMode::Stretch, AspectMode::Fit, AspectMode::Fill] {
+            state.aspect_mode = mode;
+            let (viewport, half) = state.view(&wide);
+            assert_eq!((viewport.vp_width, viewport.vp_height), (800, 400));
+            assert_eq!(half, cgmath::Vector2::new(2.0, 1.0));
+        }
+
+        state.view_aspect = Some(1.0);
+        state.aspect_mode = AspectMode::Fit;
+        let (viewport, half) = state.view(&wide);
+        assert_eq!((viewport.vp_x, viewport.vp_width, viewport.vp_height), (200, 400, 400));
+        assert_eq!(half, cgmath::Vector2::new(1.0, 1.0));
+        state.aspect_mode = AspectMode::Fill;
+        assert_eq!(state.view(&wide).1, cgmath::Vector2::new(1.0, 0.5));
+    }
+
+    #[test]
+    fn windows_can_show_their_own_camera() {
+        let square = TargetData { vp_x: 0, vp_y: 0, vp_width: 100, vp_height: 100 };
+        let mut state = AppState::new();
+        state.set_camera_state(CameraState { scale: 2.0, pos: [1.0, 0.0], rot: 0.0 });
+        state.camera.time = 3.0;
+        state.previous_camera = state.camera;
+        let preview = CameraState { scale: 0.5, pos: [0.0, -1.0], rot: 0.0 };
+
+        let followed = state.create_scene_data(&square, None);
+        let own = state.create_scene_data(&square, Some(preview));
+        assert_eq!(followed.camera_tf.w.truncate(), cgmath::Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(own.camera_tf.w.truncate(), cgmath::Vector3::new(0.0, -1.0, 0.0));
+        assert_eq!((followed.camera_tf.x.x, own.camera_tf.x.x), (2.0, 0.5));
+        // the views only differ in where they look, not in when
+        assert_eq!(own.time, followed.time);
+        assert_eq!(state.camera_state().scale, 2.0);
+    }
+
+    #[test]
+    fn replays_reproduce_keyboard_and_mouse_input() {
+        let square = TargetData { vp_x: 0, vp_y: 0, vp_width: 100, vp_height: 100 };
+        let mut app = App::new();
+        app.start_recording();
+        let mut recorded = Vec::new();
+        for frame in 0..8 {
+            // long enough frames for a few update steps, whose partial steps the mouse moves then act on
+            std::thread::sleep(Duration::from_millis(11));
+            app.state.held = if frame < 4 { HashSet::from([Action::PanRight, Action::ZoomIn]) } else { HashSet::new() };
+            app.state.modifiers.set(winit::keyboard::ModifiersState::SHIFT, frame % 3 == 0);
+            app.state.pan_pixels(3.0, -2.0, &square);
+            app.state.cursor = Some(winit::dpi::PhysicalPosition::new(20.0 + frame as f64, 70.0));
+            app.state.zoom_at_cursor(0.9, &square);
+            app.advance_input_frame();
+            recorded.push((app.state.displayed_camera().pos, app.camera_state()));
+        }
+        let mut text = Vec::new();
+        app.stop_recording().unwrap().write_to(&mut text).unwrap();
+
+        app.play_recording(InputRecording::read_from(text.as_slice()).unwrap());
+        for expected in recorded {
+            app.advance_input_frame();
+            assert_eq!((app.state.displayed_camera().pos, app.camera_state()), expected);
         }
     }
+
+    #[test]
+    fn cursor_controls_follow_the_displayed_camera() {
+        let square = TargetData { vp_x: 0, vp_y: 0, vp_width: 100, vp_height: 100 };
+        let mut state = AppState::new();
+        state.camera.scale = 3.0;
+        state.step_alpha = 0.5;
+        // shown at scale 2, where a pixel spans 0.04 world units
+        state.pan_pixels(10.0, 0.0, &square);
+        assert!((state.camera.pos.x + 0.4).abs() < 1e-6, "{:?}", state.camera.pos);
+
+        let mut state = AppState::new();
+        state.camera.scale = 3.0;
+        state.step_alpha = 0.5;
+        state.cursor = Some(winit::dpi::PhysicalPosition::new(100.0, 50.0));
+        // the right edge shows x = 2, and halving the shown scale brings x = 1 there unless the camera moves by 1
+        state.zoom_at_cursor(0.5, &square);
+        assert_eq!(state.camera.scale, 1.5);
+        assert!((state.camera.pos.x - 1.0).abs() < 1e-6, "{:?}", state.camera.pos);
+    }
+
+    #[test]
+    fn fitting_nothing_resets() {
+        let state = fit_camera_state(std::iter::empty(), cgmath::Vector2::new(1.0, 1.0), 0.05, 1.0);
+        assert_eq!(state, CameraState::default());
+    }
+
+    #[test]
+    fn fitting_bounds_are_in_the_camera_frame() {
+        let mut state = AppState::new();
+        let shifted = cgmath::Matrix4::from_translation(cgmath::Vector3::new(2.0, 0.0, 0.0));
+        let handle = state.objects.insert(Object::new(shifted, 0));
+        let counter_rotated = state.objects.insert(Object::new(shifted, 0));
+        state.counter_rotated = Some(counter_rotated);
+        let rot = std::f32::consts::FRAC_PI_2;
+        let frame_bounds = [-0.5, -1.0, 0.5, 1.0];
+        // a quarter turn of the camera turns the objects, and so their boxes, about the world origin
+        let bb = world_bounds(&(state.object_rotation(handle, rot) * shifted), &frame_bounds);
+        assert_close(bb, [-1.0, 1.5, 1.0, 2.5]);
+        let bb = world_bounds(&(state.object_rotation(counter_rotated, rot) * shifted), &frame_bounds);
+        assert_close(bb, [-1.0, -2.5, 1.0, -1.5]);
+    }
 }
@@ -1,5 +1,5 @@
 use winit::{
-    event_loop::{ControlFlow, EventLoop},
+    event_loop::ControlFlow,
 };
 use anyhow::Result;
 use log::LevelFilter;
@@ -10,9 +10,13 @@ fn main() -> Result<()>{
         .filter(Some("wgpu_hal"), LevelFilter::Warn)
         .filter(Some("wgpu_core"), LevelFilter::Warn)
         .init();
-    let event_loop = EventLoop::new()?;
-    event_loop.set_control_flow(ControlFlow::Poll);
     let mut app = App::new();
+    let event_loop = app.create_event_loop()?;
+    event_loop.set_control_flow(ControlFlow::Poll);
+    #[cfg(feature = "gilrs")]
+    if let Err(e) = app.enable_gamepad(fightish::InputBindings::default()) {
+        log::warn!("{e}");
+    }
     event_loop.run_app(&mut app)?;
     Ok(())
 }
@@ -1,12 +1,17 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::iter;
-use crate::buffer_structs::{FrameInfo, ModelFrame, ModelGroup, ModelSegment, ModelShard, ModelVertex};
+use std::io::{BufRead, BufReader, Read};
+use crate::error::{FightishError, Result};
+pub use crate::buffer_structs::{FrameInfo, ModelFrame, ModelGroup, ModelSegment, ModelShard, ModelVertex};
 use crate::render::{DeviceHandle, LayoutEnum};
 use rand::prelude::*;
 use log::*;
 
-// ideally one wouldn't waste memory on having a cpu copy of the model.
+// ideally one wouldn't waste memory on having a cpu copy of the model, which other ModelLoaders can avoid.
 // so this is a simple stupid placeholder storage format
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Model {
     pub vertices: Vec<ModelVertex>,
     pub segments: Vec<ModelSegment>,
@@ -14,104 +19,633 @@ pub struct Model {
     pub frames: Vec<ModelFrame>,
 }
 
+impl Model {
+    /// Curves approximating a full circle in [`Model::push_circle`].
+    const CIRCLE_ARCS: usize = 8;
+
+    /// Starts a new, empty frame after the existing ones, returning its index.
+    /// Shapes pushed afterwards go into it.
+    pub fn push_frame(&mut self) -> usize {
+        let shards = self.shards.len() as i32;
+        let segments = self.segments.len() as i32;
+        self.frames.push(ModelFrame { shard_range: [shards, shards], segment_range: [segments, segments] });
+        self.frames.len() - 1
+    }
+
+    /// Adds a filled circle to the last frame as one shard, returning the shard index.
+    /// The outline is made of quadratic curves, which stay within a fraction of a percent of the radius.
+    pub fn push_circle(&mut self, center: [f32; 2], radius: f32, color: [f32; 4], clip_depth: u32) -> Result<usize> {
+        self.push_outline(circle_outline(center, radius), color, clip_depth)
+    }
+
+    /// Adds a line through `points` to the last frame as one shard, returning the shard index.
+    /// Its ends and corners are shaped by `style`. Repeated points are skipped, and at least two distinct points
+    /// are needed.
+    pub fn push_stroke(
+        &mut self,
+        points: &[[f32; 2]],
+        style: StrokeStyle,
+        color: [f32; 4],
+        clip_depth: u32,
+    ) -> Result<usize> {
+        let mut points = points.to_vec();
+        points.dedup();
+        let h = style.width / 2.0;
+        if points.len() < 2 || !(h > 0.0 && h.is_finite()) {
+            return Err(FightishError::InvalidArgument(
+                "Cannot add a stroke: it needs two distinct points and a positive width.".into(),
+            ));
+        }
+        let add = |a: [f32; 2], b: [f32; 2], s: f32| [a[0] + b[0] * s, a[1] + b[1] * s];
+        // unit directions of the segments, and their left normals scaled to half the width
+        let dirs: Vec<[f32; 2]> = points
+            .windows(2)
+            .map(|w| {
+                let d = [w[1][0] - w[0][0], w[1][1] - w[0][1]];
+                let len = d[0].hypot(d[1]);
+                [d[0] / len, d[1] / len]
+            })
+            .collect();
+        let normal = |d: [f32; 2]| [-d[1] * h, d[0] * h];
+
+        // the pieces overlap, so their union is filled as long as they all wind counterclockwise
+        let mut loops: Vec<Outline> = Vec::new();
+        let mut polygon = |corners: Vec<[f32; 2]>| loops.push(counterclockwise(corners).into_iter().map(|p| (p, None)).collect());
+        for (w, &d) in points.windows(2).zip(&dirs) {
+            let n = normal(d);
+            polygon(vec![add(w[0], n, -1.0), add(w[1], n, -1.0), add(w[1], n, 1.0), add(w[0], n, 1.0)]);
+        }
+        let mut circles = Vec::new();
+        for (i, d) in dirs.windows(2).enumerate() {
+            let p = points[i + 1];
+            let cross = d[0][0] * d[1][1] - d[0][1] * d[1][0];
+            let dot = d[0][0] * d[1][0] + d[0][1] * d[1][1];
+            // straight on, the segments already meet
+            if cross.abs() <= f32::EPSILON && dot > 0.0 { continue; }
+            // the outer side of the corner, where the segments leave a gap
+            let side = if cross > 0.0 { -1.0 } else { 1.0 };
+            let (o0, o1) = (add([0.0; 2], normal(d[0]), side), add([0.0; 2], normal(d[1]), side));
+            match style.join {
+                LineJoin::Round => circles.push(p),
+                LineJoin::Miter | LineJoin::Bevel => {
+                    // the miter tip is where the outer edges meet, 1 / cos of half the turn away in half widths
+                    let half_turn_cos = ((1.0 + dot) / 2.0).max(0.0).sqrt();
+                    let ratio = 1.0 / half_turn_cos;
+                    if style.join == LineJoin::Miter && ratio <= style.miter_limit {
+                        let m = [o0[0] + o1[0], o0[1] + o1[1]];
+                        let m_len = m[0].hypot(m[1]);
+                        let tip = add(p, m, h * ratio / m_len);
+                        polygon(vec![p, add(p, o0, 1.0), tip, add(p, o1, 1.0)]);
+                    } else {
+                        polygon(vec![p, add(p, o0, 1.0), add(p, o1, 1.0)]);
+                    }
+                }
+            }
+        }
+        let ends = [(points[0], dirs[0], -1.0), (points[points.len() - 1], dirs[dirs.len() - 1], 1.0)];
+        for (p, d, outwards) in ends {
+            match style.cap {
+                LineCap::Butt => {}
+                LineCap::Round => circles.push(p),
+                LineCap::Square => {
+                    let (n, beyond) = (normal(d), add(p, d, h * outwards));
+                    polygon(vec![add(p, n, -1.0), add(beyond, n, -1.0), add(beyond, n, 1.0), add(p, n, 1.0)]);
+                }
+            }
+        }
+        loops.extend(circles.into_iter().map(|p| circle_outline(p, h)));
+        self.push_loops(loops, color, clip_depth)
+    }
+
+    /// Adds a filled rectangle from `min` to `max` to the last frame as one shard, returning the shard index.
+    /// The corners are rounded with quadratic curves of `radius`, clamped to half the shorter side.
+    pub fn push_rounded_rect(
+        &mut self,
+        min: [f32; 2],
+        max: [f32; 2],
+        radius: f32,
+        color: [f32; 4],
+        clip_depth: u32,
+    ) -> Result<usize> {
+        let [x0, y0] = [min[0].min(max[0]), min[1].min(max[1])];
+        let [x1, y1] = [min[0].max(max[0]), min[1].max(max[1])];
+        let r = radius.clamp(0.0, (x1 - x0).min(y1 - y0) / 2.0);
+        // counterclockwise from the bottom edge, each corner curving around the rectangle's corner point
+        let corners = [([x1, y0], [-r, 0.0], [0.0, r]), ([x1, y1], [0.0, -r], [-r, 0.0]),
+            ([x0, y1], [r, 0.0], [0.0, -r]), ([x0, y0], [0.0, r], [r, 0.0])];
+        let mut outline = Vec::with_capacity(8);
+        for (corner, into, out_of) in corners {
+            let start = [corner[0] + into[0], corner[1] + into[1]];
+            let end = [corner[0] + out_of[0], corner[1] + out_of[1]];
+            if r > 0.0 {
+                outline.push((start, Some(corner)));
+                outline.push((end, None));
+            } else {
+                outline.push((corner, None));
+            }
+        }
+        self.push_outline(outline, color, clip_depth)
+    }
+
+    /// Adds a closed outline to the last frame as one shard. Each point starts a segment to the next point,
+    /// the last one back to the first, curving towards the control point if there is one.
+    fn push_outline(
+        &mut self,
+        outline: Outline,
+        color: [f32; 4],
+        clip_depth: u32,
+    ) -> Result<usize> {
+        self.push_loops(vec![outline], color, clip_depth)
+    }
+
+    /// Adds closed outlines to the last frame as one shard, filled where their winding numbers add up to
+    /// anything but 0. Each is laid out as for [`Model::push_outline`].
+    fn push_loops(
+        &mut self,
+        loops: Vec<Outline>,
+        color: [f32; 4],
+        clip_depth: u32,
+    ) -> Result<usize> {
+        if self.frames.is_empty() {
+            self.push_frame();
+        }
+        let shards_end = self.shards.len() as i32;
+        let segments_end = self.segments.len() as i32;
+        let frame = self.frames.last_mut().unwrap();
+        if frame.shard_range[1] != shards_end || frame.segment_range[1] != segments_end {
+            return Err(FightishError::ModelValidation(
+                "Cannot add a shape: the last frame doesn't end the model's shards and segments.".into(),
+            ));
+        }
+
+        let mut bb = [f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY];
+        for outline in loops {
+            let first_vertex = self.vertices.len() as i32;
+            let n = outline.len() as i32;
+            self.vertices.extend(outline.iter().map(|&(pos, _)| ModelVertex { pos }));
+            for (i, (pos, control)) in outline.into_iter().enumerate() {
+                // the curve stays within the hull of its end and control points
+                for [x, y] in iter::once(pos).chain(control) {
+                    bb = [bb[0].min(x), bb[1].min(y), bb[2].max(x), bb[3].max(y)];
+                }
+                let control = control.map_or(-1, |pos| {
+                    self.vertices.push(ModelVertex { pos });
+                    self.vertices.len() as i32 - 1
+                });
+                let start = first_vertex + i as i32;
+                let end = first_vertex + (i as i32 + 1) % n;
+                self.segments.push(ModelSegment { idx: [start, end, control, -1] });
+            }
+        }
+        self.shards.push(ModelShard {
+            bb,
+            color,
+            segment_range: [segments_end, self.segments.len() as i32],
+            clip_depth,
+            anim_mode: 0,
+        });
+        let frame = self.frames.last_mut().unwrap();
+        frame.shard_range[1] = self.shards.len() as i32;
+        frame.segment_range[1] = self.segments.len() as i32;
+        Ok(self.shards.len() - 1)
+    }
+
+    /// Exports the model as an SVG document, one `<path>` per shard.
+    /// With `frame_index` set only that frame is exported,
+    /// otherwise every frame is exported in its own `<g>` group.
+    ///
+    /// Shards are painted in increasing `clip_depth` order so deeper shards end up on top,
+    /// matching the depth test, and use the nonzero fill rule like the fragment shader.
+    /// The model's y-axis points up, so y is flipped to fit SVG's downward y-axis.
+    pub fn to_svg(&self, frame_index: Option<usize>) -> Result<String> {
+        let frames = match frame_index {
+            Some(i) => {
+                self.frames.get(i).ok_or(FightishError::FrameOutOfRange { frame_index: i as i32, num_frames: self.frames.len() })?;
+                i..(i + 1)
+            }
+            None => 0..self.frames.len(),
+        };
+        self.svg_document(frames)
+    }
+
+    fn svg_document(&self, frames: std::ops::Range<usize>) -> Result<String> {
+        // view box is the union of the exported shards' bounding boxes
+        let mut bounds = [f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY];
+        let mut body = String::new();
+        for i in frames {
+            let frame = &self.frames[i];
+            let mut shards: Vec<&ModelShard> = (frame.shard_range[0]..frame.shard_range[1])
+                .map(|s| {
+                    self.shards
+                        .get(s as usize)
+                        .ok_or(FightishError::ModelValidation(format!("Frame {}: shard {} out of range.", i, s)))
+                })
+                .collect::<Result<_>>()?;
+            shards.sort_by_key(|s| s.clip_depth);
+
+            body += &format!("  <g id=\"frame-{}\">\n", i);
+            for shard in shards {
+                if shard.segment_range[0] == shard.segment_range[1] { continue; }
+                bounds = [
+                    bounds[0].min(shard.bb[0]),
+                    bounds[1].min(shard.bb[1]),
+                    bounds[2].max(shard.bb[2]),
+                    bounds[3].max(shard.bb[3]),
+                ];
+                body += &format!(
+                    "    <path d=\"{}\" fill=\"{}\" fill-opacity=\"{}\" fill-rule=\"nonzero\"/>\n",
+                    self.shard_svg_path(shard)?,
+                    svg_color(&shard.color),
+                    shard.color[3],
+                );
+            }
+            body.push_str("  </g>\n");
+        }
+        if bounds[0] > bounds[2] { bounds = [0.0, 0.0, 0.0, 0.0]; }
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+            bounds[0],
+            -bounds[3],
+            bounds[2] - bounds[0],
+            bounds[3] - bounds[1],
+        );
+        svg.push_str(&body);
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Model serialization can't fail.")
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Model> {
+        let model: Model = serde_json::from_str(json).map_err(|e| FightishError::ModelValidation(e.to_string()))?;
+        validate(&model)?;
+        Ok(model)
+    }
+
+    /// Serializes the model into the layout read by [`SimpleLoader::from_bytes`], failing if it's invalid.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        validate(self)?;
+        let header = ModelBytesHeader {
+            magic: ModelBytesHeader::MAGIC,
+            version: ModelBytesHeader::VERSION,
+            num_vertices: self.vertices.len() as u32,
+            num_segments: self.segments.len() as u32,
+            num_shards: self.shards.len() as u32,
+            num_frames: self.frames.len() as u32,
+        };
+        let mut bytes = Vec::from(bytemuck::bytes_of(&header));
+        for f in compute_frame_info(&self.frames, &self.shards) {
+            bytes.extend_from_slice(bytemuck::cast_slice(&[f.clip_size, f.shard_size, f.segment_size]));
+        }
+        bytes.extend_from_slice(bytemuck::cast_slice(&self.vertices));
+        bytes.extend_from_slice(bytemuck::cast_slice(&self.segments));
+        bytes.extend_from_slice(bytemuck::cast_slice(&self.shards));
+        bytes.extend_from_slice(bytemuck::cast_slice(&self.frames));
+        Ok(bytes)
+    }
+
+    fn shard_svg_path(&self, shard: &ModelShard) -> Result<String> {
+        let vertex = |i: i32| self.vertices
+            .get(i as usize)
+            .filter(|_| i >= 0)
+            .map(|v| (v.pos[0], 0.0 - v.pos[1])) // avoids printing -0
+            .ok_or(FightishError::ModelValidation(format!("Vertex {} out of range.", i)));
+
+        let mut path = String::new();
+        let mut last_end: Option<i32> = None;
+        for s in shard.segment_range[0]..shard.segment_range[1] {
+            let segment = self.segments
+                .get(s as usize)
+                .ok_or(FightishError::ModelValidation(format!("Segment {} out of range.", s)))?;
+            let [start, end, control, _] = segment.idx;
+            // segments don't have to be chained, so only move when the path is discontinuous
+            if last_end != Some(start) {
+                let (x, y) = vertex(start)?;
+                path += &format!("M {} {} ", x, y);
+            }
+            let (x, y) = vertex(end)?;
+            if control < 0 {
+                path += &format!("L {} {} ", x, y);
+            } else {
+                let (cx, cy) = vertex(control)?;
+                path += &format!("Q {} {} {} {} ", cx, cy, x, y);
+            }
+            last_end = Some(end);
+        }
+        path.push('Z');
+        Ok(path)
+    }
+}
+
+/// A closed outline for [`Model::push_outline`], as each segment's start point and control point if it's curved.
+type Outline = Vec<([f32; 2], Option<[f32; 2]>)>;
+
+/// A counterclockwise circle of `radius` around `center`, as quadratic curves for [`Model::push_outline`].
+fn circle_outline(center: [f32; 2], radius: f32) -> Outline {
+    let step = std::f32::consts::TAU / Model::CIRCLE_ARCS as f32;
+    // the control point where the tangents at the arc's ends meet
+    let control_radius = radius / (step / 2.0).cos();
+    let point = |angle: f32, r: f32| [center[0] + r * angle.cos(), center[1] + r * angle.sin()];
+    (0..Model::CIRCLE_ARCS)
+        .map(|i| {
+            let angle = i as f32 * step;
+            (point(angle, radius), Some(point(angle + step / 2.0, control_radius)))
+        })
+        .collect()
+}
+
+/// `polygon`, reversed if it winds clockwise.
+fn counterclockwise(mut polygon: Vec<[f32; 2]>) -> Vec<[f32; 2]> {
+    let n = polygon.len();
+    let area: f32 = (0..n)
+        .map(|i| {
+            let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+            a[0] * b[1] - a[1] * b[0]
+        })
+        .sum();
+    if area < 0.0 {
+        polygon.reverse();
+    }
+    polygon
+}
+
+/// How [`Model::push_stroke`] ends a line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineCap {
+    /// Flat at the end points.
+    #[default]
+    Butt,
+    /// A half circle around each end point.
+    Round,
+    /// Flat, half the width past each end point.
+    Square,
+}
+
+/// How [`Model::push_stroke`] fills the outer side of a corner.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineJoin {
+    /// The outer edges extended until they meet, or a bevel past [`StrokeStyle::miter_limit`].
+    #[default]
+    Miter,
+    /// A circular arc around the corner point.
+    Round,
+    /// A straight edge between the outer corners of the segments.
+    Bevel,
+}
+
+/// The shape of a line added with [`Model::push_stroke`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    /// The longest miter join, as a multiple of the width, as in SVG. Sharper corners are beveled.
+    pub miter_limit: f32,
+}
+
+impl Default for StrokeStyle {
+    /// A line of width 1 with butt caps and miter joins, limited to 4 widths like SVG's.
+    fn default() -> Self {
+        Self { width: 1.0, cap: LineCap::Butt, join: LineJoin::Miter, miter_limit: 4.0 }
+    }
+}
+
+/// Hex color for a shard color, converting from the linear colors the shader outputs to sRGB.
+fn svg_color(color: &[f32; 4]) -> String {
+    format!("#{:02x}{:02x}{:02x}", linear_to_srgb8(color[0]), linear_to_srgb8(color[1]), linear_to_srgb8(color[2]))
+}
+
+/// Encodes a linear color channel as an 8 bit sRGB one.
+pub(crate) fn linear_to_srgb8(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let srgb = if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    (srgb * 255.0).round() as u8
+}
+
+/// Element counts of a model, and the GPU memory its buffers take once loaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModelStats {
+    pub frames: usize,
+    pub shards: usize,
+    pub segments: usize,
+    pub vertices: usize,
+    pub frame_bytes: u64,
+    pub shard_bytes: u64,
+    pub segment_bytes: u64,
+    pub vertex_bytes: u64,
+}
+
+impl ModelStats {
+    fn new(frames: usize, shards: usize, segments: usize, vertices: usize) -> Self {
+        Self {
+            frames,
+            shards,
+            segments,
+            vertices,
+            frame_bytes: ModelGroup::Frame.size() * frames as u64,
+            shard_bytes: ModelGroup::Shard.size() * shards as u64,
+            segment_bytes: ModelGroup::Segment.size() * segments as u64,
+            vertex_bytes: ModelGroup::Vertex.size() * vertices as u64,
+        }
+    }
+
+    /// Bytes taken by all the model buffers.
+    pub fn gpu_bytes(&self) -> u64 {
+        self.frame_bytes + self.shard_bytes + self.segment_bytes + self.vertex_bytes
+    }
+}
+
+/// Supplies a model's GPU buffers and frame extents to a [`crate::RenderEngine`]. Implement it to keep models
+/// in other ways than [`SimpleLoader`], e.g. streamed in or without a CPU side copy.
+pub trait ModelLoader {
+    /// The clip depth, shard and segment extents and bounds of each frame, known before loading.
+    fn frame_info(&self) -> &[FrameInfo];
+
+    /// The union of a frame's shard bounding boxes, `[min_x, min_y, max_x, max_y]` in local coordinates.
+    /// `None` if the frame is out of range or has no shards.
+    fn frame_bounds(&self, frame_index: usize) -> Option<[f32; 4]> {
+        self.frame_info().get(frame_index).filter(|f| f.shard_size > 0).map(|f| f.bounds)
+    }
+
+    /// Binds the model buffers as made by [`model_bind_group`], `None` until loaded.
+    fn bind_group(&self) -> Option<&wgpu::BindGroup>;
+
+    /// Uploads the model to the device. Called when the engine takes the loader.
+    fn load(&mut self, device: &DeviceHandle);
+
+    /// Bytes of GPU memory taken by the model buffers, for memory reports.
+    fn gpu_bytes(&self) -> u64 {
+        0
+    }
+
+    /// The CPU side model, needed for picking.
+    fn model(&self) -> Option<&Model> {
+        None
+    }
+}
+
 #[derive(Debug)]
 pub struct SimpleLoader {
-    model: Model,
+    model: Option<Model>, // None if uploaded straight from bytes
     frame_info: Vec<FrameInfo>,
+    stats: ModelStats,
     bind_group: Option<wgpu::BindGroup>,
+    gpu_bytes: u64,
 }
 
 impl SimpleLoader {
-    pub fn new(model: Model) -> Self {
-        let frame_info = model
-            .frames
-            .iter()
-            .map(|f| {
-                if f.shard_range[0] == f.shard_range[1] {return FrameInfo{..Default::default()}}
-                FrameInfo {
-                    clip_size: (f.shard_range[0] .. f.shard_range[1])
-                        .map(|i| model.shards[i as usize].clip_depth)
-                        .max().unwrap() + 1,
-                    shard_size: (f.shard_range[1] - f.shard_range[0]) as u32,
-                    segment_size: (f.segment_range[1] - f.segment_range[0]) as u32,
-                }
-            }).collect();
-        info!(
-            "Model information:\n# Frames: {}\n# Shards: {}\n# Segments: {}\n# Vertices: {}",
-            model.frames.len(),
-            model.shards.len(),
-            model.segments.len(),
-            model.vertices.len(),
-        );
-        Self {
-            model,
+    /// Creates a loader for `model`, failing if any of its indices or ranges are out of bounds.
+    pub fn new(model: Model) -> Result<Self> {
+        validate(&model)?;
+        let frame_info = compute_frame_info(&model.frames, &model.shards);
+        let stats = ModelStats::new(model.frames.len(), model.shards.len(), model.segments.len(), model.vertices.len());
+        log_stats(&stats);
+        Ok(Self {
+            model: Some(model),
             frame_info,
+            stats,
             bind_group: None,
+            gpu_bytes: 0,
+        })
+    }
+
+    /// Uploads a model in the layout written by [`Model::to_bytes`] straight from `bytes`,
+    /// e.g. a memory mapped file, without building a CPU side [`Model`].
+    /// The loader is resident on `device` right away, so the engine must use the same device.
+    /// Fails, before allocating anything, if the bytes are truncated or hold an invalid model.
+    pub fn from_bytes(device: &DeviceHandle, bytes: &[u8]) -> Result<Self> {
+        let header_size = size_of::<ModelBytesHeader>();
+        let header: ModelBytesHeader = bytemuck::pod_read_unaligned(
+            bytes.get(..header_size).ok_or(FightishError::ModelValidation("Model bytes too short for header.".into()))?
+        );
+        if header.magic != ModelBytesHeader::MAGIC {
+            return Err(FightishError::ModelValidation("Model bytes have the wrong magic number.".into()));
+        }
+        if header.version != ModelBytesHeader::VERSION {
+            return Err(FightishError::ModelValidation(format!("Unsupported model bytes version {}.", header.version)));
+        }
+
+        // every length is checked before anything is read or allocated
+        let counts = [header.num_vertices, header.num_segments, header.num_shards, header.num_frames];
+        let frame_info_size = header.num_frames as u64 * size_of::<[u32; 3]>() as u64;
+        let sizes: Vec<u64> = ModelGroup::entry_iter().zip(counts).map(|(t, count)| t.size() * count as u64).collect();
+        let expected = header_size as u64 + frame_info_size + sizes.iter().sum::<u64>();
+        if (bytes.len() as u64) < expected {
+            return Err(FightishError::ModelValidation(format!(
+                "Model bytes truncated: {} bytes, but the header needs {}.",
+                bytes.len(),
+                expected,
+            )));
+        }
+        let max_size = device.device.limits().max_storage_buffer_binding_size as u64;
+        if let Some(size) = sizes.iter().find(|&&size| size > max_size) {
+            return Err(FightishError::BufferOverflow(format!(
+                "Model buffer of {} bytes exceeds the device's limit of {}.",
+                size,
+                max_size,
+            )));
         }
+
+        let mut offset = header_size;
+        let mut take = |len: u64| {
+            let slice = &bytes[offset..offset + len as usize];
+            offset += len as usize;
+            slice
+        };
+        let header_frame_info = take(frame_info_size);
+        let slices: Vec<&[u8]> = sizes.iter().map(|&size| take(size)).collect();
+        let vertices: Cow<[ModelVertex]> = cast_bytes(slices[0]);
+        let segments: Cow<[ModelSegment]> = cast_bytes(slices[1]);
+        let shards: Cow<[ModelShard]> = cast_bytes(slices[2]);
+        let frames: Cow<[ModelFrame]> = cast_bytes(slices[3]);
+        validate_parts(header.num_vertices as usize, &segments, &shards, &frames)?;
+        let segments = mark_shared_edges(&vertices, &segments, &shards, &frames);
+        let frame_info = compute_frame_info(&frames, &shards);
+        let matches_header = header_frame_info
+            .chunks_exact(size_of::<[u32; 3]>())
+            .map(bytemuck::pod_read_unaligned::<[u32; 3]>)
+            .zip(&frame_info)
+            .all(|(sizes, f)| sizes == [f.clip_size, f.shard_size, f.segment_size]);
+        if !matches_header {
+            return Err(FightishError::ModelValidation("Frame extents don't match the frames and shards.".into()));
+        }
+
+        let uploads = [slices[0], bytemuck::cast_slice(&segments), slices[2], slices[3]];
+        let buffers: Vec<wgpu::Buffer> = ModelGroup::entry_iter()
+            .zip(counts)
+            .zip(&uploads)
+            .map(|((t, count), slice)| {
+                let buffer = device.create_buffer_with_layout_enum(&t, count as u64);
+                device.queue.write_buffer(&buffer, 0, slice);
+                buffer
+            })
+            .collect();
+        let gpu_bytes = sizes.iter().sum();
+        let stats = ModelStats::new(
+            header.num_frames as usize,
+            header.num_shards as usize,
+            header.num_segments as usize,
+            header.num_vertices as usize,
+        );
+        log_stats(&stats);
+        Ok(Self {
+            model: None,
+            frame_info,
+            stats,
+            bind_group: Some(model_bind_group(device, &buffers[0], &buffers[1], &buffers[2], &buffers[3])),
+            gpu_bytes,
+        })
+    }
+
+    /// The model's element counts and buffer sizes, whether or not it's loaded yet.
+    pub fn stats(&self) -> ModelStats {
+        self.stats
     }
 
+    /// Replaces the model, recreating the model buffers and bind group on `device`.
+    /// On error the current model stays loaded.
+    pub fn reload(&mut self, device: &DeviceHandle, model: Model) -> Result<()> {
+        *self = Self::new(model)?;
+        self.load(device);
+        Ok(())
+    }
+
+    /// The clip depth, shard and segment extents of each frame.
     pub fn frame_info(&self) -> &Vec<FrameInfo> {
         &self.frame_info
     }
 
+    /// The CPU side model, `None` for loaders created with [`SimpleLoader::from_bytes`].
+    pub fn model(&self) -> Option<&Model> {
+        self.model.as_ref()
+    }
+
+    /// Uploads the model to the device. Loaders created with [`SimpleLoader::from_bytes`] are already resident.
     pub fn load(&mut self, device: &DeviceHandle) {
+        let Some(model) = self.model.as_ref() else { return };
 
-        let vertex_model_buffer = device
-            .create_buffer_with_layout_enum(&ModelGroup::Vertex, self.model.vertices.len() as u64);
-        let segment_model_buffer = device
-            .create_buffer_with_layout_enum(&ModelGroup::Segment, self.model.segments.len() as u64);
-        let shard_model_buffer = device
-            .create_buffer_with_layout_enum(&ModelGroup::Shard, self.model.shards.len() as u64);
-        let frame_model_buffer = device
-            .create_buffer_with_layout_enum(&ModelGroup::Frame, self.model.frames.len() as u64);
-        self.bind_group = Some(device
-            .create_bind_group_with_enum_layout_map(
-                &device.create_bind_group_layout::<ModelGroup>(Some("Model bind group layout")),
-                Some("Model bind group"),
-                |t| match t {
-                    ModelGroup::Vertex => vertex_model_buffer.as_entire_binding(),
-                    ModelGroup::Segment => segment_model_buffer.as_entire_binding(),
-                    ModelGroup::Shard => shard_model_buffer.as_entire_binding(),
-                    ModelGroup::Frame => frame_model_buffer.as_entire_binding(),
-                }
-            ));
+        self.bind_group = Some(model_bind_group(
+            device,
+            &device.create_and_upload(&ModelGroup::Vertex, &model.vertices),
+            &device.create_and_upload(
+                &ModelGroup::Segment,
+                &mark_shared_edges(&model.vertices, &model.segments, &model.shards, &model.frames),
+            ),
+            &device.create_and_upload(&ModelGroup::Shard, &model.shards),
+            &device.create_and_upload(&ModelGroup::Frame, &model.frames),
+        ));
 
-        device
-            .queue
-            .write_buffer_with(
-                &vertex_model_buffer,
-                0,
-                wgpu::BufferSize::new(ModelGroup::Vertex.size() * self.model.vertices.len() as u64).unwrap()
-            )
-            .unwrap()
-            .copy_from_slice(bytemuck::cast_slice(self.model.vertices.as_slice()));
-        device
-            .queue
-            .write_buffer_with(
-                &segment_model_buffer,
-                0,
-                wgpu::BufferSize::new(ModelGroup::Segment.size() * self.model.segments.len() as u64).unwrap()
-            )
-            .unwrap()
-            .copy_from_slice(bytemuck::cast_slice(self.model.segments.as_slice()));
-        device
-            .queue
-            .write_buffer_with(
-                &shard_model_buffer,
-                0,
-                wgpu::BufferSize::new(ModelGroup::Shard.size() * self.model.shards.len() as u64).unwrap()
-            )
-            .unwrap()
-            .copy_from_slice(bytemuck::cast_slice(self.model.shards.as_slice()));
-        device
-            .queue
-            .write_buffer_with(
-                &frame_model_buffer,
-                0,
-                wgpu::BufferSize::new(ModelGroup::Frame.size() * self.model.frames.len() as u64).unwrap()
-            )
-            .unwrap()
-            .copy_from_slice(bytemuck::cast_slice(self.model.frames.as_slice()));
+        self.gpu_bytes = self.stats.gpu_bytes();
+    }
+
+    /// Bytes of GPU memory taken by the model buffers, 0 if not loaded.
+    pub fn gpu_bytes(&self) -> u64 {
+        self.gpu_bytes
     }
 
     pub fn bind_group(&self) -> Option<&wgpu::BindGroup> {
@@ -119,6 +653,356 @@ impl SimpleLoader {
     }
 }
 
+impl ModelLoader for SimpleLoader {
+    fn frame_info(&self) -> &[FrameInfo] {
+        SimpleLoader::frame_info(self)
+    }
+
+    fn bind_group(&self) -> Option<&wgpu::BindGroup> {
+        SimpleLoader::bind_group(self)
+    }
+
+    fn load(&mut self, device: &DeviceHandle) {
+        SimpleLoader::load(self, device)
+    }
+
+    fn gpu_bytes(&self) -> u64 {
+        SimpleLoader::gpu_bytes(self)
+    }
+
+    fn model(&self) -> Option<&Model> {
+        SimpleLoader::model(self)
+    }
+}
+
+fn log_stats(stats: &ModelStats) {
+    info!(
+        "Model information:\n# Frames: {}\n# Shards: {}\n# Segments: {}\n# Vertices: {}\n# GPU bytes: {}",
+        stats.frames,
+        stats.shards,
+        stats.segments,
+        stats.vertices,
+        stats.gpu_bytes(),
+    );
+}
+
+/// The models an engine draws from. A model's id, as used by [`crate::Object::model_id`],
+/// is its index in the order the models were added.
+#[derive(Debug)]
+pub struct ModelRegistry<L: ModelLoader = SimpleLoader> {
+    loaders: Vec<L>,
+}
+
+impl<L: ModelLoader> Default for ModelRegistry<L> {
+    fn default() -> Self {
+        Self { loaders: Vec::new() }
+    }
+}
+
+impl<L: ModelLoader> ModelRegistry<L> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a loader, returning its model id.
+    pub fn add(&mut self, loader: L) -> usize {
+        self.loaders.push(loader);
+        self.loaders.len() - 1
+    }
+
+    pub fn get(&self, model_id: usize) -> Option<&L> {
+        self.loaders.get(model_id)
+    }
+
+    pub fn get_mut(&mut self, model_id: usize) -> Option<&mut L> {
+        self.loaders.get_mut(model_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.loaders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.loaders.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &L> {
+        self.loaders.iter()
+    }
+
+    /// Bytes of GPU memory taken by all loaded models.
+    pub fn gpu_bytes(&self) -> u64 {
+        self.loaders.iter().map(|l| l.gpu_bytes()).sum()
+    }
+}
+
+fn compute_frame_info(frames: &[ModelFrame], shards: &[ModelShard]) -> Vec<FrameInfo> {
+    frames
+        .iter()
+        .map(|f| {
+            if f.shard_range[0] == f.shard_range[1] {return FrameInfo{..Default::default()}}
+            FrameInfo {
+                clip_size: (f.shard_range[0] .. f.shard_range[1])
+                    .map(|i| shards[i as usize].clip_depth)
+                    .max().unwrap() + 1,
+                shard_size: (f.shard_range[1] - f.shard_range[0]) as u32,
+                segment_size: (f.segment_range[1] - f.segment_range[0]) as u32,
+                bounds: frame_bounds(f, shards),
+                animated: (f.shard_range[0] .. f.shard_range[1]).any(|i| shards[i as usize].anim_mode != 0),
+            }
+        }).collect()
+}
+
+/// The union of a frame's shard bounding boxes, skipping shards out of range.
+fn frame_bounds(frame: &ModelFrame, shards: &[ModelShard]) -> [f32; 4] {
+    (frame.shard_range[0]..frame.shard_range[1])
+        .filter_map(|i| usize::try_from(i).ok().and_then(|i| shards.get(i)))
+        .fold(
+            [f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY],
+            |b, s| {
+                let [x0, y0, x1, y1] = s.bb;
+                [b[0].min(x0.min(x1)), b[1].min(y0.min(y1)), b[2].max(x0.max(x1)), b[3].max(y0.max(y1))]
+            },
+        )
+}
+
+/// Views `bytes` as elements in place when they're aligned, e.g. in a memory map, or copies them otherwise.
+fn cast_bytes<T: bytemuck::Pod>(bytes: &[u8]) -> Cow<'_, [T]> {
+    match bytemuck::try_cast_slice(bytes) {
+        Ok(elements) => Cow::Borrowed(elements),
+        Err(_) => Cow::Owned(bytes.chunks_exact(size_of::<T>()).map(bytemuck::pod_read_unaligned).collect()),
+    }
+}
+
+/// The segments as uploaded to the GPU: the last index is 1 on edges another shard of the same frame has too,
+/// with the same end and control points, and -1 elsewhere. Antialiasing keeps those edges hard,
+/// so abutting shards meet without fading into each other. Expects a valid model.
+pub fn mark_shared_edges(
+    vertices: &[ModelVertex],
+    segments: &[ModelSegment],
+    shards: &[ModelShard],
+    frames: &[ModelFrame],
+) -> Vec<ModelSegment> {
+    let mut marked: Vec<ModelSegment> = segments
+        .iter()
+        .map(|s| ModelSegment { idx: [s.idx[0], s.idx[1], s.idx[2], -1] })
+        .collect();
+    let pos = |v: i32| vertices[v as usize].pos.map(f32::to_bits);
+    for frame in frames {
+        // the shards with each edge, which may run either way
+        let mut edges: HashMap<_, Vec<(i32, usize)>> = HashMap::new();
+        for s in frame.shard_range[0]..frame.shard_range[1] {
+            let range = shards[s as usize].segment_range;
+            for i in range[0]..range[1] {
+                let [start, end, control, _] = segments[i as usize].idx;
+                let (start, end) = (pos(start), pos(end));
+                let key = (start.min(end), start.max(end), (control >= 0).then(|| pos(control)));
+                edges.entry(key).or_default().push((s, i as usize));
+            }
+        }
+        for users in edges.values().filter(|users| users.iter().any(|&(s, _)| s != users[0].0)) {
+            for &(_, i) in users {
+                marked[i].idx[3] = 1;
+            }
+        }
+    }
+    marked
+}
+
+/// Binds model buffers in the layout the engine's shaders read, for [`ModelLoader::bind_group`].
+/// The buffers hold [`ModelVertex`], [`ModelSegment`], [`ModelShard`] and [`ModelFrame`] elements,
+/// and need storage usage. Segments should be marked with [`mark_shared_edges`].
+pub fn model_bind_group(
+    device: &DeviceHandle,
+    vertex_model_buffer: &wgpu::Buffer,
+    segment_model_buffer: &wgpu::Buffer,
+    shard_model_buffer: &wgpu::Buffer,
+    frame_model_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device
+        .create_bind_group_with_enum_layout_map(
+            &device.create_bind_group_layout::<ModelGroup>(Some("Model bind group layout")),
+            Some("Model bind group"),
+            |t| match t {
+                ModelGroup::Vertex => vertex_model_buffer.as_entire_binding(),
+                ModelGroup::Segment => segment_model_buffer.as_entire_binding(),
+                ModelGroup::Shard => shard_model_buffer.as_entire_binding(),
+                ModelGroup::Frame => frame_model_buffer.as_entire_binding(),
+            }
+        )
+}
+
+/// Header of the binary model layout. It's followed by a `[clip_size, shard_size, segment_size]`
+/// triple of `u32`s per frame, then the vertex, segment, shard and frame arrays back to back.
+/// Everything is in native byte order, i.e. little endian on all supported targets.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ModelBytesHeader {
+    magic: u32,
+    version: u32,
+    num_vertices: u32,
+    num_segments: u32,
+    num_shards: u32,
+    num_frames: u32,
+}
+
+impl ModelBytesHeader {
+    const MAGIC: u32 = u32::from_le_bytes(*b"FTSH");
+    const VERSION: u32 = 1;
+}
+
+/// Parses a model from its text format, which starts with a `fightish-model 1` line followed by
+/// `vertices`, `segments`, `shards` and `frames` sections, each a keyword line and then one item per line:
+///
+/// ```text
+/// vertices
+/// x y
+/// segments
+/// start end [control]
+/// shards
+/// min_x min_y max_x max_y r g b a segment_start segment_end clip_depth [anim_mode]
+/// frames
+/// shard_start shard_end segment_start segment_end
+/// ```
+///
+/// Indices and ranges are into the whole model, ranges are half open, and colors are linear.
+/// Blank lines and lines starting with `#` are ignored.
+pub fn load_from_reader<R: Read>(r: R) -> Result<Model> {
+    let mut model = Model {
+        vertices: Vec::new(),
+        segments: Vec::new(),
+        shards: Vec::new(),
+        frames: Vec::new(),
+    };
+    let mut lines = BufReader::new(r)
+        .lines()
+        .enumerate()
+        .map(|(i, l)| l.map(|l| (i + 1, l)))
+        .filter(|l| l.as_ref().map_or(true, |(_, l)| !l.trim().is_empty() && !l.trim().starts_with('#')));
+    let (_, header) = lines.next().ok_or(FightishError::ModelValidation("Empty model file.".to_string()))??;
+    if header.trim() != MODEL_TEXT_HEADER {
+        return Err(FightishError::ModelValidation(format!("Unsupported model header \"{}\".", header.trim())));
+    }
+
+    let mut section = None;
+    for line in lines {
+        let (line_no, line) = line?;
+        let line = line.trim();
+        if matches!(line, "vertices" | "segments" | "shards" | "frames") {
+            section = Some(line.to_string());
+            continue;
+        }
+        let fields: Vec<f64> = line
+            .split_whitespace()
+            .map(|w| w.parse::<f64>().map_err(|e| FightishError::ModelValidation(format!("Line {}: invalid number \"{}\": {}", line_no, w, e))))
+            .collect::<Result<_>>()?;
+        let int = |i: usize| -> Result<i32> {
+            let v = fields[i];
+            if v.fract() != 0.0 || v < i32::MIN as f64 || v > i32::MAX as f64 {
+                return Err(FightishError::ModelValidation(format!("Line {}: expected an integer, got {}.", line_no, v)));
+            }
+            Ok(v as i32)
+        };
+        let expect = |counts: &[usize]| -> Result<()> {
+            if counts.contains(&fields.len()) { return Ok(()); }
+            Err(FightishError::ModelValidation(format!("Line {}: expected {:?} values, got {}.", line_no, counts, fields.len())))
+        };
+        match section.as_deref() {
+            Some("vertices") => {
+                expect(&[2])?;
+                model.vertices.push(ModelVertex { pos: [fields[0] as f32, fields[1] as f32] });
+            }
+            Some("segments") => {
+                expect(&[2, 3])?;
+                let control = if fields.len() == 3 { int(2)? } else { -1 };
+                model.segments.push(ModelSegment { idx: [int(0)?, int(1)?, control, -1] });
+            }
+            Some("shards") => {
+                expect(&[11, 12])?;
+                let clip_depth = u32::try_from(int(10)?)
+                    .map_err(|_| FightishError::ModelValidation(format!("Line {}: negative clip depth.", line_no)))?;
+                let anim_mode = if fields.len() == 12 {
+                    u32::try_from(int(11)?).map_err(|_| FightishError::ModelValidation(format!("Line {}: negative animation mode.", line_no)))?
+                } else {
+                    0
+                };
+                model.shards.push(ModelShard {
+                    bb: [fields[0] as f32, fields[1] as f32, fields[2] as f32, fields[3] as f32],
+                    color: [fields[4] as f32, fields[5] as f32, fields[6] as f32, fields[7] as f32],
+                    segment_range: [int(8)?, int(9)?],
+                    clip_depth,
+                    anim_mode,
+                });
+            }
+            Some("frames") => {
+                expect(&[4])?;
+                model.frames.push(ModelFrame {
+                    shard_range: [int(0)?, int(1)?],
+                    segment_range: [int(2)?, int(3)?],
+                });
+            }
+            _ => return Err(FightishError::ModelValidation(format!("Line {}: data before any section.", line_no))),
+        }
+    }
+    validate(&model)?;
+    Ok(model)
+}
+
+const MODEL_TEXT_HEADER: &str = "fightish-model 1";
+
+/// Checks that all indices and ranges of the model are in bounds and ordered,
+/// naming the first offending item otherwise.
+fn validate(model: &Model) -> Result<()> {
+    validate_parts(model.vertices.len(), &model.segments, &model.shards, &model.frames)
+}
+
+/// [`validate`] for a model's parts, which needn't be in a [`Model`], only the vertex count matters.
+fn validate_parts(
+    num_vertices: usize,
+    segments: &[ModelSegment],
+    shards: &[ModelShard],
+    frames: &[ModelFrame],
+) -> Result<()> {
+    let check_range = |range: [i32; 2], len: usize| range[0] >= 0 && range[0] <= range[1] && range[1] as usize <= len;
+
+    for (i, segment) in segments.iter().enumerate() {
+        let [start, end, control, _] = segment.idx;
+        for v in [start, end] {
+            if v < 0 || v as usize >= num_vertices {
+                return Err(FightishError::ModelValidation(format!("Segment {}: vertex {} out of range.", i, v)));
+            }
+        }
+        if control >= 0 && control as usize >= num_vertices {
+            return Err(FightishError::ModelValidation(format!("Segment {}: control vertex {} out of range.", i, control)));
+        }
+    }
+    let mut segments_end = 0;
+    for (i, shard) in shards.iter().enumerate() {
+        if !check_range(shard.segment_range, segments.len()) {
+            return Err(FightishError::ModelValidation(format!("Shard {}: invalid segment range {:?}.", i, shard.segment_range)));
+        }
+        if shard.segment_range[0] < segments_end {
+            return Err(FightishError::ModelValidation(format!("Shard {}: segment range {:?} overlaps the previous shard.", i, shard.segment_range)));
+        }
+        segments_end = shard.segment_range[1];
+    }
+    for (i, frame) in frames.iter().enumerate() {
+        if !check_range(frame.shard_range, shards.len()) {
+            return Err(FightishError::ModelValidation(format!("Frame {}: invalid shard range {:?}.", i, frame.shard_range)));
+        }
+        if !check_range(frame.segment_range, segments.len()) {
+            return Err(FightishError::ModelValidation(format!("Frame {}: invalid segment range {:?}.", i, frame.segment_range)));
+        }
+        for s in frame.shard_range[0]..frame.shard_range[1] {
+            let range = shards[s as usize].segment_range;
+            if range[0] < range[1] && (range[0] < frame.segment_range[0] || range[1] > frame.segment_range[1]) {
+                return Err(FightishError::ModelValidation(format!("Frame {}: shard {} segments {:?} outside the frame's segments.", i, s, range)));
+            }
+        }
+    }
+    Ok(())
+}
+
 pub mod check {
     use super::*;
 
@@ -145,14 +1029,14 @@ pub mod check {
             color: [1.0, 0.0, 0.0, 1.0],
             segment_range: [0, 4],
             clip_depth: 0,
-            filler: 0,
+            anim_mode: 0,
         },
         ModelShard {
             bb: [-0.2f32, 0.2f32, 1.3f32, 1.5f32],
             color: [0.0, 0.0, 1.0, 1.0],
             segment_range: [4, 7],
             clip_depth: 1,
-            filler: 0,
+            anim_mode: 0,
         },];
 
     pub const FRAMES: &[ModelFrame] = &[
@@ -170,12 +1054,27 @@ pub mod check {
     }}
 }
 
+/// Seed of [`make_load_test_default`].
+pub const LOAD_TEST_SEED: [u8; 32] = *b"hflkajafdsahlvbsdfhqueesaydailay";
+
+/// [`make_load_test`] with the fixed [`LOAD_TEST_SEED`].
+pub fn make_load_test_default(
+    num_frames: u32,
+    num_frame_shards: std::ops::Range<u32>,
+    num_shard_segments: std::ops::Range<u32>,
+) -> Model {
+    make_load_test(LOAD_TEST_SEED, num_frames, num_frame_shards, num_shard_segments)
+}
+
+/// A random model of `num_frames` frames, each with a number of shards drawn from `num_frame_shards`
+/// made of a number of segments drawn from `num_shard_segments`. The same seed gives the same model.
 pub fn make_load_test(
+    seed: [u8; 32],
     num_frames: u32,
     num_frame_shards: std::ops::Range<u32>,
     num_shard_segments: std::ops::Range<u32>,
 ) -> Model {
-    let mut rng = StdRng::from_seed(b"hflkajafdsahlvbsdfhqueesaydailay".clone());
+    let mut rng = StdRng::from_seed(seed);
     let mut vertices:  Vec<ModelVertex> = Vec::new();
     let mut segments: Vec<ModelSegment> = Vec::new();
     let mut shards: Vec<ModelShard> = Vec::new();
@@ -198,7 +1097,7 @@ pub fn make_load_test(
                     color: [0., 0., 0., 1.],
                     segment_range: [shard_segment_offset, shard_segment_offset],
                     clip_depth: shard,
-                    filler: 0,
+                    anim_mode: 0,
                 });
                 continue;
             }
@@ -248,7 +1147,7 @@ pub fn make_load_test(
                 color: [rng.gen(), rng.gen(), rng.gen(), 1.0],
                 segment_range: [shard_segment_offset, segments.len() as i32],
                 clip_depth: shard,
-                filler: 0,
+                anim_mode: 0,
             })
         }
         frames.push(ModelFrame {
@@ -263,3 +1162,142 @@ pub fn make_load_test(
         frames,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame of one triangle shard, with corners at (0, 0), (2, 0) and (0, 1).
+    fn triangle(color: [f32; 4]) -> Model {
+        Model {
+            vertices: vec![
+                ModelVertex { pos: [0.0, 0.0] },
+                ModelVertex { pos: [2.0, 0.0] },
+                ModelVertex { pos: [0.0, 1.0] },
+            ],
+            segments: vec![
+                ModelSegment { idx: [0, 1, -1, -1] },
+                ModelSegment { idx: [1, 2, -1, -1] },
+                ModelSegment { idx: [2, 0, -1, -1] },
+            ],
+            shards: vec![ModelShard {
+                bb: [0.0, 0.0, 2.0, 1.0],
+                color,
+                segment_range: [0, 3],
+                clip_depth: 0,
+                anim_mode: 0,
+            }],
+            frames: vec![ModelFrame { shard_range: [0, 1], segment_range: [0, 3] }],
+        }
+    }
+
+    #[test]
+    fn only_edges_between_shards_are_shared() {
+        let mut model = Model::default();
+        model.push_rounded_rect([-1.0, 0.0], [0.0, 1.0], 0.0, [1.0; 4], 0).unwrap();
+        model.push_rounded_rect([0.0, 0.0], [1.0, 1.0], 0.0, [1.0; 4], 0).unwrap();
+        // a later frame with the same edge doesn't share it
+        model.push_frame();
+        model.push_rounded_rect([0.0, 0.0], [1.0, 1.0], 0.0, [1.0; 4], 0).unwrap();
+        let marked = mark_shared_edges(&model.vertices, &model.segments, &model.shards, &model.frames);
+        let shared: Vec<[[f32; 2]; 2]> = marked
+            .iter()
+            .filter(|s| s.idx[3] == 1)
+            .map(|s| [model.vertices[s.idx[0] as usize].pos, model.vertices[s.idx[1] as usize].pos])
+            .collect();
+        assert_eq!(shared.len(), 2);
+        assert!(shared.iter().all(|[a, b]| a[0] == 0.0 && b[0] == 0.0 && a[1] != b[1]), "{:?}", shared);
+    }
+
+    #[test]
+    fn animated_shards_mark_their_frame() {
+        let mut model = triangle([1.0; 4]);
+        let mut shard = model.shards[0];
+        shard.anim_mode = 1;
+        model.shards.push(shard);
+        model.frames.push(ModelFrame { shard_range: [1, 2], segment_range: [0, 3] });
+        let animated: Vec<bool> = compute_frame_info(&model.frames, &model.shards).iter().map(|f| f.animated).collect();
+        assert_eq!(animated, [false, true]);
+    }
+
+    #[test]
+    fn svg_exports_triangle_path() {
+        let svg = triangle([1.0, 0.0, 0.5, 0.25]).to_svg(Some(0)).unwrap();
+        // y is flipped, so the view box starts at the negated max y
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 -1 2 1\">\n"), "{}", svg);
+        assert!(svg.contains(
+            "<path d=\"M 0 0 L 2 0 L 0 -1 L 0 0 Z\" fill=\"#ff00bc\" fill-opacity=\"0.25\" fill-rule=\"nonzero\"/>"
+        ), "{}", svg);
+        assert!(triangle([1.0; 4]).to_svg(Some(1)).is_err());
+    }
+
+    #[test]
+    fn pushed_shapes_have_valid_ranges() {
+        let mut model = Model::default();
+        // a circle starts the first frame, a rounded rectangle joins it
+        assert_eq!(model.push_circle([0.0, 0.0], 1.0, [1.0; 4], 0).unwrap(), 0);
+        assert_eq!(model.push_rounded_rect([-1.0, -1.0], [1.0, 0.5], 0.25, [1.0; 4], 1).unwrap(), 1);
+        // a rectangle without rounding in a new frame
+        assert_eq!(model.push_frame(), 1);
+        assert_eq!(model.push_rounded_rect([2.0, 2.0], [0.0, 0.0], 0.0, [1.0; 4], 0).unwrap(), 2);
+
+        let segment_ranges: Vec<[i32; 2]> = model.shards.iter().map(|s| s.segment_range).collect();
+        assert_eq!(segment_ranges, vec![[0, 8], [8, 16], [16, 20]]);
+        assert_eq!(model.frames, vec![
+            ModelFrame { shard_range: [0, 2], segment_range: [0, 16] },
+            ModelFrame { shard_range: [2, 3], segment_range: [16, 20] },
+        ]);
+        assert_eq!(model.shards[2].bb, [0.0, 0.0, 2.0, 2.0]);
+        // 8 circle points and their controls, 8 rounded rectangle points and 4 corner controls, 4 corners
+        assert_eq!(model.vertices.len(), 32);
+        assert!(model.segments[16..].iter().all(|s| s.idx[2] == -1 && s.idx[3] == -1));
+        assert!(model.segments[..16].iter().all(|s| s.idx[3] == -1));
+        assert!(SimpleLoader::new(model).is_ok());
+    }
+
+    #[test]
+    fn strokes_need_a_direction_and_width() {
+        let mut model = Model::default();
+        let style = StrokeStyle::default();
+        assert!(model.push_stroke(&[[0.0, 0.0], [0.0, 0.0]], style, [1.0; 4], 0).is_err());
+        assert!(model.push_stroke(&[[0.0, 0.0], [1.0, 0.0]], StrokeStyle { width: 0.0, ..style }, [1.0; 4], 0).is_err());
+        // two segments, a miter join and two square caps, each a quadrilateral
+        let square = StrokeStyle { cap: LineCap::Square, ..style };
+        let points = [[0.0, 0.0], [2.0, 0.0], [2.0, 0.0], [2.0, 1.0]];
+        assert_eq!(model.push_stroke(&points, square, [1.0; 4], 0).unwrap(), 0);
+        assert_eq!(model.shards[0].segment_range, [0, 20]);
+        assert_eq!(model.shards[0].bb, [-0.5, -0.5, 2.5, 1.5]);
+        assert!(SimpleLoader::new(model).is_ok());
+    }
+
+    #[test]
+    fn load_tests_are_valid_across_seeds() {
+        let mut empty_shards = 0;
+        let mut empty_frames = 0;
+        for seed in 0..64u8 {
+            let model = make_load_test([seed; 32], 4, 0..4, 0..5);
+            assert_eq!(model, make_load_test([seed; 32], 4, 0..4, 0..5));
+            empty_shards += model.shards.iter().filter(|s| s.segment_range[0] == s.segment_range[1]).count();
+            empty_frames += model.frames.iter().filter(|f| f.shard_range[0] == f.shard_range[1]).count();
+            let loader = SimpleLoader::new(model).unwrap_or_else(|e| panic!("seed {}: {}", seed, e));
+            assert_eq!(loader.frame_info().len(), 4);
+        }
+        // the sweep covers shards without segments and frames without shards
+        assert!(empty_shards > 0 && empty_frames > 0);
+    }
+
+    #[test]
+    fn to_bytes_rejects_invalid_model() {
+        let mut model = check::model();
+        model.frames[0].shard_range = [0, 3];
+        assert!(matches!(model.to_bytes(), Err(FightishError::ModelValidation(_))));
+        assert!(check::model().to_bytes().is_ok());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let model = check::model();
+        assert_eq!(Model::from_json(&model.to_json()).unwrap(), model);
+    }
+}
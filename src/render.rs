@@ -1,73 +1,185 @@
-use std::sync::Arc;
+use std::fmt;
+use std::sync::{Arc, Mutex};
 use std::ops::Deref;
-use anyhow::anyhow;
+use crate::error::{FightishError, Result};
+use log::{error, info, warn};
 use winit::window::Window;
+use crate::scene::SceneData;
 
 #[derive(Debug)]
 pub struct RenderContext {
-    instance: wgpu::Instance,
-    devices: Vec<DeviceHandle>,
+    instance: Arc<wgpu::Instance>,
+    power_preference: wgpu::PowerPreference,
+    devices: Vec<Option<DeviceHandle>>, // None once pruned, so the ids of the others stay valid
+}
+
+impl Default for RenderContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RenderContext {
+    /// A context on the primary backends, preferring a high performance adapter.
     pub fn new() -> Self {
+        Self::with_options(wgpu::Backends::PRIMARY, wgpu::PowerPreference::HighPerformance)
+    }
+
+    /// A context restricted to `backends`, e.g. to force Vulkan, which requests adapters with `power_preference`.
+    pub fn with_options(backends: wgpu::Backends, power_preference: wgpu::PowerPreference) -> Self {
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
+            backends,
             ..Default::default()
         });
         Self {
-            instance,
+            instance: Arc::new(instance),
+            power_preference,
             devices: Vec::new(),
         }
     }
 
+    /// The device with the given id. Panics if it was dropped by [`RenderContext::prune_lost_devices`].
     pub fn get_device_by_id(&self, id: DeviceId) -> &DeviceHandle {
-        &self.devices[*id]
-    }
-
-    async fn device(&mut self, compatible_surface: Option<&wgpu::Surface<'_>>) -> Option<DeviceId> {
-        let mut compatible_device = match compatible_surface {
-            Some(s) => self
-                .devices
-                .iter()
-                .enumerate()
-                .find(|(_, d)| d.adapter.is_surface_supported(s))
-                .map(|(index, _)| DeviceId(index)),
-            None => (!self.devices.is_empty()).then_some(DeviceId(0, )),
-        };
-        if compatible_device.is_none() {
-            compatible_device = self.new_device(compatible_surface).await;
+        self.devices[*id].as_ref().expect("Device used after it was pruned.")
+    }
+
+    /// The name, backend, type and driver of a device's adapter.
+    pub fn adapter_info(&self, id: DeviceId) -> wgpu::AdapterInfo {
+        self.get_device_by_id(id).adapter().get_info()
+    }
+
+    /// Creates a surface for the window, to create a target from once there's a device for it.
+    pub fn create_surface(&self, window: Arc<Window>) -> Result<wgpu::Surface<'static>> {
+        Ok(self.instance.create_surface(window)?)
+    }
+
+    /// An existing device that can present to the surface, or any device without a surface. Lost devices are skipped.
+    pub fn compatible_device(&self, compatible_surface: Option<&wgpu::Surface<'_>>) -> Option<DeviceId> {
+        self
+            .devices
+            .iter()
+            .enumerate()
+            .filter_map(|(index, d)| Some((index, d.as_ref()?)))
+            .filter(|(_, d)| d.lost().is_none())
+            .find(|(_, d)| compatible_surface.is_none_or(|s| d.adapter.is_surface_supported(s)))
+            .map(|(index, _)| DeviceId(index))
+    }
+
+    /// Opens new devices like the context does, without borrowing it, e.g. to open one on another thread.
+    pub fn device_opener(&self) -> DeviceOpener {
+        DeviceOpener {
+            instance: self.instance.clone(),
+            power_preference: self.power_preference,
+        }
+    }
+
+    /// Adds a device opened with [`RenderContext::device_opener`].
+    pub fn add_device(&mut self, device: DeviceHandle) -> DeviceId {
+        self.devices.push(Some(device));
+        DeviceId(self.devices.len() - 1)
+    }
+
+    /// Drops the lost devices, once the targets and engines using them are gone. Their ids aren't reused,
+    /// and must not be used afterwards. Returns how many were dropped.
+    pub fn prune_lost_devices(&mut self) -> usize {
+        let mut pruned = 0;
+        for device in &mut self.devices {
+            if device.as_ref().is_some_and(|d| d.lost().is_some()) {
+                *device = None;
+                pruned += 1;
+            }
         }
-        compatible_device
+        pruned
     }
 
-    async fn new_device(&mut self, compatible_surface: Option<&wgpu::Surface<'_>>) -> Option<DeviceId> {
+    /// An existing device compatible with the surface, or else a newly opened one.
+    /// Fails with [`FightishError::NoCompatibleDevice`] if the system has no suitable adapter.
+    pub async fn device(&mut self, compatible_surface: Option<&wgpu::Surface<'_>>) -> Result<DeviceId> {
+        match self.compatible_device(compatible_surface) {
+            Some(id) => Ok(id),
+            None => {
+                let device = self.device_opener().open(compatible_surface).await?;
+                Ok(self.add_device(device))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceOpener {
+    instance: Arc<wgpu::Instance>,
+    power_preference: wgpu::PowerPreference,
+}
+
+impl DeviceOpener {
+    pub async fn open(&self, compatible_surface: Option<&wgpu::Surface<'_>>) -> Result<DeviceHandle> {
         let adapter = self.instance.request_adapter(
             &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
+                power_preference: self.power_preference,
                 compatible_surface,
                 force_fallback_adapter: false,
             }
         )
-            .await?;
+            .await
+            .ok_or(FightishError::NoCompatibleDevice)?;
 
+        // line polygon mode is only used for the optional wireframe overlay, timestamps for pass timings
+        let required_features = adapter.features()
+            & (wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::TIMESTAMP_QUERY);
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::empty(),
+                required_features,
                 required_limits: wgpu::Limits::default(), // if web need to take into account limits
                 label: None,
                 memory_hints: Default::default(),
             },
             None,
         )
-            .await.ok()?;
-        let id = DeviceId(self.devices.len());
-        self.devices.push(DeviceHandle {
+            .await
+            .map_err(DeviceError::Request)?;
+        let lost = Arc::new(Mutex::new(None));
+        let lost_flag = lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            // also called when the device is dropped normally
+            if reason != wgpu::DeviceLostReason::Dropped {
+                error!("Device lost ({:?}): {}", reason, message);
+                *lost_flag.lock().unwrap() = Some(reason);
+            }
+        });
+        Ok(DeviceHandle {
             adapter,
             device,
-            queue
-        });
-        Some(id)
+            queue,
+            lost,
+        })
+    }
+}
+
+/// Why a device couldn't be opened, or stopped working. A missing adapter is
+/// [`FightishError::NoCompatibleDevice`] instead.
+#[derive(Debug)]
+pub enum DeviceError {
+    /// The adapter refused to open a device.
+    Request(wgpu::RequestDeviceError),
+    /// The device was lost after opening, e.g. to a driver reset.
+    Lost(wgpu::DeviceLostReason),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Request(e) => write!(f, "Cannot open device: {}", e),
+            Self::Lost(reason) => write!(f, "Device lost: {:?}", reason),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Request(e) => Some(e),
+            _ => None,
+        }
     }
 }
 
@@ -87,10 +199,25 @@ pub struct DeviceHandle {
     adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
+    lost: Arc<Mutex<Option<wgpu::DeviceLostReason>>>, // set by the device lost callback
 }
 
 impl DeviceHandle {
-    pub fn create_bind_group_layout<T: LayoutEnum> (&self, label: wgpu::Label<'_>) -> wgpu::BindGroupLayout {
+    pub fn adapter(&self) -> &wgpu::Adapter {
+        &self.adapter
+    }
+
+    /// Why the device was lost, if it was. Lost devices can't render anymore and have to be replaced.
+    pub fn lost(&self) -> Option<wgpu::DeviceLostReason> {
+        *self.lost.lock().unwrap()
+    }
+
+    /// Fails with [`DeviceError::Lost`] if the device was lost.
+    pub fn check_lost(&self) -> Result<(), DeviceError> {
+        self.lost().map_or(Ok(()), |reason| Err(DeviceError::Lost(reason)))
+    }
+
+    pub(crate) fn create_bind_group_layout<T: LayoutEnum> (&self, label: wgpu::Label<'_>) -> wgpu::BindGroupLayout {
         let entries : Vec<_> = T::entry_iter()
             .map(|t| T::layout_entry(&t))
             .collect();
@@ -102,14 +229,46 @@ impl DeviceHandle {
             })
     }
 
-    pub fn create_buffer_with_layout_enum<T: LayoutEnum> (&self, ty: &T, count: u64) -> wgpu::Buffer {
+    /// Returns `sample_count` if all `formats` support it for multisampling, otherwise 1.
+    pub fn supported_sample_count(&self, formats: &[wgpu::TextureFormat], sample_count: u32) -> u32 {
+        let supported = formats.iter().all(|&f| {
+            self.adapter
+                .get_texture_format_features(f)
+                .flags
+                .sample_count_supported(sample_count)
+        });
+        if supported { sample_count } else { 1 }
+    }
+
+    /// Whether `format` is a color format that can be rendered to.
+    pub fn is_color_renderable(&self, format: wgpu::TextureFormat) -> bool {
+        !format.has_depth_aspect()
+            && !format.has_stencil_aspect()
+            && self
+                .adapter
+                .get_texture_format_features(format)
+                .allowed_usages
+                .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+    }
+
+    pub(crate) fn create_buffer_with_layout_enum<T: LayoutEnum> (&self, ty: &T, count: u64) -> wgpu::Buffer {
         self
             .device
             .create_buffer(&ty.buffer_descriptor(count))
     }
 
+    /// Creates a buffer sized for `data`, which must be laid out as `ty` expects, and queues the upload of `data`.
+    /// For example, a [`crate::model::ModelLoader`] uploads its model arrays with [`crate::model::ModelGroup`]s.
+    pub fn create_and_upload<T: LayoutEnum, D: bytemuck::Pod>(&self, ty: &T, data: &[D]) -> wgpu::Buffer {
+        let buffer = self.create_buffer_with_layout_enum(ty, data.len() as u64);
+        if !data.is_empty() {
+            self.queue.write_buffer(&buffer, 0, bytemuck::cast_slice(data));
+        }
+        buffer
+    }
+
     /// Creates a bind group using a wgpu layout and a map sending enums to binding resources.
-    pub fn create_bind_group_with_enum_layout_map< 'l, 'a, T: LayoutEnum, F>
+    pub(crate) fn create_bind_group_with_enum_layout_map< 'l, 'a, T: LayoutEnum, F>
     (
         &self,
         layout: &wgpu::BindGroupLayout,
@@ -137,6 +296,7 @@ pub struct RenderTarget<'s, D: TargetTextureDongle> {
     surface: wgpu::Surface<'s>,
     config: wgpu::SurfaceConfiguration,
     format: wgpu::TextureFormat,
+    capabilities: wgpu::SurfaceCapabilities, // of the surface with the target's adapter
 
     minimized: bool,
     device_id: DeviceId,
@@ -150,8 +310,10 @@ impl<D: TargetTextureDongle> RenderTarget<'_, D> {
     pub fn surface(&self) -> &wgpu::Surface<'_> { &self.surface }
     pub fn surface_format(&self) -> &wgpu::TextureFormat { &self.format }
     pub fn device_id(&self) -> DeviceId { self.device_id }
+    /// The formats, present modes and alpha modes the surface supports with the target's adapter.
+    pub fn capabilities(&self) -> &wgpu::SurfaceCapabilities { &self.capabilities }
     pub fn is_live(&self) -> bool {
-        return !self.minimized
+        !self.minimized
     }
 
     pub fn get_data(&self) -> TargetData {
@@ -171,21 +333,30 @@ impl<D: TargetTextureDongle> RenderTarget<'_, D> {
         context.get_device_by_id(self.device_id)
     }
 
-    pub async fn create<'a, 'b> (context: &'a mut RenderContext, window: Arc<Window>, dongle: D) -> anyhow::Result<RenderTarget<'b, D>> {
+    /// Creates a target from a surface made with [`RenderContext::create_surface`], rendering with a device
+    /// compatible with it. Uses the surface format `preferred_format` if the surface supports it,
+    /// otherwise the first sRGB format it supports.
+    pub fn from_surface<'b>(
+        context: &RenderContext,
+        window: Arc<Window>,
+        surface: wgpu::Surface<'b>,
+        device_id: DeviceId,
+        dongle: D,
+        preferred_format: Option<wgpu::TextureFormat>,
+    ) -> Result<RenderTarget<'b, D>> {
         let size = window.inner_size();
         if size.width == 0 || size.height == 0 {
-            return Err(anyhow!("Cannot create zero size window."))
+            return Err(FightishError::ZeroSizeTarget)
         }
-        let surface_target: wgpu::SurfaceTarget<'b> = window.clone().into();
-        let surface: wgpu::Surface<'b> = context.instance.create_surface(surface_target)?;
-        let device_id = context.device(Some(&surface)).await.ok_or(anyhow!("No compatible device."))?;
-
         let surface_caps = surface
             .get_capabilities(&context.get_device_by_id(device_id).adapter);
 
-        let format = surface_caps.formats.iter()
-            .find(|f| f.is_srgb())
-            .copied()
+        if let Some(f) = preferred_format.filter(|f| !surface_caps.formats.contains(f)) {
+            warn!("Surface doesn't support {:?}, using its default format.", f);
+        }
+        let format = preferred_format
+            .filter(|f| surface_caps.formats.contains(f))
+            .or(surface_caps.formats.iter().find(|f| f.is_srgb()).copied())
             .unwrap_or(surface_caps.formats[0]);
         // note surface_caps.formats only supposed to be empty when surface and adapter not compatible
         // so taking first should be ok.
@@ -205,11 +376,12 @@ impl<D: TargetTextureDongle> RenderTarget<'_, D> {
             surface,
             config,
             format,
+            capabilities: surface_caps,
             device_id,
             window,
             minimized: false,
             texture_handler: TargetTextureHandler::new(
-                context, dongle, device_id, size.width, size.height
+                context, dongle, device_id, format, size.width, size.height
             ),
         })
     }
@@ -226,6 +398,85 @@ impl<D: TargetTextureDongle> RenderTarget<'_, D> {
         }
     }
 
+    /// Requests a present mode, falling back to the first mode the surface supports if needed.
+    pub fn set_present_mode(&mut self, context: &RenderContext, mode: wgpu::PresentMode) {
+        let supported = &self.capabilities.present_modes;
+        self.config.present_mode = match mode {
+            // wgpu resolves the automatic modes to a supported one itself
+            wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => mode,
+            _ if supported.contains(&mode) => mode,
+            _ => {
+                warn!("Present mode {:?} unsupported, falling back to {:?}.", mode, supported[0]);
+                supported[0]
+            }
+        };
+        if !self.minimized {
+            self.configure(context);
+        }
+    }
+
+    /// Sets how many frames the surface may queue ahead of the display, 2 by default.
+    /// 1 lowers the input latency at the cost of throughput; 0 is invalid and treated as 1.
+    pub fn set_frame_latency(&mut self, context: &RenderContext, latency: u32) {
+        if latency == 0 {
+            warn!("Frame latency must be at least 1, using 1.");
+        }
+        self.config.desired_maximum_frame_latency = latency.max(1);
+        if !self.minimized {
+            self.configure(context);
+        }
+    }
+
+    /// Requests how the surface is composited with what's behind the window, e.g. `PreMultiplied`
+    /// for a transparent window, falling back to the first mode the surface supports if needed.
+    pub fn set_alpha_mode(&mut self, context: &RenderContext, mode: wgpu::CompositeAlphaMode) {
+        let supported = &self.capabilities.alpha_modes;
+        self.config.alpha_mode = match mode {
+            // wgpu picks a supported mode itself
+            wgpu::CompositeAlphaMode::Auto => mode,
+            _ if supported.contains(&mode) => mode,
+            _ => {
+                warn!("Alpha mode {:?} unsupported, falling back to {:?}.", mode, supported[0]);
+                supported[0]
+            }
+        };
+        if !self.minimized {
+            self.configure(context);
+        }
+    }
+
+    /// Gets the surface texture to draw the next frame to, reconfiguring the surface once if it's lost or outdated.
+    /// Returns `None` if the frame has to be skipped, after requesting a redraw to try again.
+    pub fn acquire(&mut self, context: &RenderContext) -> Result<Option<wgpu::SurfaceTexture>> {
+        match self.surface.get_current_texture() {
+            Ok(output) => Ok(Some(output)),
+            Err(wgpu::SurfaceError::Timeout) => {
+                self.window.request_redraw();
+                Ok(None)
+            }
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                // retry once, if the surface is still unusable the next frame tries again.
+                info!("Surface lost or outdated, reconfiguring.");
+                self.reconfigure(context);
+                match self.surface.get_current_texture() {
+                    Ok(output) => Ok(Some(output)),
+                    Err(wgpu::SurfaceError::OutOfMemory) => Err(wgpu::SurfaceError::OutOfMemory.into()),
+                    Err(e) => {
+                        warn!("Skipping frame: {e}");
+                        self.window.request_redraw();
+                        Ok(None)
+                    }
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Configures the surface again from the stored configuration, e.g. after it was lost.
+    pub fn reconfigure(&mut self, context: &RenderContext) {
+        self.configure(context);
+    }
+
     fn configure(&mut self, context: &RenderContext) {
         let device = self.device(context);
         self.surface.configure(&device.device, &self.config);
@@ -234,6 +485,154 @@ impl<D: TargetTextureDongle> RenderTarget<'_, D> {
     pub fn texture_views(&self) -> &Vec<wgpu::TextureView> {
         self.texture_handler.views()
     }
+
+    /// The view of a named attachment, see [`TargetTextureDongle::attachment_view`].
+    pub fn attachment(&self, name: &str) -> Option<&wgpu::TextureView> {
+        self.texture_handler.attachment(name)
+    }
+
+    pub fn dongle(&self) -> &D {
+        self.texture_handler.dongle()
+    }
+}
+
+/// A render target backed by an offscreen color texture instead of a window surface.
+#[derive(Debug)]
+pub struct OffscreenTarget<D: TargetTextureDongle> {
+    color: wgpu::Texture,
+    color_view: wgpu::TextureView,
+    format: wgpu::TextureFormat,
+    device_id: DeviceId,
+
+    texture_handler: TargetTextureHandler<D>,
+}
+
+impl<D: TargetTextureDongle> OffscreenTarget<D> {
+    pub fn color_view(&self) -> &wgpu::TextureView { &self.color_view }
+    pub fn format(&self) -> &wgpu::TextureFormat { &self.format }
+    pub fn device_id(&self) -> DeviceId { self.device_id }
+
+    pub fn device<'a>(&self, context: &'a RenderContext) -> &'a DeviceHandle {
+        context.get_device_by_id(self.device_id)
+    }
+
+    pub fn get_data(&self) -> TargetData {
+        TargetData {
+            vp_x: 0,
+            vp_y: 0,
+            vp_width: self.color.width(),
+            vp_height: self.color.height(),
+        }
+    }
+
+    pub async fn create(
+        context: &mut RenderContext,
+        dongle: D,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(FightishError::ZeroSizeTarget)
+        }
+        let device_id = context.device(None).await?;
+        let color = context
+            .get_device_by_id(device_id)
+            .device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("Offscreen color texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+        let color_view = color.create_view(&wgpu::TextureViewDescriptor::default());
+        Ok(Self {
+            color,
+            color_view,
+            format,
+            device_id,
+            texture_handler: TargetTextureHandler::new(context, dongle, device_id, format, width, height),
+        })
+    }
+
+    pub fn texture_views(&self) -> &Vec<wgpu::TextureView> {
+        self.texture_handler.views()
+    }
+
+    /// The view of a named attachment, see [`TargetTextureDongle::attachment_view`].
+    pub fn attachment(&self, name: &str) -> Option<&wgpu::TextureView> {
+        self.texture_handler.attachment(name)
+    }
+
+    pub fn dongle(&self) -> &D {
+        self.texture_handler.dongle()
+    }
+
+    /// Reads the color texture back, rows tightly packed from the top.
+    /// Blocks until the GPU has finished all submitted work.
+    pub fn read_pixels(&self, context: &RenderContext) -> Result<Vec<u8>> {
+        let device = self.device(context);
+        let (width, height) = (self.color.width(), self.color.height());
+        let pixel_size = self.format
+            .block_copy_size(None)
+            .ok_or(FightishError::InvalidTarget(format!("Cannot read back format {:?}.", self.format)))?;
+        let row_size = width * pixel_size;
+        // buffer rows must be padded to the copy alignment
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row_size = row_size.div_ceil(align) * align;
+
+        let buffer = device.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen readback buffer"),
+            size: padded_row_size as u64 * height as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Offscreen readback encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            self.color.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(height),
+                },
+            },
+            self.color.size(),
+        );
+        device.queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |r| { let _ = sender.send(r); });
+        device.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().map_err(anyhow::Error::from)??;
+
+        let mapped = buffer.slice(..).get_mapped_range();
+        let pixels = mapped
+            .chunks(padded_row_size as usize)
+            .flat_map(|row| &row[..row_size as usize])
+            .copied()
+            .collect();
+        drop(mapped);
+        buffer.unmap();
+        Ok(pixels)
+    }
 }
 
 #[derive(Debug)]
@@ -244,7 +643,15 @@ struct TargetTextureHandler<D: TargetTextureDongle> {
 }
 
 impl<D: TargetTextureDongle> TargetTextureHandler<D> {
-    pub fn new(context: &RenderContext, dongle: D, device_id: DeviceId, width: u32, height: u32) -> Self {
+    pub fn new(
+        context: &RenderContext,
+        mut dongle: D,
+        device_id: DeviceId,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        dongle.configure(context.get_device_by_id(device_id), format);
         let mut this = Self {
             textures: Vec::new(),
             views: Vec::new(),
@@ -278,23 +685,181 @@ impl<D: TargetTextureDongle> TargetTextureHandler<D> {
 
     pub fn views(&self) -> &Vec<wgpu::TextureView> { &self.views }
 
+    pub fn attachment(&self, name: &str) -> Option<&wgpu::TextureView> {
+        self.dongle.attachment_view(name).and_then(|i| self.views.get(i))
+    }
+
+    pub fn dongle(&self) -> &D { &self.dongle }
+
 }
 
+/// Name of the depth buffer attachment, which engines render with.
+pub const DEPTH_ATTACHMENT: &str = "depth";
+/// Name of the multisampled color attachment, which engines render into when multisampling.
+pub const COLOR_ATTACHMENT: &str = "color";
+
 pub trait TargetTextureDongle {
+    /// Called once by the target before any textures are created, with its device and color format.
+    #[allow(unused_variables)]
+    fn configure(&mut self, device: &DeviceHandle, format: wgpu::TextureFormat) {}
+
     fn num_textures(&self) -> usize;
 
     fn num_views(&self) -> usize { self.num_textures() }
 
-    fn texture_desc(&self, index: usize, width: u32, height: u32) -> wgpu::TextureDescriptor;
+    fn texture_desc(&self, index: usize, width: u32, height: u32) -> wgpu::TextureDescriptor<'_>;
 
     /// The texture index associated with a given view.
     fn view_index(&self, index: usize) -> usize { index }
 
     #[allow(unused_variables)]
-    fn view_desc(&self, index: usize) -> wgpu::TextureViewDescriptor { wgpu::TextureViewDescriptor::default() }
+    fn view_desc(&self, index: usize) -> wgpu::TextureViewDescriptor<'_> { wgpu::TextureViewDescriptor::default() }
+
+    /// The view of the attachment called `name`, e.g. [`DEPTH_ATTACHMENT`], if the dongle has one.
+    #[allow(unused_variables)]
+    fn attachment_view(&self, name: &str) -> Option<usize> { None }
+
+    /// The view to render color into instead of the target, which then receives the resolved result.
+    /// Needed when multisampling. Defaults to the [`COLOR_ATTACHMENT`] view, if it's multisampled.
+    fn color_view_index(&self) -> Option<usize> {
+        self.attachment_view(COLOR_ATTACHMENT).filter(|&i| self.view_sample_count(i) > 1)
+    }
+
+    /// The sample count of a given view's texture.
+    fn view_sample_count(&self, index: usize) -> u32 {
+        self.texture_desc(self.view_index(index), 1, 1).sample_count
+    }
+
+    /// The format of a given view, falling back to the format of its texture.
+    fn view_format(&self, index: usize) -> wgpu::TextureFormat {
+        self.view_desc(index)
+            .format
+            .unwrap_or_else(|| self.texture_desc(self.view_index(index), 1, 1).format)
+    }
 }
 
-#[derive(Debug)]
+/// One texture of an [`AttachmentDongle`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttachmentSpec {
+    pub name: &'static str,
+    /// `None` uses the target's color format.
+    pub format: Option<wgpu::TextureFormat>,
+    pub usage: wgpu::TextureUsages,
+    pub sample_count: u32,
+}
+
+impl AttachmentSpec {
+    /// A single sampled attachment, usable as render attachment and texture binding.
+    pub fn new(name: &'static str, format: Option<wgpu::TextureFormat>) -> Self {
+        Self {
+            name,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            sample_count: 1,
+        }
+    }
+
+    pub fn with_usage(mut self, usage: wgpu::TextureUsages) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count.max(1);
+        self
+    }
+}
+
+/// A dongle built from a list of attachments, with one texture and view each, looked up by name.
+/// Engines need a [`DEPTH_ATTACHMENT`], and when multisampling a [`COLOR_ATTACHMENT`] in the target's format
+/// with the same sample count. Sample counts the device doesn't support fall back to 1.
+#[derive(Debug, Clone)]
+pub struct AttachmentDongle {
+    attachments: Vec<AttachmentSpec>,
+    color_format: wgpu::TextureFormat, // set by the target in `configure`
+}
+
+impl Default for AttachmentDongle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AttachmentDongle {
+    pub fn new() -> Self {
+        Self {
+            attachments: Vec::new(),
+            color_format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        }
+    }
+
+    /// Adds an attachment, whose view comes after those of the previous ones.
+    /// Fails if an attachment of the same name was added already.
+    pub fn with_attachment(mut self, spec: AttachmentSpec) -> Result<Self> {
+        if self.attachments.iter().any(|a| a.name == spec.name) {
+            return Err(FightishError::InvalidTarget(format!("Attachment {:?} added twice.", spec.name)));
+        }
+        self.attachments.push(spec);
+        Ok(self)
+    }
+
+    /// The attachments, with sample counts after any fallback once the target was created.
+    pub fn attachments(&self) -> &[AttachmentSpec] {
+        &self.attachments
+    }
+}
+
+impl TargetTextureDongle for AttachmentDongle {
+    fn configure(&mut self, device: &DeviceHandle, format: wgpu::TextureFormat) {
+        self.color_format = format;
+        // multisampled attachments are rendered together, so all their formats must support the count.
+        let formats: Vec<wgpu::TextureFormat> = self.attachments
+            .iter()
+            .filter(|a| a.sample_count > 1)
+            .map(|a| a.format.unwrap_or(format))
+            .collect();
+        for attachment in self.attachments.iter_mut().filter(|a| a.sample_count > 1) {
+            let sample_count = device.supported_sample_count(&formats, attachment.sample_count);
+            if sample_count != attachment.sample_count {
+                warn!(
+                    "Sample count {} of attachment {:?} unsupported, falling back to {}.",
+                    attachment.sample_count,
+                    attachment.name,
+                    sample_count,
+                );
+                attachment.sample_count = sample_count;
+            }
+        }
+    }
+
+    fn num_textures(&self) -> usize {
+        self.attachments.len()
+    }
+
+    fn texture_desc(&self, index: usize, width: u32, height: u32) -> wgpu::TextureDescriptor<'_> {
+        let attachment = &self.attachments[index];
+        wgpu::TextureDescriptor {
+            label: Some(attachment.name),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: attachment.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: attachment.format.unwrap_or(self.color_format),
+            usage: attachment.usage,
+            view_formats: &[],
+        }
+    }
+
+    fn attachment_view(&self, name: &str) -> Option<usize> {
+        self.attachments.iter().position(|a| a.name == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct TargetData {
     pub vp_x: i32,
     pub vp_y: i32,
@@ -302,6 +867,99 @@ pub struct TargetData {
     pub vp_height: u32,
 }
 
+impl TargetData {
+    /// The whole target as a viewport, e.g. to split for [`RenderEngine::render_multi`](crate::engine::RenderEngine::render_multi).
+    pub fn viewport(&self) -> Viewport {
+        Viewport {
+            x: self.vp_x,
+            y: self.vp_y,
+            width: self.vp_width,
+            height: self.vp_height,
+        }
+    }
+}
+
+/// A rectangle of a render target, in pixels from the top left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    pub fn from_scene(scene_data: &SceneData) -> Self {
+        Self {
+            x: scene_data.vp_x,
+            y: scene_data.vp_y,
+            width: scene_data.vp_width,
+            height: scene_data.vp_height,
+        }
+    }
+
+    /// Splits into `count` side by side columns of equal width, left to right.
+    /// The last column takes any leftover pixels, so the columns cover the viewport exactly.
+    pub fn split_columns(&self, count: u32) -> Vec<Viewport> {
+        let count = count.max(1);
+        let width = self.width / count;
+        (0..count)
+            .map(|i| Viewport {
+                x: self.x + (i * width) as i32,
+                width: if i + 1 == count { self.width - i * width } else { width },
+                ..*self
+            })
+            .collect()
+    }
+
+    /// Splits into `count` stacked rows of equal height, top to bottom.
+    /// The last row takes any leftover pixels.
+    pub fn split_rows(&self, count: u32) -> Vec<Viewport> {
+        let count = count.max(1);
+        let height = self.height / count;
+        (0..count)
+            .map(|i| Viewport {
+                y: self.y + (i * height) as i32,
+                height: if i + 1 == count { self.height - i * height } else { height },
+                ..*self
+            })
+            .collect()
+    }
+
+    /// The part of this viewport inside a target of `[width, height]` pixels, as a scissor rectangle
+    /// `[x, y, width, height]`. `None` if no part of it is inside.
+    pub fn scissor(&self, target_size: [u32; 2]) -> Option<[u32; 4]> {
+        let clip = |start: i32, size: u32, limit: u32| {
+            let end = (start as i64 + size as i64).clamp(0, limit as i64) as u32;
+            let start = (start as i64).clamp(0, limit as i64) as u32;
+            (start, end - start)
+        };
+        let (x, width) = clip(self.x, self.width, target_size[0]);
+        let (y, height) = clip(self.y, self.height, target_size[1]);
+        (width > 0 && height > 0).then_some([x, y, width, height])
+    }
+
+    /// Restricts a render pass to this viewport, within a target of `[width, height]` pixels.
+    /// Returns false, leaving the pass as it was, if no part of the viewport is inside the target,
+    /// in which case nothing should be drawn. wgpu rejects viewports only partly inside, which must be cut down
+    /// to their [`Viewport::scissor`] first, with the camera narrowed to match as
+    /// [`RenderEngine::render_multi`](crate::engine::RenderEngine::render_multi) does.
+    pub fn apply(&self, render_pass: &mut wgpu::RenderPass, target_size: [u32; 2]) -> bool {
+        let Some([x, y, width, height]) = self.scissor(target_size) else { return false };
+        render_pass.set_viewport(
+            self.x as f32,
+            self.y as f32,
+            self.width as f32,
+            self.height as f32,
+            0.0,
+            1.0,
+        );
+        render_pass.set_scissor_rect(x, y, width, height);
+        true
+    }
+}
+
+/// The bindings of a bind group, each with the size of its elements and how its buffers are made.
 pub trait LayoutEnum {
     type Iter : Iterator<Item = Self>;
     fn entry_iter() -> Self::Iter;
@@ -310,3 +968,51 @@ pub trait LayoutEnum {
     fn layout_entry(&self) -> wgpu::BindGroupLayoutEntry;
     fn buffer_descriptor(&self, count: u64) -> wgpu::BufferDescriptor<'static>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scissors_stay_in_the_target() {
+        let viewport = |x, y, width, height| Viewport { x, y, width, height };
+        assert_eq!(viewport(2, 3, 4, 5).scissor([10, 10]), Some([2, 3, 4, 5]));
+        assert_eq!(viewport(-2, 6, 4, 5).scissor([10, 10]), Some([0, 6, 2, 4]));
+        assert_eq!(viewport(8, -1, 4, 20).scissor([10, 10]), Some([8, 0, 2, 10]));
+        assert_eq!(viewport(-4, 0, 4, 5).scissor([10, 10]), None);
+        assert_eq!(viewport(10, 0, 4, 5).scissor([10, 10]), None);
+        assert_eq!(viewport(0, 0, 0, 5).scissor([10, 10]), None);
+        assert_eq!(viewport(i32::MAX, 0, u32::MAX, 5).scissor([10, 10]), None);
+    }
+
+    #[test]
+    fn lost_devices_are_pruned() {
+        let mut context = RenderContext::with_options(wgpu::Backends::all(), wgpu::PowerPreference::LowPower);
+        let id = match pollster::block_on(context.device(None)) {
+            Ok(id) => id,
+            Err(FightishError::NoCompatibleDevice) => return,
+            Err(e) => panic!("{e}"),
+        };
+        let device = &context.get_device_by_id(id).device;
+        device.destroy();
+        device.poll(wgpu::Maintain::Wait);
+        assert!(context.get_device_by_id(id).check_lost().is_err());
+        assert_eq!(context.prune_lost_devices(), 1);
+        assert_eq!(context.compatible_device(None), None);
+        // a new device gets a new id
+        let new_id = pollster::block_on(context.device(None)).unwrap();
+        assert_ne!(new_id, id);
+        assert_eq!(context.prune_lost_devices(), 0);
+    }
+
+    #[test]
+    fn attachment_names_are_unique() {
+        let dongle = AttachmentDongle::new()
+            .with_attachment(AttachmentSpec::new(DEPTH_ATTACHMENT, Some(wgpu::TextureFormat::Depth24Plus)))
+            .unwrap()
+            .with_attachment(AttachmentSpec::new(COLOR_ATTACHMENT, None).with_sample_count(4))
+            .unwrap();
+        assert_eq!(dongle.attachment_view(COLOR_ATTACHMENT), Some(1));
+        assert!(dongle.with_attachment(AttachmentSpec::new(DEPTH_ATTACHMENT, None)).is_err());
+    }
+}
@@ -1,15 +1,508 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use cgmath::{Matrix3, Matrix4, SquareMatrix, Vector2, Vector4};
+
+/// Everything the engine needs to draw one view of a scene, e.g. to render without the [`crate::App`] shell
+/// through [`crate::headless`].
 pub struct SceneData {
+    /// The viewport in target pixels, from the top left corner.
     pub vp_x: i32,
     pub vp_y: i32,
     pub vp_width: u32,
     pub vp_height: u32,
 
-    pub camera_tf: cgmath::Matrix4<f32>,
+    /// Maps clip space, -1 to 1 across the viewport, to world space.
+    pub camera_tf: Matrix4<f32>,
+    /// Seconds since some fixed start, exposed to the shaders for time dependent effects.
+    pub time: f32,
+    /// Multiplied into the colors of every object, on top of their own tints. White leaves colors unchanged.
+    pub global_tint: [f32; 4],
 
     pub objects: Vec<Object>
 }
 
+/// A 2D placement, scaled first, then rotated counterclockwise by `rotation` radians, then translated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Transform2D {
+    pub translation: Vector2<f32>,
+    pub rotation: f32,
+    pub scale: Vector2<f32>,
+}
+
+impl Default for Transform2D {
+    fn default() -> Self {
+        Self { translation: Vector2::new(0.0, 0.0), rotation: 0.0, scale: Vector2::new(1.0, 1.0) }
+    }
+}
+
+impl Transform2D {
+    pub fn to_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation.extend(0.0))
+            * Matrix4::from_angle_z(cgmath::Rad(self.rotation))
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, 1.0)
+    }
+
+    /// The placement a transform makes of the z = 0 plane, `None` if it skews, has perspective or collapses
+    /// the x axis. Only the x, y and w of the result are compared, as only they are drawn.
+    pub fn from_matrix(tf: &Matrix4<f32>) -> Option<Self> {
+        let x_axis = Vector2::new(tf.x.x, tf.x.y);
+        let scale_x = x_axis.x.hypot(x_axis.y);
+        if scale_x == 0.0 { return None; }
+        let rotation = x_axis.y.atan2(x_axis.x);
+        // the y axis, rotated back, is only scaled if there's no skew
+        let scale_y = tf.y.y * rotation.cos() - tf.y.x * rotation.sin();
+        let result = Self {
+            translation: Vector2::new(tf.w.x, tf.w.y),
+            rotation,
+            scale: Vector2::new(scale_x, scale_y),
+        };
+        let m = result.to_matrix();
+        let tolerance = 1.0e-5 * (1.0 + scale_x.max(scale_y.abs()) + result.translation.x.abs().max(result.translation.y.abs()));
+        let close = |a: Vector4<f32>, b: Vector4<f32>| {
+            (a.x - b.x).abs() <= tolerance && (a.y - b.y).abs() <= tolerance && (a.w - b.w).abs() <= tolerance
+        };
+        (close(m.x, tf.x) && close(m.y, tf.y) && close(m.w, tf.w)).then_some(result)
+    }
+}
+
+impl From<Transform2D> for Matrix4<f32> {
+    fn from(tf: Transform2D) -> Self {
+        tf.to_matrix()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Object {
-    pub world_local_tf: cgmath::Matrix4<f32>,
+    /// Only the z = 0 plane of local space is drawn, and only the x, y and w of the result matter, so it can
+    /// carry perspective and skew. w must stay positive over the object, i.e. it must be in front of the viewer.
+    /// The z of the result is ignored, since layers and clip depths decide the draw order, and the camera is
+    /// always 2D affine.
+    pub world_local_tf: Matrix4<f32>,
+    /// Which of the engine's models the frame is from, 0 for the one the engine was created with.
+    pub model_id: usize,
     pub frame_index: i32,
-}
\ No newline at end of file
+    /// Multiplied into the colors of the frame's shards, e.g. for team colors.
+    pub tint: [f32; 4],
+    /// Draw order between objects. An object is drawn on top of every object in a lower layer, whatever the
+    /// `clip_depth`s of their shards; within a layer, later objects in the scene are on top. Shard `clip_depth`s
+    /// only order shards within an object.
+    pub layer: f32,
+    /// Hidden objects stay in the scene but aren't drawn, picked or given GPU work.
+    pub visible: bool,
+    /// An animation overriding `frame_index`, and the scene time it started at.
+    pub animation: Option<(Arc<Animation>, f32)>,
+    /// Seconds per turn of a spin about the local y axis, seen in perspective as for card flips.
+    pub flip_period: Option<f32>,
+    /// Draws the object as a quad over its frame's bounds, textured from `[min_u, min_v, max_u, max_v]` of the
+    /// engine's sprite atlas instead of with the frame's shards. The min v is at the top, the bounds' max y.
+    /// Sprites are drawn in the scene's order with the other objects, see [`crate::engine::RenderEngine::set_blend_mode`].
+    pub sprite: Option<[f32; 4]>,
+}
+
+impl Object {
+    /// An untinted object.
+    pub fn new(world_local_tf: Matrix4<f32>, frame_index: i32) -> Self {
+        Self {
+            world_local_tf,
+            model_id: 0,
+            frame_index,
+            tint: [1.0; 4],
+            layer: 0.0,
+            visible: true,
+            animation: None,
+            flip_period: None,
+            sprite: None,
+        }
+    }
+
+    /// An untinted object placed by a 2D transform, see [`Transform2D`].
+    pub fn from_transform(tf: Transform2D, frame_index: i32) -> Self {
+        Self::new(tf.to_matrix(), frame_index)
+    }
+
+    /// Distance of the viewer from the local origin for flips, in local units.
+    /// Local x has to stay below it for w to stay positive.
+    pub const FLIP_DISTANCE: f32 = 4.0;
+
+    /// The transform at scene time `time`, with the flip applied if there is one.
+    pub fn local_tf_at(&self, time: f32) -> Matrix4<f32> {
+        let Some(period) = self.flip_period.filter(|&p| p > 0.0) else { return self.world_local_tf };
+        let rotation = Matrix4::from_angle_y(cgmath::Rad(std::f32::consts::TAU * time / period));
+        // w grows with the distance from the viewer, which looks down the z axis
+        let mut perspective = Matrix4::identity();
+        perspective.z.w = 1.0 / Self::FLIP_DISTANCE;
+        self.world_local_tf * perspective * rotation
+    }
+
+    /// The frame to show at scene time `time`, from the animation if there is one.
+    pub fn frame_index_at(&self, time: f32) -> i32 {
+        self.animation
+            .as_ref()
+            .and_then(|(animation, start)| animation.frame_at(time - start))
+            .unwrap_or(self.frame_index)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationFrame {
+    pub frame_index: i32,
+    /// Seconds the frame is shown for.
+    pub duration: f32,
+}
+
+/// A sequence of model frames shown one after another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub frames: Vec<AnimationFrame>,
+    /// Whether to start over after the last frame, instead of holding it.
+    pub looping: bool,
+}
+
+impl Animation {
+    /// The frame shown `elapsed` seconds after the start, `None` if there are no frames.
+    pub fn frame_at(&self, elapsed: f32) -> Option<i32> {
+        let total: f32 = self.frames.iter().map(|f| f.duration).sum();
+        let mut t = if self.looping && total > 0.0 { elapsed.rem_euclid(total) } else { elapsed.max(0.0) };
+        for frame in &self.frames {
+            if t < frame.duration { return Some(frame.frame_index); }
+            t -= frame.duration;
+        }
+        self.frames.last().map(|f| f.frame_index)
+    }
+}
+
+/// Refers to an object in an [`ObjectList`]. Handles of removed objects stay invalid
+/// even after their slot is reused, since the slot's generation changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectHandle {
+    index: usize,
+    generation: u32,
+}
+
+/// Objects addressed by stable handles, with removed slots reused for new objects.
+#[derive(Debug, Default)]
+pub struct ObjectList {
+    slots: Vec<(u32, Option<Object>)>, // generation and object of each slot
+    free: Vec<usize>,
+}
+
+impl ObjectList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, object: Object) -> ObjectHandle {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.1 = Some(object);
+                ObjectHandle { index, generation: slot.0 }
+            }
+            None => {
+                self.slots.push((0, Some(object)));
+                ObjectHandle { index: self.slots.len() - 1, generation: 0 }
+            }
+        }
+    }
+
+    /// Removes and returns the object, or `None` if the handle is stale.
+    pub fn remove(&mut self, handle: ObjectHandle) -> Option<Object> {
+        let slot = self.slots.get_mut(handle.index)?;
+        if slot.0 != handle.generation { return None; }
+        let object = slot.1.take()?;
+        slot.0 = slot.0.wrapping_add(1);
+        self.free.push(handle.index);
+        Some(object)
+    }
+
+    pub fn get(&self, handle: ObjectHandle) -> Option<&Object> {
+        self.slots
+            .get(handle.index)
+            .filter(|slot| slot.0 == handle.generation)
+            .and_then(|slot| slot.1.as_ref())
+    }
+
+    pub fn get_mut(&mut self, handle: ObjectHandle) -> Option<&mut Object> {
+        self.slots
+            .get_mut(handle.index)
+            .filter(|slot| slot.0 == handle.generation)
+            .and_then(|slot| slot.1.as_mut())
+    }
+
+    /// Removes all objects. Later inserts refill the slots in order, so they keep their insertion order.
+    pub fn clear(&mut self) {
+        for slot in &mut self.slots {
+            if slot.1.take().is_some() {
+                slot.0 = slot.0.wrapping_add(1);
+            }
+        }
+        // every slot is free now, popped from the front
+        self.free = (0..self.slots.len()).rev().collect();
+    }
+
+    /// The live objects, in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = &Object> {
+        self.slots.iter().filter_map(|slot| slot.1.as_ref())
+    }
+
+    /// The handles of the live objects, in the same order as [`ObjectList::iter`].
+    pub fn handles(&self) -> impl Iterator<Item = ObjectHandle> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.1.is_some())
+            .map(|(index, slot)| ObjectHandle { index, generation: slot.0 })
+    }
+}
+
+/// A uniform grid over the world space bounding boxes of a scene's objects,
+/// used to narrow a point query down to the few objects worth an exact test.
+#[derive(Debug)]
+pub struct SceneIndex {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    object_cells: Vec<Option<ObjectCells>>,
+    oversized: Vec<usize>, // objects covering too many cells, candidates for every query
+}
+
+/// Where an indexed object is kept.
+#[derive(Debug, Clone, Copy)]
+enum ObjectCells {
+    /// The inclusive cell range covered by the object.
+    Range([i32; 4]),
+    Oversized,
+}
+
+impl SceneIndex {
+    /// Objects covering more cells than this aren't put in the grid, and are candidates for every query instead.
+    /// Keeps a stage sized object or a small cell size from inserting millions of entries.
+    pub const MAX_OBJECT_CELLS: u64 = 1024;
+
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0, "Scene index cell size must be positive.");
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            object_cells: Vec::new(),
+            oversized: Vec::new(),
+        }
+    }
+
+    /// Builds an index over `objects`, where `frame_bounds[i]` is the local bounding box
+    /// `[min_x, min_y, max_x, max_y]` of frame `i`.
+    pub fn build(cell_size: f32, objects: &[Object], frame_bounds: &[[f32; 4]]) -> Self {
+        let mut index = Self::new(cell_size);
+        for (i, o) in objects.iter().enumerate() {
+            index.update(i, o, frame_bounds);
+        }
+        index
+    }
+
+    /// Reinserts the object at `index`, e.g. after it moved.
+    /// Hidden objects, and objects with a frame index outside `frame_bounds`, are left out of the index.
+    pub fn update(&mut self, index: usize, object: &Object, frame_bounds: &[[f32; 4]]) {
+        self.remove(index);
+        if !object.visible { return; }
+        let Some(bb) = usize::try_from(object.frame_index).ok().and_then(|i| frame_bounds.get(i)) else {
+            return;
+        };
+        let bb = world_bounds(&object.world_local_tf, bb);
+        if !bb.iter().all(|c| c.is_finite()) { return; }
+        let (x0, y0) = self.cell(bb[0], bb[1]);
+        let (x1, y1) = self.cell(bb[2], bb[3]);
+        let span = |min: i32, max: i32| (max as i64 - min as i64 + 1) as u64;
+        let cells = if span(x0, x1).saturating_mul(span(y0, y1)) > Self::MAX_OBJECT_CELLS {
+            self.oversized.push(index);
+            ObjectCells::Oversized
+        } else {
+            for x in x0..=x1 {
+                for y in y0..=y1 {
+                    self.cells.entry((x, y)).or_default().push(index);
+                }
+            }
+            ObjectCells::Range([x0, y0, x1, y1])
+        };
+        if self.object_cells.len() <= index {
+            self.object_cells.resize(index + 1, None);
+        }
+        self.object_cells[index] = Some(cells);
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        let Some(Some(cells)) = self.object_cells.get_mut(index).map(Option::take) else {
+            return;
+        };
+        let ObjectCells::Range([x0, y0, x1, y1]) = cells else {
+            self.oversized.retain(|&i| i != index);
+            return;
+        };
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                if let Some(cell) = self.cells.get_mut(&(x, y)) {
+                    cell.retain(|&i| i != index);
+                    if cell.is_empty() { self.cells.remove(&(x, y)); }
+                }
+            }
+        }
+    }
+
+    /// Indices of objects whose bounding box may contain `point`, in increasing order.
+    /// Candidates still need an exact test.
+    pub fn query_point(&self, point: Vector2<f32>) -> Vec<usize> {
+        let mut candidates: Vec<usize> = self.cells
+            .get(&self.cell(point.x, point.y))
+            .into_iter()
+            .flatten()
+            .chain(&self.oversized)
+            .copied()
+            .filter(|&i| self.object_cells[i].is_some())
+            .collect();
+        candidates.sort_unstable();
+        candidates
+    }
+
+    fn cell(&self, x: f32, y: f32) -> (i32, i32) {
+        ((x / self.cell_size).floor() as i32, (y / self.cell_size).floor() as i32)
+    }
+}
+
+/// Inverts `tf` restricted to the z = 0 plane, mapping the x and y of a drawn point back to the plane.
+/// Unlike the full inverse this is exact when `tf` mixes z into x, y or w, as perspective transforms do.
+/// The result ignores z, and puts the plane point in x, y and w.
+pub fn planar_inverse(tf: &Matrix4<f32>) -> Option<Matrix4<f32>> {
+    let planar = Matrix3::new(tf.x.x, tf.x.y, tf.x.w, tf.y.x, tf.y.y, tf.y.w, tf.w.x, tf.w.y, tf.w.w);
+    let inverse = planar.invert()?;
+    Some(Matrix4::new(
+        inverse.x.x, inverse.x.y, 0.0, inverse.x.z,
+        inverse.y.x, inverse.y.y, 0.0, inverse.y.z,
+        0.0, 0.0, 0.0, 0.0,
+        inverse.z.x, inverse.z.y, 0.0, inverse.z.z,
+    ))
+}
+
+/// Transforms a local bounding box `[min_x, min_y, max_x, max_y]` and returns the bounding box of the result.
+pub fn world_bounds(tf: &Matrix4<f32>, bb: &[f32; 4]) -> [f32; 4] {
+    [(bb[0], bb[1]), (bb[0], bb[3]), (bb[2], bb[1]), (bb[2], bb[3])]
+        .into_iter()
+        .map(|(x, y)| {
+            let v = tf * Vector4::new(x, y, 0.0, 1.0);
+            (v.x / v.w, v.y / v.w)
+        })
+        .fold(
+            [f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY],
+            |b, (x, y)| [b[0].min(x), b[1].min(y), b[2].max(x), b[3].max(y)],
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector3;
+    use rand::prelude::*;
+
+    const FRAME_BOUNDS: [[f32; 4]; 1] = [[-0.5, -0.5, 0.5, 0.5]];
+
+    fn placed(x: f32, y: f32, scale: f32) -> Object {
+        Object::new(Matrix4::from_translation(Vector3::new(x, y, 0.0)) * Matrix4::from_scale(scale), 0)
+    }
+
+    /// Indices of the objects whose bounding box contains `point`, testing every object.
+    fn brute_force(objects: &[Object], point: Vector2<f32>) -> Vec<usize> {
+        (0..objects.len())
+            .filter(|&i| {
+                let [x0, y0, x1, y1] = world_bounds(&objects[i].world_local_tf, &FRAME_BOUNDS[0]);
+                (x0..=x1).contains(&point.x) && (y0..=y1).contains(&point.y)
+            })
+            .collect()
+    }
+
+    fn random_scene(rng: &mut StdRng, num_objects: usize) -> Vec<Object> {
+        (0..num_objects)
+            .map(|_| placed(rng.gen_range(-100.0..100.0), rng.gen_range(-100.0..100.0), rng.gen_range(0.1..3.0)))
+            .collect()
+    }
+
+    #[test]
+    fn cleared_lists_refill_in_order() {
+        let mut list = ObjectList::new();
+        let old: Vec<ObjectHandle> = (0..4).map(|i| list.insert(Object::new(Matrix4::identity(), i))).collect();
+        list.remove(old[1]);
+        list.remove(old[2]);
+        list.clear();
+        let new: Vec<ObjectHandle> = (10..15).map(|i| list.insert(Object::new(Matrix4::identity(), i))).collect();
+        assert_eq!(list.iter().map(|o| o.frame_index).collect::<Vec<_>>(), vec![10, 11, 12, 13, 14]);
+        assert!(old.iter().all(|&h| list.get(h).is_none()));
+        assert!(new.iter().zip(10..).all(|(&h, i)| list.get(h).unwrap().frame_index == i));
+    }
+
+    #[test]
+    fn oversized_objects_are_candidates_everywhere() {
+        let objects = [placed(0.0, 0.0, 1.0e6), placed(5.0, 5.0, 0.1), placed(0.0, 0.0, 1.0e30)];
+        let mut index = SceneIndex::build(0.01, &objects, &FRAME_BOUNDS);
+        assert_eq!(index.oversized, vec![0, 2]);
+        assert!(index.cells.len() as u64 <= SceneIndex::MAX_OBJECT_CELLS);
+        assert_eq!(index.query_point(Vector2::new(5.0, 5.0)), vec![0, 1, 2]);
+        assert_eq!(index.query_point(Vector2::new(-300.0, 7.0)), vec![0, 2]);
+        index.remove(0);
+        assert_eq!(index.query_point(Vector2::new(-300.0, 7.0)), vec![2]);
+        index.update(2, &placed(-300.0, 7.0, 0.1), &FRAME_BOUNDS);
+        assert_eq!(index.query_point(Vector2::new(-300.0, 7.0)), vec![2]);
+        assert!(index.oversized.is_empty());
+    }
+
+    #[test]
+    fn candidates_include_every_hit() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let objects = random_scene(&mut rng, 1000);
+        let index = SceneIndex::build(2.0, &objects, &FRAME_BOUNDS);
+        for _ in 0..1000 {
+            let point = Vector2::new(rng.gen_range(-100.0..100.0), rng.gen_range(-100.0..100.0));
+            let candidates = index.query_point(point);
+            assert!(brute_force(&objects, point).iter().all(|i| candidates.contains(i)));
+        }
+    }
+
+    /// Point queries against 10k objects, with and without the index. Run with `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore]
+    fn bench_point_queries() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let objects = random_scene(&mut rng, 10_000);
+        let points: Vec<Vector2<f32>> = (0..10_000)
+            .map(|_| Vector2::new(rng.gen_range(-100.0..100.0), rng.gen_range(-100.0..100.0)))
+            .collect();
+
+        let start = std::time::Instant::now();
+        let brute_hits: usize = points.iter().map(|&p| brute_force(&objects, p).len()).sum();
+        let brute_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let index = SceneIndex::build(2.0, &objects, &FRAME_BOUNDS);
+        let build_time = start.elapsed();
+        let start = std::time::Instant::now();
+        let index_hits: usize = points
+            .iter()
+            .map(|&p| {
+                index.query_point(p)
+                    .into_iter()
+                    .filter(|&i| {
+                        let [x0, y0, x1, y1] = world_bounds(&objects[i].world_local_tf, &FRAME_BOUNDS[0]);
+                        (x0..=x1).contains(&p.x) && (y0..=y1).contains(&p.y)
+                    })
+                    .count()
+            })
+            .sum();
+        let index_time = start.elapsed();
+
+        assert_eq!(brute_hits, index_hits);
+        println!(
+            "{} queries against {} objects: brute force {:?}, index {:?} after building it in {:?}",
+            points.len(),
+            objects.len(),
+            brute_time,
+            index_time,
+            build_time,
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use cgmath::Matrix4;
+use crate::error::{FightishError, Result};
+use crate::scene::{Object, Transform2D};
+use crate::CameraState;
+
+/// The camera and objects of an [`crate::App`], as saved by [`crate::App::save_session`].
+/// Animations aren't saved, so objects come back on their static frames.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub(crate) struct Session {
+    version: u32,
+    pub camera: CameraState,
+    pub objects: Vec<SessionObject>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub(crate) struct SessionObject {
+    /// The placement, when the transform is a plain 2D one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transform: Option<Transform2D>,
+    /// The transform's columns otherwise, e.g. for skewed or perspective objects.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    matrix: Option<[[f32; 4]; 4]>,
+    frame_index: i32,
+    // later additions to objects default, so older sessions still load
+    #[serde(default)]
+    model_id: usize,
+    #[serde(default = "white")]
+    tint: [f32; 4],
+    #[serde(default)]
+    layer: f32,
+    #[serde(default = "visible")]
+    visible: bool,
+    #[serde(default)]
+    flip_period: Option<f32>,
+    #[serde(default)]
+    sprite: Option<[f32; 4]>,
+}
+
+fn white() -> [f32; 4] {
+    [1.0; 4]
+}
+
+fn visible() -> bool {
+    true
+}
+
+impl Session {
+    // bumped when a change can't be read by older versions, which refuse newer sessions.
+    const VERSION: u32 = 1;
+
+    pub fn new<'a>(camera: CameraState, objects: impl Iterator<Item = &'a Object>) -> Self {
+        Self { version: Self::VERSION, camera, objects: objects.map(SessionObject::from).collect() }
+    }
+
+    /// Fails for values JSON can't hold, e.g. a NaN camera scale, which would be written as null
+    /// and couldn't be loaded again.
+    pub fn to_json(&self) -> Result<String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| FightishError::Session(format!("Cannot save session: {}", e)))?;
+        // serde_json writes non-finite floats as null instead of failing, which only shows when reading them
+        Self::from_json(&json)
+            .map_err(|_| FightishError::Session("Cannot save session: it has numbers that aren't finite.".into()))?;
+        Ok(json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        // the version is checked first, since a newer session may not parse as this one
+        #[derive(serde::Deserialize)]
+        struct Version {
+            version: u32,
+        }
+        let Version { version } = serde_json::from_str(json).map_err(|e| FightishError::Session(format!("Invalid session: {}", e)))?;
+        if version > Self::VERSION {
+            return Err(FightishError::Session(format!(
+                "Session version {} is newer than the supported version {}.",
+                version,
+                Self::VERSION,
+            )));
+        }
+        serde_json::from_str(json).map_err(|e| FightishError::Session(format!("Invalid session: {}", e)))
+    }
+}
+
+impl From<&Object> for SessionObject {
+    fn from(o: &Object) -> Self {
+        let transform = Transform2D::from_matrix(&o.world_local_tf);
+        Self {
+            transform,
+            matrix: transform.is_none().then(|| o.world_local_tf.into()),
+            frame_index: o.frame_index,
+            model_id: o.model_id,
+            tint: o.tint,
+            layer: o.layer,
+            visible: o.visible,
+            flip_period: o.flip_period,
+            sprite: o.sprite,
+        }
+    }
+}
+
+impl SessionObject {
+    pub fn to_object(&self) -> Result<Object> {
+        let world_local_tf = match (self.transform, self.matrix) {
+            (Some(transform), _) => transform.to_matrix(),
+            (None, Some(matrix)) => Matrix4::from(matrix),
+            (None, None) => return Err(FightishError::Session("Invalid session: object without a transform.".into())),
+        };
+        Ok(Object {
+            model_id: self.model_id,
+            tint: self.tint,
+            layer: self.layer,
+            visible: self.visible,
+            flip_period: self.flip_period,
+            sprite: self.sprite,
+            ..Object::new(world_local_tf, self.frame_index)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Array, Vector3};
+
+    #[test]
+    fn sessions_round_trip() {
+        let camera = CameraState { scale: 2.5, pos: [1.0, -3.0], rot: 0.25 };
+        let placed = Object {
+            model_id: 1,
+            tint: [0.5, 0.25, 1.0, 0.75],
+            layer: 2.0,
+            flip_period: Some(3.0),
+            ..Object::new(Matrix4::from_translation(Vector3::new(4.0, 5.0, 0.0)), 2)
+        };
+        let skewed = Object {
+            visible: false,
+            sprite: Some([0.0, 0.5, 0.25, 1.0]),
+            ..Object::new(Matrix4::from_nonuniform_scale(1.0, 2.0, 1.0) * Matrix4::from_angle_z(cgmath::Rad(0.5)), -1)
+        };
+        let objects = [placed, skewed];
+
+        let session = Session::from_json(&Session::new(camera, objects.iter()).to_json().unwrap()).unwrap();
+        assert_eq!(session.camera, camera);
+        assert_eq!(session.objects.len(), objects.len());
+        for (saved, object) in session.objects.iter().zip(&objects) {
+            let loaded = saved.to_object().unwrap();
+            let error: f32 = (0..4)
+                .map(|c| (loaded.world_local_tf[c] - object.world_local_tf[c]).map(f32::abs).sum())
+                .sum();
+            assert!(error < 1e-5, "{:?} != {:?}", loaded.world_local_tf, object.world_local_tf);
+            assert_eq!(loaded.model_id, object.model_id);
+            assert_eq!(loaded.frame_index, object.frame_index);
+            assert_eq!(loaded.tint, object.tint);
+            assert_eq!(loaded.layer, object.layer);
+            assert_eq!(loaded.visible, object.visible);
+            assert_eq!(loaded.flip_period, object.flip_period);
+            assert_eq!(loaded.sprite, object.sprite);
+        }
+    }
+
+    #[test]
+    fn non_finite_sessions_are_not_saved() {
+        let camera = CameraState { scale: f32::NAN, ..CameraState::default() };
+        assert!(Session::new(camera, std::iter::empty()).to_json().is_err());
+        let object = Object { layer: f32::INFINITY, ..Object::new(Matrix4::from_scale(1.0), 0) };
+        assert!(Session::new(CameraState::default(), [object].iter()).to_json().is_err());
+    }
+}
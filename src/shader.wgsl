@@ -4,6 +4,24 @@ struct Uniforms {
     clip_world_tf: mat4x4<f32>,
     @location(1)
     frag_clip_tf: mat4x4<f32>,
+    @location(2)
+    winding_epsilon: f32,
+    @location(3)
+    time: f32,
+    @location(4)
+    viewport_size: vec2<f32>,
+    @location(5)
+    aa_width: f32,
+    @location(6)
+    premultiplied: u32,
+    @location(7)
+    global_tint: vec4<f32>,
+    @location(8)
+    world_clip_tf: mat4x4<f32>,
+    @location(9)
+    grid_color: vec4<f32>,
+    @location(10)
+    grid_spacing: f32,
 }
 @group(0) @binding(0)
 var<uniform> uniforms: Uniforms;
@@ -20,7 +38,7 @@ struct FrameSegment {
     s: vec2<f32>,
     e: vec2<f32>,
     m: vec2<f32>,
-    flags: u32,
+    flags: u32, // 1 for straight lines, 2 for edges shared with another shard
 }
 
 @group(1) @binding(0)
@@ -40,7 +58,55 @@ fn vs_main(
 ) -> VertexOutput {
     var out: VertexOutput;
     let vert = shard_verts[index];
-    out.clip_position = vec4(vert.pos.xy / vert.pos.w, f32(vert.clip_depth) / 16777216.0 , 1.0);
+    // dividing here handles perspective object transforms, and is exact since every output is flat.
+    out.clip_position = vec4(vert.pos.xy / vert.pos.w, clip_depth_z(vert.clip_depth), 1.0);
+    out.color = vert.color;
+    out.segment_range = vert.segment_range;
+    return out;
+}
+
+struct GridOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world: vec2<f32>,
+};
+
+// a triangle covering the whole viewport, behind everything else.
+@vertex
+fn vs_grid(
+    @builtin(vertex_index) index: u32,
+) -> GridOutput {
+    var corners = array<vec2<f32>, 3>(vec2(-1.0, -1.0), vec2(3.0, -1.0), vec2(-1.0, 3.0));
+    var out: GridOutput;
+    out.clip_position = vec4(corners[index], 0.0, 1.0);
+    let world = uniforms.world_clip_tf * out.clip_position;
+    out.world = world.xy / world.w;
+    return out;
+}
+
+// lines one pixel wide at multiples of the grid spacing, fading out when the cells get too small to tell apart.
+@fragment
+fn fs_grid(in: GridOutput) -> @location(0) vec4<f32> {
+    let cell = in.world / uniforms.grid_spacing;
+    // grid cells per pixel along each axis
+    let width = fwidth(cell);
+    let distance = abs(fract(cell - 0.5) - 0.5) / width;
+    let line = 1.0 - min(min(distance.x, distance.y), 1.0);
+    let fade = clamp((0.25 - max(width.x, width.y)) / 0.125, 0.0, 1.0);
+    let alpha = uniforms.grid_color.a * line * fade;
+    if alpha <= 0.0 { discard; }
+    return vec4(uniforms.grid_color.rgb, alpha);
+}
+
+// the outline of each shard's bounding box, as 4 lines between the corners of its quad.
+@vertex
+fn vs_bounds(
+    @builtin(vertex_index) index: u32,
+) -> VertexOutput {
+    // the quad's first 4 vertices are the corners (x, y), (z, y), (x, w), (z, w)
+    var corners = array<u32, 8>(0u, 1u, 1u, 3u, 3u, 2u, 2u, 0u);
+    var out: VertexOutput;
+    let vert = shard_verts[6u * (index / 8u) + corners[index % 8u]];
+    out.clip_position = vec4(vert.pos.xy / vert.pos.w, 1.0, 1.0);
     out.color = vert.color;
     out.segment_range = vert.segment_range;
     return out;
@@ -48,66 +114,54 @@ fn vs_main(
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let inside = winding(in) != 0;
+    if uniforms.aa_width <= 0.0 {
+        if !inside { discard; }
+        return in.color;
+    }
+    // the segments are in fragment coordinates already, so the distance is in pixels.
+    let coverage = edge_coverage(edge_distance(in), inside);
+    if coverage <= 0.0 { discard; }
+    return covered(in.color, coverage);
+}
+
+// the whole shard quads, drawn as lines over the scene for debugging.
+@fragment
+fn fs_wireframe(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(in.color.rgb, 1.0);
+}
+
+// distance from the fragment to the nearest of the shard's segments, leaving out the edges shared with
+// other shards, which stay hard so the shards on either side don't both fade there.
+fn edge_distance(in: VertexOutput) -> f32 {
+    let v0 = in.clip_position.xy / in.clip_position.w;
+    var distance = 3.0e38;
+
+    for (var segment_index: i32 = in.segment_range.x; segment_index < in.segment_range.y; segment_index++) {
+        let segment = segments[segment_index];
+        if ((segment.flags & 2u) != 0u) {
+            continue;
+        }
+        if ((segment.flags & 1u) == 0u) {
+            distance = min(distance, distance_quad(v0, segment.s, segment.m, segment.e));
+        } else {
+            distance = min(distance, distance_line(v0, segment.s, segment.e));
+        }
+    }
+    return distance;
+}
+
+fn winding(in: VertexOutput) -> i32 {
     let v0 = in.clip_position.xy / in.clip_position.w;
     var winding: i32 = 0;
 
     for (var segment_index: i32 = in.segment_range.x; segment_index < in.segment_range.y; segment_index++) {
         let segment = segments[segment_index];
-        if (segment.flags == 0) {
+        if ((segment.flags & 1u) == 0u) {
             winding += winding_quad(v0, segment.s, segment.m, segment.e);
         } else {
             winding += winding_line(v0, segment.s, segment.e);
         }
     }
-    if winding == 0 { discard; }
-    return in.color;
-}
-
-fn winding_line(v0: vec2<f32>, v1: vec2<f32>, v2: vec2<f32>) -> i32 {
-    let code: u32 = (u32(v0.y < v1.y) << 3)
-                + (u32(v0.y < v2.y) << 2)
-                + (u32((v2.x - v0.x) * ((v0.y - v1.y) / (v2.y - v1.y)) + (v1.x - v0.x) * ((v0.y - v2.y) / (v1.y - v2.y)) > 0) << 1);
-    return i32((0x5195u >> code) & 3) - 1;
+    return winding;
 }
-
-fn winding_quad(v0: vec2<f32>, v1: vec2<f32>, v2: vec2<f32>, v3: vec2<f32>) -> i32 {
-    // v0 the point to be tested, v1, then v2, then v3 in the end, control, end order.
-    // quadratics specifications. Not mandating symmetry of the two directions,
-    // so internal boundaries must be lines.
-    // this is using Lengyel's algorithm, modified a bit.
-    let code: u32 = (
-        0x2E74u >>
-        (
-            select(0x0u, 0x2u, v1.y > v0.y) +
-            select(0x0u, 0x4u, v2.y > v0.y) +
-            select(0x0u, 0x8u, v3.y > v0.y)
-        )
-    ) & 0x3u;
-
-    // they used a t^2 - 2b t + c polynomial format.
-    // they make a branch to skip this if the code vanished, need to analyze whether you get
-    // workgroup divergence problems.
-    let ax = (v1.x + v3.x) - 2 * v2.x;
-    let ay = (v1.y + v3.y) - 2 * v2.y;
-    let bx = v1.x - v2.x;
-    let by = v1.y - v2.y;
-    let cy = v1.y - v0.y;
-    let ra = 1.0f / ay;
-
-    let d = sqrt(max(by * by - ay * cy, 0.0));
-    // when code is 0x1u, this is the case where root 1 (the minus one) is forced in the range
-    // but the other root could be big.
-    // specifically, v1.y > v3.y, when ay is small, by > 0
-    // so (by - d) * ra is O(1) due to a cancellation, which is bad, so use a different formula.
-    // this is more accurate than their formula with an explicit epsilon, but maybe slower?
-    let t1 = select((by - d) * ra, cy / (by + d), code == 0x1u);
-    // same logic, but flipped.
-    let t2 = select((by + d) * ra, cy / (by - d), code == 0x2u);
-    // so now the original values of t1 and t2 only actually get used when the code is 0x3u.
-    // note need t1 == t2 in the case when there are no roots, but this is only in case 0x3u, so o.k.
-
-    let b1 = (ax * t1 - 2 * bx) * t1 + v1.x > v0.x;
-    let b2 = (ax * t2 - 2 * bx) * t2 + v1.x > v0.x;
-
-    return i32((code > 1) && b2) - i32 (((code & 1) != 0) && b1);
-}
\ No newline at end of file
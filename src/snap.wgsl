@@ -0,0 +1,9 @@
+// fragment coordinates of transformed segment vertices, shared by the compute pass and the instanced shader.
+// snapping makes edges shared between shards bit-identical after the transform,
+// so the half-open tests in the fragment shader cover seam pixels exactly once.
+
+fn get_xy(v: vec4<f32>) -> vec2<f32> {
+    let p = v.xy / v.w;
+    let eps = uniforms.winding_epsilon;
+    return select(p, round(p / eps) * eps, eps > 0.0);
+}
@@ -0,0 +1,83 @@
+struct Uniforms {
+    // mat3x3's are EVIL
+    @location(0)
+    clip_world_tf: mat4x4<f32>,
+    @location(1)
+    frag_clip_tf: mat4x4<f32>,
+    @location(2)
+    winding_epsilon: f32,
+    @location(3)
+    time: f32,
+    @location(4)
+    viewport_size: vec2<f32>,
+    @location(5)
+    aa_width: f32,
+    @location(6)
+    premultiplied: u32,
+    @location(7)
+    global_tint: vec4<f32>,
+    @location(8)
+    world_clip_tf: mat4x4<f32>,
+    @location(9)
+    grid_color: vec4<f32>,
+    @location(10)
+    grid_spacing: f32,
+}
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+struct Sprite {
+    world_tex_tf: mat4x4<f32>,
+    bounds: vec4<f32>,
+    uv: vec4<f32>,
+    tint: vec4<f32>,
+    clip_depth: u32,
+}
+
+@group(1) @binding(0)
+var atlas: texture_2d<f32>;
+@group(1) @binding(1)
+var atlas_sampler: sampler;
+
+@group(2) @binding(0)
+var<storage, read> sprites: array<Sprite>;
+
+struct SpriteOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) tint: vec4<f32>,
+};
+
+// a quad over the sprite's bounds, with the atlas rect stretched over it.
+@vertex
+fn vs_sprite(
+    @builtin(vertex_index) index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> SpriteOutput {
+    let sprite = sprites[instance_index];
+    let bb = sprite.bounds;
+    let uv = sprite.uv;
+    // the same corners as the shard quads, with v growing downwards where y grows upwards
+    var corners = array<vec2<f32>, 6>(bb.xy, bb.zy, bb.xw, bb.zw, bb.xw, bb.zy);
+    var uvs = array<vec2<f32>, 6>(uv.xw, uv.zw, uv.xy, uv.zy, uv.xy, uv.zw);
+    let pos = uniforms.clip_world_tf * sprite.world_tex_tf * vec4(corners[index], 0.0, 1.0);
+
+    var out: SpriteOutput;
+    // unlike the shards the uv is interpolated, so w is kept for perspective correct interpolation.
+    out.clip_position = vec4(pos.xy, clip_depth_z(sprite.clip_depth) * pos.w, pos.w);
+    out.uv = uvs[index];
+    // tints have straight alpha, like the atlas
+    let tint = sprite.tint * uniforms.global_tint;
+    out.tint = select(tint, vec4(tint.rgb * tint.a, tint.a), uniforms.premultiplied != 0u);
+    return out;
+}
+
+@fragment
+fn fs_sprite(in: SpriteOutput) -> @location(0) vec4<f32> {
+    let texel = textureSample(atlas, atlas_sampler, in.uv);
+    // transparent texels neither cover nor hide what's drawn later
+    if texel.a <= 0.0 { discard; }
+    // the atlas has straight alpha
+    let color = select(texel, vec4(texel.rgb * texel.a, texel.a), uniforms.premultiplied != 0u);
+    return color * in.tint;
+}
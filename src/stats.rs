@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+/// CPU side frame timing, updated once per rendered frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    /// Frames timed so far.
+    pub frames: u64,
+    /// The time between the last two frames.
+    pub frame_time: Duration,
+    /// An exponential moving average of the frame time.
+    pub smoothed_frame_time: Duration,
+    /// Weight of the newest frame in the average, between 0 and 1.
+    pub smoothing: f64,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self {
+            frames: 0,
+            frame_time: Duration::ZERO,
+            smoothed_frame_time: Duration::ZERO,
+            smoothing: 0.1,
+        }
+    }
+}
+
+impl FrameStats {
+    /// Adds the time since the previous frame.
+    pub fn record(&mut self, frame_time: Duration) {
+        self.smoothed_frame_time = if self.frames == 0 {
+            // nothing to average with yet
+            frame_time
+        } else {
+            let smoothed = self.smoothed_frame_time.as_secs_f64();
+            Duration::from_secs_f64(smoothed + (frame_time.as_secs_f64() - smoothed) * self.smoothing)
+        };
+        self.frame_time = frame_time;
+        self.frames += 1;
+    }
+
+    /// Clears the timings, keeping the smoothing factor.
+    pub fn reset(&mut self) {
+        *self = Self { smoothing: self.smoothing, ..Self::default() };
+    }
+
+    /// Frames per second from the smoothed frame time, 0 before any frame was timed.
+    pub fn fps(&self) -> f64 {
+        if self.smoothed_frame_time.is_zero() { 0.0 } else { 1.0 / self.smoothed_frame_time.as_secs_f64() }
+    }
+}
@@ -0,0 +1,82 @@
+// winding number contributions of segments around a point, shared by the shard shaders.
+// the point is counted as inside when the sum over a shard's segments is nonzero.
+
+fn winding_line(v0: vec2<f32>, v1: vec2<f32>, v2: vec2<f32>) -> i32 {
+    let code: u32 = (u32(v0.y < v1.y) << 3)
+                + (u32(v0.y < v2.y) << 2)
+                + (u32((v2.x - v0.x) * ((v0.y - v1.y) / (v2.y - v1.y)) + (v1.x - v0.x) * ((v0.y - v2.y) / (v1.y - v2.y)) > 0) << 1);
+    return i32((0x5195u >> code) & 3) - 1;
+}
+
+fn winding_quad(v0: vec2<f32>, v1: vec2<f32>, v2: vec2<f32>, v3: vec2<f32>) -> i32 {
+    // v0 the point to be tested, v1, then v2, then v3 in the end, control, end order.
+    // quadratics specifications. Not mandating symmetry of the two directions,
+    // so internal boundaries must be lines.
+    // this is using Lengyel's algorithm, modified a bit.
+    let code: u32 = (
+        0x2E74u >>
+        (
+            select(0x0u, 0x2u, v1.y > v0.y) +
+            select(0x0u, 0x4u, v2.y > v0.y) +
+            select(0x0u, 0x8u, v3.y > v0.y)
+        )
+    ) & 0x3u;
+
+    // they used a t^2 - 2b t + c polynomial format.
+    // they make a branch to skip this if the code vanished, need to analyze whether you get
+    // workgroup divergence problems.
+    let ax = (v1.x + v3.x) - 2 * v2.x;
+    let ay = (v1.y + v3.y) - 2 * v2.y;
+    let bx = v1.x - v2.x;
+    let by = v1.y - v2.y;
+    let cy = v1.y - v0.y;
+    let ra = 1.0f / ay;
+
+    let d = sqrt(max(by * by - ay * cy, 0.0));
+    // when code is 0x1u, this is the case where root 1 (the minus one) is forced in the range
+    // but the other root could be big.
+    // specifically, v1.y > v3.y, when ay is small, by > 0
+    // so (by - d) * ra is O(1) due to a cancellation, which is bad, so use a different formula.
+    // this is more accurate than their formula with an explicit epsilon, but maybe slower?
+    let t1 = select((by - d) * ra, cy / (by + d), code == 0x1u);
+    // same logic, but flipped.
+    let t2 = select((by + d) * ra, cy / (by - d), code == 0x2u);
+    // so now the original values of t1 and t2 only actually get used when the code is 0x3u.
+    // note need t1 == t2 in the case when there are no roots, but this is only in case 0x3u, so o.k.
+
+    let b1 = (ax * t1 - 2 * bx) * t1 + v1.x > v0.x;
+    let b2 = (ax * t2 - 2 * bx) * t2 + v1.x > v0.x;
+
+    return i32((code > 1) && b2) - i32 (((code & 1) != 0) && b1);
+}
+// distance from v0 to the line segment from v1 to v2.
+fn distance_line(v0: vec2<f32>, v1: vec2<f32>, v2: vec2<f32>) -> f32 {
+    let d = v2 - v1;
+    let t = clamp(dot(v0 - v1, d) / max(dot(d, d), 1.0e-12), 0.0, 1.0);
+    return length(v0 - (v1 + t * d));
+}
+
+// distance from v0 to the quadratic from v1 to v3 with control point v2, approximated by 4 chords.
+// close enough for antialiasing, where only distances within a pixel or two matter.
+fn distance_quad(v0: vec2<f32>, v1: vec2<f32>, v2: vec2<f32>, v3: vec2<f32>) -> f32 {
+    var distance = 3.0e38;
+    var previous = v1;
+    for (var i = 1; i <= 4; i++) {
+        let t = f32(i) / 4.0;
+        let p = mix(mix(v1, v2, t), mix(v2, v3, t), t);
+        distance = min(distance, distance_line(v0, previous, p));
+        previous = p;
+    }
+    return distance;
+}
+
+// fraction of a pixel covered, for a fragment `distance` pixels from the nearest edge,
+// ramping over the antialiasing width centered on the edge.
+fn edge_coverage(distance: f32, inside: bool) -> f32 {
+    return clamp(0.5 + select(-distance, distance, inside) / uniforms.aa_width, 0.0, 1.0);
+}
+
+// a color with only `coverage` of the pixel covered, premultiplied colors scaling entirely.
+fn covered(color: vec4<f32>, coverage: f32) -> vec4<f32> {
+    return select(vec4(color.rgb, color.a * coverage), color * coverage, uniforms.premultiplied != 0u);
+}